@@ -1 +1 @@
-SELECT * FROM agents LIMIT 1;
+SELECT * FROM agents WHERE symbol = ?1;
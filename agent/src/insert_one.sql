@@ -6,6 +6,7 @@
              starting_faction,
              ship_count,
              token,
+             email,
              created_at,
              updated_at)
 VALUES      (?1,
@@ -15,6 +16,15 @@ VALUES      (?1,
              ?5,
              ?6,
              ?7,
+             ?8,
              CURRENT_TIMESTAMP,
-             CURRENT_TIMESTAMP);
-            
+             CURRENT_TIMESTAMP)
+ON CONFLICT (symbol) DO UPDATE SET
+             account_id = excluded.account_id,
+             headquarters = excluded.headquarters,
+             credits = excluded.credits,
+             starting_faction = excluded.starting_faction,
+             ship_count = excluded.ship_count,
+             token = excluded.token,
+             email = excluded.email,
+             updated_at = excluded.updated_at;
@@ -21,6 +21,17 @@ pub enum AgentError {
     RegistrationError(#[from] apis::Error<RegisterError>),
 }
 
+/// What an agent is registered with. Read from config or asked for by the
+/// CLI.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Registration {
+    pub symbol: String,
+    pub faction: FactionSymbol,
+    /// Needed to claim a symbol reserved between resets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Agent {
     pub account_id: Option<String>,
@@ -30,59 +41,131 @@ pub struct Agent {
     pub starting_faction: String,
     pub ship_count: i64,
     pub token: String,
+    pub email: Option<String>,
     pub created_at: Option<NaiveDateTime>,
     pub updated_at: Option<NaiveDateTime>,
 }
 
 impl Agent {
-    pub async fn fetch_or_create(pool: &SqlitePool) -> Result<Agent, AgentError> {
-        let agent = fetch_agent(pool).await;
-
-        match agent {
-            Ok(agent) => Ok(agent),
-            Err(RowNotFound) => create_agent(&pool).await,
-            Err(err) => Err(AgentError::DBError(err)),
+    /// Looks up the agent with the symbol, registering it first when it isn't
+    /// stored yet.
+    pub async fn fetch_or_register(
+        pool: &SqlitePool,
+        conf: &Configuration,
+        registration: &Registration,
+    ) -> Result<Agent, AgentError> {
+        match Agent::fetch(pool, registration.symbol.as_str()).await? {
+            Some(agent) => Ok(agent),
+            None => Agent::register(pool, conf, registration).await,
         }
     }
-}
 
-async fn fetch_agent(pool: &SqlitePool) -> Result<Agent, sqlx::Error> {
-    sqlx::query_file_as!(Agent, "src/fetch_one.sql")
-        .fetch_one(pool)
-        .await
-}
+    pub async fn fetch(pool: &SqlitePool, symbol: &str) -> Result<Option<Agent>, AgentError> {
+        let agent = sqlx::query_file_as!(Agent, "src/fetch_one.sql", symbol)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(agent)
+    }
+
+    /// Every stored agent, ordered by symbol.
+    pub async fn list(pool: &SqlitePool) -> Result<Vec<Agent>, AgentError> {
+        let agents = sqlx::query_as!(Agent, "SELECT * FROM agents ORDER BY symbol")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(agents)
+    }
+
+    /// Registers a new agent with the API and stores it along with its token.
+    /// An agent stored with the same symbol is replaced.
+    pub async fn register(
+        pool: &SqlitePool,
+        conf: &Configuration,
+        registration: &Registration,
+    ) -> Result<Agent, AgentError> {
+        let res = register_agent(conf, registration)
+            .await
+            .map_err(|err| AgentError::RegistrationError(err))?;
+        let agent = res.agent;
 
-async fn create_agent(pool: &SqlitePool) -> Result<Agent, AgentError> {
-    let res = register_agent("Natingar3".into(), FactionSymbol::Cosmic)
-        .await
-        .map_err(|err| AgentError::RegistrationError(err))?;
-    let agent = res.agent;
-
-    sqlx::query_file!(
-        "src/insert_one.sql",
-        agent.account_id,
-        agent.symbol,
-        agent.headquarters,
-        agent.credits,
-        agent.starting_faction,
-        agent.ship_count,
-        res.token,
-    )
-    .execute(pool)
-    .await?;
-
-    fetch_agent(pool)
-        .await
-        .map_err(|err| AgentError::DBError(err))
+        sqlx::query_file!(
+            "src/insert_one.sql",
+            agent.account_id,
+            agent.symbol,
+            agent.headquarters,
+            agent.credits,
+            agent.starting_faction,
+            agent.ship_count,
+            res.token,
+            registration.email,
+        )
+        .execute(pool)
+        .await?;
+
+        Agent::fetch(pool, agent.symbol.as_str())
+            .await?
+            .ok_or(AgentError::DBError(RowNotFound))
+    }
+
+    /// Stores the credits the API reports now, to chart them over time.
+    pub async fn record_credits(&self, pool: &SqlitePool, credits: i64) -> Result<(), AgentError> {
+        sqlx::query!(
+            "INSERT INTO credit_history (agent_symbol, credits, recorded_at)
+             VALUES (?1, ?2, CURRENT_TIMESTAMP)",
+            self.symbol,
+            credits
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Recorded credits, oldest first.
+    pub async fn credit_history(
+        &self,
+        pool: &SqlitePool,
+    ) -> Result<Vec<(NaiveDateTime, i64)>, AgentError> {
+        let rows = sqlx::query!(
+            "SELECT credits, recorded_at FROM credit_history
+             WHERE agent_symbol = ?1 ORDER BY recorded_at",
+            self.symbol
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|r| (r.recorded_at, r.credits))
+            .collect())
+    }
+
+    pub fn faction(&self) -> Option<FactionSymbol> {
+        serde_json::from_value(serde_json::Value::String(self.starting_faction.clone())).ok()
+    }
+
+    /// What the agent was registered with, to register it again after a
+    /// reset.
+    pub fn registration(&self) -> Option<Registration> {
+        Some(Registration {
+            symbol: self.symbol.clone(),
+            faction: self.faction()?,
+            email: self.email.clone(),
+        })
+    }
 }
 
 async fn register_agent(
-    symbol: String,
-    faction: FactionSymbol,
+    conf: &Configuration,
+    registration: &Registration,
 ) -> Result<Box<Register201ResponseData>, apis::Error<RegisterError>> {
-    let conf = Configuration::new();
-    let req = RegisterRequest::new(faction.into(), symbol);
-    let res = default_api::register(&conf, Some(req)).await?;
+    let req = RegisterRequest {
+        faction: registration.faction,
+        symbol: registration.symbol.clone(),
+        email: registration.email.clone(),
+    };
+    let res = default_api::register(conf, Some(req)).await?;
 
     Ok(res.data)
 }
@@ -98,6 +181,7 @@ impl From<Box<Register201ResponseData>> for Agent {
             starting_faction: agent.starting_faction,
             ship_count: agent.ship_count as i64,
             token: value.token,
+            email: None,
             created_at: None,
             updated_at: None,
         }
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use agent::Agent;
+use commander::checkpoint::{self, Checkpoint};
+use common::models::{Contract, MarketTransaction, Ship};
+use eyre::Result;
+use sea_orm::DatabaseConnection;
+use sqlx::SqlitePool;
+use time::OffsetDateTime;
+
+const TRANSACTIONS: u64 = 50;
+const ERRORS: usize = 50;
+
+/// What the API reported on one poll.
+pub struct Poll {
+    pub credits: i64,
+    pub ships: Vec<Ship>,
+    pub contracts: Vec<Contract>,
+}
+
+/// A line of the ship list. Ships only known from a checkpoint have no game
+/// state until the API is polled.
+pub struct ShipRow<'a> {
+    pub symbol: &'a str,
+    pub ship: Option<&'a Ship>,
+    pub checkpoint: Option<&'a Checkpoint>,
+}
+
+#[derive(Default)]
+pub struct App {
+    pub agent: Option<Agent>,
+    pub credits: Option<i64>,
+    pub credit_history: Vec<u64>,
+    pub ships: Vec<Ship>,
+    pub checkpoints: HashMap<String, Checkpoint>,
+    pub contracts: Vec<Contract>,
+    pub transactions: Vec<MarketTransaction>,
+    /// Errors while loading or polling, newest first, with the time they
+    /// happened.
+    pub errors: Vec<(OffsetDateTime, String)>,
+    pub selected: usize,
+    pub detail: bool,
+    pub polling: bool,
+}
+
+impl App {
+    /// Reads everything the commander stores.
+    pub async fn load(
+        &mut self,
+        pool: &SqlitePool,
+        db: &DatabaseConnection,
+        symbol: Option<&str>,
+    ) -> Result<()> {
+        self.agent = match symbol {
+            Some(symbol) => Agent::fetch(pool, symbol).await?,
+            None => {
+                let mut agents = Agent::list(pool).await?;
+                match agents.len() {
+                    1 => Some(agents.remove(0)),
+                    _ => None,
+                }
+            }
+        };
+
+        if let Some(agent) = &self.agent {
+            let history = agent.credit_history(pool).await?;
+            self.credit_history = history
+                .iter()
+                .map(|(_, credits)| (*credits).max(0) as u64)
+                .collect();
+            self.credits = history
+                .last()
+                .map(|(_, credits)| *credits)
+                .or(Some(agent.credits));
+        }
+
+        self.checkpoints = checkpoint::load(pool).await?;
+        self.transactions = common::repository::get_recent_transactions(db, TRANSACTIONS).await?;
+        self.select(self.selected);
+
+        Ok(())
+    }
+
+    pub fn update(&mut self, poll: Poll) {
+        self.credits = Some(poll.credits);
+        self.ships = poll.ships;
+        self.contracts = poll
+            .contracts
+            .into_iter()
+            .filter(|c| !c.fulfilled)
+            .collect();
+        self.select(self.selected);
+    }
+
+    pub fn error(&mut self, error: String) {
+        self.errors.insert(0, (OffsetDateTime::now_utc(), error));
+        self.errors.truncate(ERRORS);
+    }
+
+    /// Every ship the API or the checkpoints know, by symbol.
+    pub fn rows(&self) -> Vec<ShipRow<'_>> {
+        let mut symbols = self
+            .ships
+            .iter()
+            .map(|s| s.symbol.as_str())
+            .chain(self.checkpoints.keys().map(|s| s.as_str()))
+            .collect::<Vec<&str>>();
+        symbols.sort();
+        symbols.dedup();
+
+        symbols
+            .into_iter()
+            .map(|symbol| ShipRow {
+                symbol,
+                ship: self.ships.iter().find(|s| s.symbol == symbol),
+                checkpoint: self.checkpoints.get(symbol),
+            })
+            .collect()
+    }
+
+    pub fn next(&mut self) {
+        self.select(self.selected + 1);
+    }
+
+    pub fn previous(&mut self) {
+        self.select(self.selected.saturating_sub(1));
+    }
+
+    fn select(&mut self, index: usize) {
+        let count = self.rows().len();
+        self.selected = index.min(count.saturating_sub(1));
+    }
+}
@@ -0,0 +1,158 @@
+use std::io::{self, Stdout};
+use std::time::{Duration, Instant};
+
+use common::machines::actions;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::ExecutableCommand;
+use eyre::Result;
+use openapi::apis::agents_api;
+use openapi::apis::configuration::Configuration;
+use ratatui::backend::CrosstermBackend;
+use ratatui::Terminal;
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+
+use crate::profile::Profile;
+
+mod app;
+mod ui;
+
+use app::{App, Poll};
+
+/// How often the database is read again.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+/// How long to wait for a key before drawing again.
+const TICK: Duration = Duration::from_millis(250);
+
+/// Shows what the commander is doing from its database: credits over time,
+/// every ship's behaviour and state, recent transactions and errors. With a
+/// poll interval the API is also asked for ships, contracts and credits.
+pub async fn run(profile: &Profile, poll: Option<u64>) -> Result<()> {
+    let endpoints = profile.endpoints();
+    let pool = SqlitePool::connect(endpoints.database_url.as_str()).await?;
+    let db = profile.connect().await?;
+
+    let mut app = App::default();
+    app.load(&pool, &db, profile.agent.as_deref()).await?;
+
+    let (tx, mut rx) = mpsc::channel(4);
+    let poller = match (poll, profile.api_config()) {
+        (Some(seconds), Some(conf)) => {
+            app.polling = true;
+            Some(tokio::spawn(poll_api(
+                conf,
+                Duration::from_secs(seconds.max(1)),
+                tx,
+            )))
+        }
+        _ => None,
+    };
+
+    let mut terminal = TerminalGuard::enter()?;
+    let mut loaded = Instant::now();
+
+    let result = loop {
+        while let Ok(poll) = rx.try_recv() {
+            match poll {
+                Ok(poll) => {
+                    if let Some(agent) = &app.agent {
+                        if let Err(e) = agent.record_credits(&pool, poll.credits).await {
+                            app.error(format!("Error recording credits: {}", e));
+                        }
+                    }
+                    app.update(poll);
+                }
+                Err(e) => app.error(format!("{:#}", e)),
+            }
+        }
+
+        if loaded.elapsed() >= RELOAD_INTERVAL {
+            if let Err(e) = app.load(&pool, &db, profile.agent.as_deref()).await {
+                app.error(format!("{:#}", e));
+            }
+            loaded = Instant::now();
+        }
+
+        if let Err(e) = terminal.0.draw(|f| ui::draw(f, &app)) {
+            break Err(e.into());
+        }
+
+        if !event::poll(TICK)? {
+            continue;
+        }
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc if app.detail => app.detail = false,
+            KeyCode::Char('q') | KeyCode::Esc => break Ok(()),
+            KeyCode::Down | KeyCode::Char('j') => app.next(),
+            KeyCode::Up | KeyCode::Char('k') => app.previous(),
+            KeyCode::Enter => app.detail = !app.detail,
+            KeyCode::Char('r') => {
+                loaded = Instant::now() - RELOAD_INTERVAL;
+            }
+            _ => {}
+        }
+    };
+
+    drop(terminal);
+    if let Some(poller) = poller {
+        poller.abort();
+    }
+    pool.close().await;
+
+    result
+}
+
+/// Sends what the API reports every interval until the dashboard closes.
+async fn poll_api(conf: Configuration, interval: Duration, tx: mpsc::Sender<Result<Poll>>) {
+    let mut interval = tokio::time::interval(interval);
+
+    loop {
+        interval.tick().await;
+
+        let poll = async {
+            let agent = agents_api::get_my_agent(&conf).await?;
+            let ships = actions::get_all_ships(&conf).await?;
+            let contracts = actions::get_all_contracts(&conf).await?;
+
+            Ok(Poll {
+                credits: agent.data.credits,
+                ships,
+                contracts,
+            })
+        };
+
+        if tx.send(poll.await).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Puts the terminal back the way it was, also when the dashboard errors.
+struct TerminalGuard(Terminal<CrosstermBackend<Stdout>>);
+
+impl TerminalGuard {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        io::stdout().execute(EnterAlternateScreen)?;
+        let terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+        Ok(Self(terminal))
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        let _ = io::stdout().execute(LeaveAlternateScreen);
+        let _ = self.0.show_cursor();
+    }
+}
@@ -0,0 +1,310 @@
+use ratatui::prelude::*;
+use ratatui::widgets::{
+    Block, Borders, Cell, List, ListItem, Paragraph, Row, Sparkline, Table, TableState, Wrap,
+};
+use time::OffsetDateTime;
+
+use super::app::{App, ShipRow};
+
+pub fn draw(f: &mut Frame, app: &App) {
+    let [header, body, bottom, footer] = Layout::vertical([
+        Constraint::Length(6),
+        Constraint::Min(8),
+        Constraint::Length(10),
+        Constraint::Length(1),
+    ])
+    .areas(f.size());
+
+    draw_credits(f, app, header);
+
+    let [ships, contracts] =
+        Layout::horizontal([Constraint::Percentage(70), Constraint::Percentage(30)]).areas(body);
+    if app.detail {
+        draw_detail(f, app, ships);
+    } else {
+        draw_ships(f, app, ships);
+    }
+    draw_contracts(f, app, contracts);
+
+    let [transactions, errors] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(bottom);
+    draw_transactions(f, app, transactions);
+    draw_errors(f, app, errors);
+
+    let help = match app.detail {
+        true => "q/esc back  r refresh",
+        false => "q quit  ↑/↓ select  enter details  r refresh",
+    };
+    f.render_widget(Paragraph::new(help).style(Style::new().dim()), footer);
+}
+
+fn draw_credits(f: &mut Frame, app: &App, area: Rect) {
+    let agent = app
+        .agent
+        .as_ref()
+        .map(|a| a.symbol.as_str())
+        .unwrap_or("no agent");
+    let credits = app
+        .credits
+        .map(|c| c.to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let polling = if app.polling { "" } else { " (not polling)" };
+
+    let title = format!(" {} · {} credits{} ", agent, credits, polling);
+
+    // Only the latest points fit, one per column.
+    let width = area.width.saturating_sub(2) as usize;
+    let skip = app.credit_history.len().saturating_sub(width);
+
+    let sparkline = Sparkline::default()
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .data(&app.credit_history[skip..])
+        .style(Style::new().green());
+    f.render_widget(sparkline, area);
+}
+
+fn draw_ships(f: &mut Frame, app: &App, area: Rect) {
+    let now = OffsetDateTime::now_utc();
+    let rows = app.rows();
+
+    let header = Row::new(["Ship", "Behaviour", "State", "Nav", "ETA", "Fuel", "Cargo"])
+        .style(Style::new().bold());
+
+    let lines = rows
+        .iter()
+        .map(|row| ship_row(row, now))
+        .collect::<Vec<Row>>();
+
+    let widths = [
+        Constraint::Length(14),
+        Constraint::Length(14),
+        Constraint::Min(12),
+        Constraint::Length(24),
+        Constraint::Length(8),
+        Constraint::Length(9),
+        Constraint::Length(9),
+    ];
+
+    let table = Table::new(lines, widths)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(" Ships "))
+        .highlight_style(Style::new().reversed());
+
+    let mut state = TableState::default().with_selected((!rows.is_empty()).then_some(app.selected));
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+fn ship_row<'a>(row: &ShipRow<'a>, now: OffsetDateTime) -> Row<'a> {
+    let (behaviour, state, style) = match row.checkpoint {
+        Some(c) if c.error.is_some() => (
+            c.behaviour.name.clone(),
+            c.state.clone(),
+            Style::new().red(),
+        ),
+        Some(c) if c.finished => (
+            c.behaviour.name.clone(),
+            "Finished".to_string(),
+            Style::new().dim(),
+        ),
+        Some(c) => (c.behaviour.name.clone(), c.state.clone(), Style::new()),
+        None => ("-".to_string(), "-".to_string(), Style::new().dim()),
+    };
+
+    let (nav, eta, fuel, cargo) = match row.ship {
+        Some(ship) => (
+            format!(
+                "{} @ {}",
+                ship.nav.status,
+                ship.nav.location.waypoint_ident()
+            ),
+            eta(ship.nav.route.arrival, now),
+            format!("{}/{}", ship.fuel.current, ship.fuel.capacity),
+            format!("{}/{}", ship.cargo.current, ship.cargo.capacity),
+        ),
+        None => Default::default(),
+    };
+
+    Row::new([
+        Cell::from(row.symbol.to_string()),
+        Cell::from(behaviour),
+        Cell::from(state),
+        Cell::from(nav),
+        Cell::from(eta),
+        Cell::from(fuel),
+        Cell::from(cargo),
+    ])
+    .style(style)
+}
+
+fn eta(arrival: OffsetDateTime, now: OffsetDateTime) -> String {
+    let left = (arrival - now).whole_seconds();
+    match left {
+        s if s <= 0 => String::new(),
+        s if s < 60 => format!("{}s", s),
+        s => format!("{}m{:02}s", s / 60, s % 60),
+    }
+}
+
+fn draw_detail(f: &mut Frame, app: &App, area: Rect) {
+    let rows = app.rows();
+    let Some(row) = rows.get(app.selected) else {
+        return;
+    };
+
+    let mut lines = vec![];
+
+    if let Some(c) = row.checkpoint {
+        lines.push(Line::from(format!("Behaviour: {}", c.behaviour.name)));
+        lines.push(Line::from(format!("State: {}", c.state)));
+        if let Some(error) = &c.error {
+            lines.push(Line::styled(
+                format!("Error: {}", error),
+                Style::new().red(),
+            ));
+        }
+        lines.push(Line::default());
+    }
+
+    if let Some(ship) = row.ship {
+        let nav = &ship.nav;
+        lines.push(Line::from(format!(
+            "{} {} · {}",
+            ship.registration.role, ship.registration.name, nav.flight_mode
+        )));
+        lines.push(Line::from(format!(
+            "{} at {}",
+            nav.status,
+            nav.location.waypoint_ident()
+        )));
+        lines.push(Line::from(format!(
+            "Route: {} -> {}, arrives {}",
+            nav.route.origin.location.waypoint_ident(),
+            nav.route.destination.location.waypoint_ident(),
+            nav.route.arrival
+        )));
+        lines.push(Line::from(format!(
+            "Fuel: {}/{}",
+            ship.fuel.current, ship.fuel.capacity
+        )));
+        if let Some(expiration) = ship.cooldown.expiration {
+            lines.push(Line::from(format!("Cooldown until {}", expiration)));
+        }
+        lines.push(Line::from(format!(
+            "Cargo: {}/{}",
+            ship.cargo.current, ship.cargo.capacity
+        )));
+        for item in &ship.cargo.inventory {
+            lines.push(Line::from(format!("  {:>5} {}", item.units, item.symbol)));
+        }
+        lines.push(Line::from(format!(
+            "Mounts: {}",
+            ship.mounts
+                .iter()
+                .map(|m| m.symbol.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        )));
+    } else {
+        lines.push(Line::styled(
+            "No game state, the API hasn't been polled",
+            Style::new().dim(),
+        ));
+    }
+
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" {} ", row.symbol)),
+        )
+        .wrap(Wrap { trim: false });
+    f.render_widget(paragraph, area);
+}
+
+fn draw_contracts(f: &mut Frame, app: &App, area: Rect) {
+    let items = app
+        .contracts
+        .iter()
+        .map(|c| {
+            let mut lines = vec![Line::from(format!(
+                "{} {:?} {}",
+                c.faction_symbol,
+                c.contract_type,
+                if c.accepted { "" } else { "(open)" }
+            ))
+            .bold()];
+            lines.extend(c.terms.deliver.iter().map(|d| {
+                Line::from(format!(
+                    "  {} {}/{} to {}",
+                    d.trade_symbol,
+                    d.units_fulfilled,
+                    d.units_required,
+                    d.destination.waypoint_ident()
+                ))
+            }));
+            lines.push(Line::from(format!(
+                "  {} + {} credits",
+                c.terms.payment.on_accepted, c.terms.payment.on_fulfilled
+            )));
+            ListItem::new(lines)
+        })
+        .collect::<Vec<ListItem>>();
+
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(" Contracts "));
+    f.render_widget(list, area);
+}
+
+fn draw_transactions(f: &mut Frame, app: &App, area: Rect) {
+    let rows = app.transactions.iter().map(|t| {
+        let style = match t.transaction_type.as_str() {
+            "SELL" => Style::new().green(),
+            _ => Style::new().yellow(),
+        };
+        Row::new([
+            t.ship_symbol.clone(),
+            t.transaction_type.clone(),
+            format!("{} {}", t.units, t.trade_symbol),
+            t.total_price.to_string(),
+            t.waypoint.waypoint_ident(),
+        ])
+        .style(style)
+    });
+
+    let widths = [
+        Constraint::Length(14),
+        Constraint::Length(9),
+        Constraint::Min(16),
+        Constraint::Length(8),
+        Constraint::Length(12),
+    ];
+
+    let table = Table::new(rows, widths).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Transactions "),
+    );
+    f.render_widget(table, area);
+}
+
+fn draw_errors(f: &mut Frame, app: &App, area: Rect) {
+    let stopped = app
+        .checkpoints
+        .values()
+        .filter_map(|c| c.error.as_ref().map(|e| (c.ship_symbol.as_str(), e)))
+        .map(|(ship, error)| ListItem::new(format!("{}: {}", ship, error)).red());
+
+    let polled = app.errors.iter().map(|(at, error)| {
+        ListItem::new(format!(
+            "{:02}:{:02}:{:02} {}",
+            at.hour(),
+            at.minute(),
+            at.second(),
+            error
+        ))
+        .yellow()
+    });
+
+    let list = List::new(stopped.chain(polled).collect::<Vec<ListItem>>())
+        .block(Block::default().borders(Borders::ALL).title(" Errors "));
+    f.render_widget(list, area);
+}
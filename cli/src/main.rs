@@ -1,39 +1,147 @@
-use std::path::Path;
+use std::path::PathBuf;
 use std::str::FromStr;
-use std::{fs, vec};
+use std::vec;
 
 use clap::Parser;
 use eyre::{Ok, Result};
-use inquire::{Select, Text};
+use inquire::{Confirm, Select, Text};
 use openapi::apis;
-use serde::{Deserialize, Serialize};
 use tabled::Table;
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
 
+use crate::output::Output;
+use crate::profile::{Profile, ProfilesFile};
+use crate::ship::ShipCommand;
+
+mod dashboard;
+mod map;
+mod output;
+mod profile;
+mod ship;
+mod status;
+
 #[derive(Debug, Parser)]
 enum Command {
     GetAgent,
-    Step,
     GetShips,
+    GetContracts,
+    /// Lists the waypoints of a system, fetching them if they aren't stored.
+    GetWaypoints {
+        /// Defaults to the profile's default_system.
+        system: Option<String>,
+    },
+    /// Acts with a single ship.
+    Ship {
+        symbol: String,
+        #[clap(subcommand)]
+        command: ShipCommand,
+    },
+    /// Draws the stored waypoints of a system and the ships in it.
+    Map {
+        /// Defaults to the profile's default_system.
+        system: Option<String>,
+        /// Write an SVG image to this file instead of plotting in the
+        /// terminal.
+        #[arg(long)]
+        svg: Option<PathBuf>,
+        #[arg(long, default_value_t = 100)]
+        width: usize,
+        #[arg(long, default_value_t = 40)]
+        height: usize,
+    },
+    /// Flies a ship to a waypoint, refueling and docking on the way.
+    Travel {
+        ship: String,
+        waypoint: String,
+    },
     InitManager,
-    Status,
+    /// Shows the server status and leaderboards.
+    Status {
+        /// Show the agent's leaderboard ranks in the snapshots the commander
+        /// took instead.
+        #[arg(long)]
+        history: bool,
+    },
+    /// Watches the fleet the commander runs.
+    Dashboard {
+        /// Also poll the API for ships, contracts and credits every this many
+        /// seconds.
+        #[arg(long)]
+        poll: Option<u64>,
+    },
     RefreshWaypoints,
-    CrdGen,
-    Run,
+    CrdGen {
+        /// Also print the operator's service account and cluster role.
+        #[arg(long)]
+        rbac: bool,
+        /// Also print an example manager.
+        #[arg(long)]
+        sample: bool,
+        /// Namespace the operator is deployed to.
+        #[arg(long, default_value = "default")]
+        namespace: String,
+    },
+    Run {
+        /// Symbol of the stored agent to run, defaults to the one in
+        /// commander.toml.
+        #[arg(long)]
+        agent: Option<String>,
+    },
+    /// Registers a new agent and writes its token to agent.toml.
+    Register {
+        #[arg(long)]
+        symbol: Option<String>,
+        #[arg(long)]
+        faction: Option<String>,
+        #[arg(long)]
+        email: Option<String>,
+        /// Overwrite an existing agent.toml without asking.
+        #[arg(long)]
+        force: bool,
+    },
+    Probe {
+        #[arg(required = true)]
+        ships: Vec<String>,
+    },
+    Staleness {
+        /// Defaults to the profile's default_system.
+        system: Option<String>,
+    },
+    Siphon {
+        ship: String,
+    },
+    Refine {
+        ship: String,
+        station: String,
+    },
+    Construction {
+        #[clap(subcommand)]
+        command: ConstructionCommand,
+    },
+}
+
+#[derive(Debug, Parser)]
+enum ConstructionCommand {
+    Status {
+        /// Defaults to the profile's default_system.
+        system: Option<String>,
+    },
+    /// Buys the materials the jump gate needs and delivers them. The ship
+    /// doesn't mine, mined materials have to be transferred to it.
+    Supply { ship: String },
 }
 
 #[derive(Debug, Parser)]
 struct Config {
+    /// Profile in agent.toml to use.
+    #[arg(long, global = true, env = "SPACETRADERS_PROFILE")]
+    profile: Option<String>,
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output: Output,
     #[clap(subcommand)]
     command: Option<Command>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct AgentConfig {
-    token: String,
-    agent: String,
-}
-
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
@@ -47,19 +155,101 @@ async fn main() -> Result<()> {
         .init();
 
     let config: Config = clap::Parser::parse();
-    let agent_config = get_conf()?;
-    let conf = apis::configuration::Configuration::new();
+    let config_path = profile::config_path();
+    let profiles = ProfilesFile::load(&config_path)?;
+    let profile_name = profiles.profile_name(config.profile.as_deref());
+    let profile = Profile::resolve(&profiles, profile_name.as_str())?;
+    let conf = profile.unauthenticated();
+    let output = config.output;
 
     match config.command {
-        Some(Command::Run) => commander::run().await?,
+        Some(Command::Run { agent }) => {
+            commander::run(&profile.endpoints(), agent.or(profile.agent.clone())).await?
+        }
+
+        Some(Command::Register {
+            symbol,
+            faction,
+            email,
+            force,
+        }) => {
+            // The file entry, a token from the environment isn't overwritten.
+            let stored = profiles
+                .profiles
+                .get(&profile_name)
+                .filter(|p| p.token.is_some());
+            if let Some(stored) = stored {
+                let holder = match &stored.agent {
+                    Some(agent) => format!("the token of {}", agent),
+                    None => "a token".to_string(),
+                };
+                println!(
+                    "Warning: profile {} in {} already holds {}",
+                    profile_name,
+                    config_path.display(),
+                    holder
+                );
+                if !force && !Confirm::new("Overwrite it?").with_default(false).prompt()? {
+                    return Ok(());
+                }
+            }
 
-        Some(Command::CrdGen) => operator::crdgen()?,
+            let symbol = match symbol {
+                Some(symbol) => symbol,
+                None => Text::new("What is your agent symbol?").prompt()?,
+            };
+            let faction = match faction {
+                Some(faction) => common::models::FactionSymbol::from_str(faction.as_str())?,
+                None => common::models::FactionSymbol::from_str(
+                    Select::new("Select a faction:", common::models::FactionSymbol::to_vec())
+                        .prompt()?,
+                )?,
+            };
 
-        Some(Command::Status) => {
-            let openapi_response = apis::default_api::get_status(&conf).await?;
-            println!("{:#?}", openapi_response);
+            let registration = agent::Registration {
+                symbol,
+                faction: faction.into(),
+                email,
+            };
+            let agent = commander::register(&profile.endpoints(), &registration).await?;
+
+            let mut profiles = profiles;
+            let entry = profiles.profiles.entry(profile_name.clone()).or_default();
+            entry.agent = Some(agent.symbol.clone());
+            entry.token = Some(agent.token.clone());
+            profiles.save(&config_path)?;
+
+            println!(
+                "Registered {} at {}, token written to profile {} in {}",
+                agent.symbol,
+                agent.headquarters,
+                profile_name,
+                config_path.display()
+            );
+        }
+
+        Some(Command::CrdGen {
+            rbac,
+            sample,
+            namespace,
+        }) => {
+            operator::crdgen()?;
+            if rbac {
+                operator::rbacgen(namespace.as_str())?;
+            }
+            if sample {
+                operator::sample_manager()?;
+            }
         }
 
+        Some(Command::Dashboard { poll }) => dashboard::run(&profile, poll).await?,
+
+        Some(Command::Status { history: false }) => {
+            status::show(&conf, output, profile.agent.as_deref()).await?
+        }
+
+        Some(Command::Status { history: true }) => status::history(&profile, output).await?,
+
         Some(Command::InitManager) => {
             let symbol = Text::new("What is your agent symbol?").prompt()?;
 
@@ -70,23 +260,19 @@ async fn main() -> Result<()> {
             operator::init_manager(symbol, faction).await?
         }
 
-        Some(Command::GetAgent) => match agent_config {
-            Some(agent_config) => {
-                let api_config = get_authenticated_config(agent_config.token);
+        Some(Command::GetAgent) => match profile.api_config() {
+            Some(api_config) => {
                 let res = apis::agents_api::get_my_agent(&api_config).await?;
 
                 let agent = common::models::Agent::from(res.data);
-                let agent_table = vec![agent];
-                let agent_table = Table::new(agent_table).to_string();
-                println!("\n{}\n", agent_table);
+                output.one(&agent)?;
             }
 
             None => println!("No agent found. Please register first"),
         },
 
-        Some(Command::GetShips) => match agent_config {
-            Some(agent_config) => {
-                let api_config = get_authenticated_config(agent_config.token);
+        Some(Command::GetShips) => match profile.api_config() {
+            Some(api_config) => {
                 let page = 1;
                 let limit = 10;
                 let res =
@@ -111,23 +297,60 @@ async fn main() -> Result<()> {
                     }
                 }
 
-                let ship_table = Table::new(ships).to_string();
-                println!("\n{}\n", ship_table)
+                output.rows(&ships)?;
             }
 
             None => println!("No agent found. Please register first"),
         },
 
-        Some(Command::Step) => match agent_config {
-            Some(agent_config) => {
-                let api_config = get_authenticated_config(agent_config.token);
-                let db = common::repository::connect().await?;
-                let dest = common::models::Location::from_str("X1-GQ23-H45")?;
+        Some(Command::GetContracts) => match profile.api_config() {
+            Some(api_config) => {
+                let contracts = common::machines::actions::get_all_contracts(&api_config).await?;
+                output.rows(&contracts)?;
+            }
+
+            None => println!("No agent found. Please register first"),
+        },
+
+        Some(Command::GetWaypoints { system }) => match profile.api_config() {
+            Some(api_config) => {
+                let system = profile.system(system)?;
+                let db = profile.connect().await?;
+                let waypoints =
+                    common::machines::actions::system_waypoints(&api_config, &db, system.as_str())
+                        .await?;
+                output.rows(&waypoints)?;
+            }
+
+            None => println!("No agent found. Please register first"),
+        },
+
+        Some(Command::Map {
+            system,
+            svg,
+            width,
+            height,
+        }) => {
+            let system = profile.system(system)?;
+            map::run(&profile, system.as_str(), svg.as_deref(), width, height).await?
+        }
+
+        Some(Command::Ship { symbol, command }) => match profile.api_config() {
+            Some(api_config) => {
+                ship::run(&api_config, output, symbol.to_uppercase().as_str(), command).await?
+            }
+            None => println!("No agent found. Please register first"),
+        },
+
+        Some(Command::Travel { ship, waypoint }) => match profile.api_config() {
+            Some(api_config) => {
+                let db = profile.connect().await?;
+                let dest = common::models::Location::from_str(waypoint.to_uppercase().as_str())?;
                 let mut machine = common::machines::TravelMachineWrapper::new(
                     api_config,
                     &db,
                     dest,
-                    "NATINGAR2-3",
+                    ship.to_uppercase().as_str(),
                 )
                 .await?;
 
@@ -146,6 +369,148 @@ async fn main() -> Result<()> {
             None => println!("No agent found. Please register first"),
         },
 
+        Some(Command::Probe { ships }) => match profile.api_config() {
+            Some(api_config) => {
+                let db = profile.connect().await?;
+
+                let count = ships.len();
+                let mut probes = vec![];
+                for (index, ship) in ships.iter().enumerate() {
+                    let probe = common::machines::ProbeMachineWrapper::new(
+                        api_config.clone(),
+                        &db,
+                        ship.as_str(),
+                        index,
+                        count,
+                    )
+                    .await?;
+
+                    probes.push(probe.run());
+                }
+
+                futures::future::try_join_all(probes).await?;
+            }
+            None => println!("No agent found. Please register first"),
+        },
+
+        Some(Command::Siphon { ship }) => match profile.api_config() {
+            Some(api_config) => {
+                let db = profile.connect().await?;
+                common::machines::SiphonMachineWrapper::new(api_config, &db, ship.as_str())
+                    .await?
+                    .run()
+                    .await?;
+            }
+            None => println!("No agent found. Please register first"),
+        },
+
+        Some(Command::Refine { ship, station }) => match profile.api_config() {
+            Some(api_config) => {
+                let db = profile.connect().await?;
+                let station = common::models::Location::from_str(station.as_str())?;
+                common::machines::RefineryMachineWrapper::new(
+                    api_config,
+                    &db,
+                    ship.as_str(),
+                    station,
+                )
+                .await?
+                .run()
+                .await?;
+            }
+            None => println!("No agent found. Please register first"),
+        },
+
+        Some(Command::Construction { command }) => match command {
+            ConstructionCommand::Status { system } => {
+                let system = profile.system(system)?;
+                let db = profile.connect().await?;
+
+                if let Some(api_config) = profile.api_config() {
+                    let gate = common::machines::actions::system_waypoints(
+                        &api_config,
+                        &db,
+                        system.as_str(),
+                    )
+                    .await?
+                    .into_iter()
+                    .find(|w| w.waypoint_type == common::models::WaypointType::JumpGate);
+
+                    if let Some(gate) = gate {
+                        let res = apis::systems_api::get_construction(
+                            &api_config,
+                            system.as_str(),
+                            gate.location.waypoint_ident().as_str(),
+                        )
+                        .await?;
+                        let construction = common::models::Construction::from(res.data);
+                        common::repository::save_construction(&db, &construction).await?;
+                    }
+                }
+
+                match common::repository::get_construction(&db, system.as_str()).await? {
+                    Some(construction) => {
+                        let mut materials = vec![];
+                        for material in construction.materials.iter() {
+                            let price = common::machines::actions::cheapest_market(
+                                &db,
+                                system.as_str(),
+                                &material.trade_symbol,
+                            )
+                            .await?
+                            .map(|(_, price)| price);
+
+                            materials.push(common::models::ConstructionMaterialStatus::new(
+                                material, price,
+                            ));
+                        }
+
+                        let remaining: i32 = materials.iter().map(|m| m.remaining).sum();
+                        let cost: i64 = materials.iter().filter_map(|m| m.estimated_cost).sum();
+
+                        if output != Output::Table {
+                            output.rows(&materials)?;
+                            return Ok(());
+                        }
+
+                        let materials_table = Table::new(materials).to_string();
+                        println!(
+                            "\nConstruction at {} (complete: {})\n{}\n",
+                            construction.location, construction.is_complete, materials_table
+                        );
+                        println!(
+                            "Remaining units: {}, estimated cost: {} credits\n",
+                            remaining, cost
+                        );
+                    }
+                    None => println!("No construction recorded for {}", system),
+                }
+            }
+
+            ConstructionCommand::Supply { ship } => match profile.api_config() {
+                Some(api_config) => {
+                    let db = profile.connect().await?;
+                    common::machines::ConstructionMachineWrapper::new(
+                        api_config,
+                        &db,
+                        ship.as_str(),
+                    )
+                    .await?
+                    .run()
+                    .await?;
+                }
+                None => println!("No agent found. Please register first"),
+            },
+        },
+
+        Some(Command::Staleness { system }) => {
+            let system = profile.system(system)?;
+            let db = profile.connect().await?;
+            let staleness = common::repository::get_waypoint_staleness(&db, &system).await?;
+
+            output.rows(&staleness)?;
+        }
+
         Some(Command::RefreshWaypoints) => {} //match agent_config {
         // Some(agent_config) => {
         //     let api_config = get_authenticated_config(agent_config.token);
@@ -192,7 +557,7 @@ async fn main() -> Result<()> {
         //
         //     println!("Total waypoints: {}", res.meta.total);
         //
-        //     let db = common::repository::connect().await?;
+        //     let db = profile.connect().await?;
         //     common::repository::insert_waypoints(&db, waypoints).await?;
         // }
         // None => println!("No agent found. Please register first"),
@@ -204,22 +569,3 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
-
-fn get_conf() -> Result<Option<AgentConfig>> {
-    let file = Path::new("agent.toml");
-    match file.exists() {
-        true => {
-            let conf = fs::read_to_string(file)?;
-            let conf: AgentConfig = toml::from_str(&conf)?;
-            Ok(Some(conf))
-        }
-        false => Ok(None),
-    }
-}
-
-fn get_authenticated_config(bearer_token: String) -> apis::configuration::Configuration {
-    apis::configuration::Configuration {
-        bearer_access_token: Some(bearer_token),
-        ..Default::default()
-    }
-}
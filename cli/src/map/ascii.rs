@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::IsTerminal;
+
+use common::models::{ShipNavStatus, WaypointType};
+use crossterm::style::{Color, Stylize};
+use time::OffsetDateTime;
+
+use super::{waypoint_style, SystemMap, WAYPOINT_TYPES};
+
+const ROUTE: char = '.';
+const SHIP: char = '@';
+const ROUTE_COLOUR: &str = "#616161";
+const SHIP_COLOUR: &str = "#ffffff";
+
+/// A character and its colour.
+type Cell = Option<(char, &'static str)>;
+
+/// Plots the system on a grid of characters, x to the right and y up, with
+/// a legend and the ships below it. Colours are only used on a terminal.
+pub fn render(map: &SystemMap, width: usize, height: usize) -> String {
+    let width = width.max(10);
+    let height = height.max(5);
+    let mut grid: Vec<Vec<Cell>> = vec![vec![None; width]; height];
+
+    let (min_x, min_y, max_x, max_y) = map.bounds();
+    let to_cell = |x: f64, y: f64| -> (usize, usize) {
+        let column = scale(x, min_x, max_x, width);
+        let row = height - 1 - scale(y, min_y, max_y, height);
+        (column, row)
+    };
+
+    for ship in &map.ships {
+        if let Some(((x0, y0), (x1, y1))) = ship.route {
+            let from = to_cell(x0 as f64, y0 as f64);
+            let to = to_cell(x1 as f64, y1 as f64);
+            for (column, row) in line(from, to) {
+                grid[row][column] = Some((ROUTE, ROUTE_COLOUR));
+            }
+        }
+    }
+
+    // Waypoints in orbit of another share its coordinates, draw them first
+    // so the one they orbit wins the cell. The cell shows the markers of all.
+    let mut waypoints = map.waypoints.iter().collect::<Vec<_>>();
+    waypoints.sort_by_key(|w| !in_orbit(&w.waypoint_type));
+
+    let mut markers: BTreeMap<(usize, usize), String> = BTreeMap::new();
+    for waypoint in waypoints {
+        let (column, row) = to_cell(waypoint.x as f64, waypoint.y as f64);
+        let (glyph, colour) = waypoint_style(&waypoint.waypoint_type);
+        grid[row][column] = Some((glyph, colour));
+
+        let cell = markers.entry((column, row)).or_default();
+        for marker in waypoint.markers().chars() {
+            if !cell.contains(marker) {
+                cell.push(marker);
+            }
+        }
+    }
+
+    for ((column, row), cell) in markers {
+        for (i, marker) in cell.chars().enumerate() {
+            let column = column + 1 + i;
+            if column < width && grid[row][column].is_none_or(|(c, _)| c == ROUTE) {
+                grid[row][column] = Some((marker, SHIP_COLOUR));
+            }
+        }
+    }
+
+    for ship in &map.ships {
+        let (column, row) = to_cell(ship.x, ship.y);
+        // Ships at a waypoint sit next to it rather than hiding it.
+        let column = match ship.status {
+            ShipNavStatus::InTransit => column,
+            _ => column.saturating_sub(1),
+        };
+        grid[row][column] = Some((SHIP, SHIP_COLOUR));
+    }
+
+    let colour = std::io::stdout().is_terminal();
+    let mut out = String::new();
+
+    let _ = writeln!(
+        out,
+        "{}  x {}..{}  y {}..{}",
+        map.system, min_x, max_x, min_y, max_y
+    );
+    let border = format!("+{}+", "-".repeat(width));
+    let _ = writeln!(out, "{}", border);
+    for row in grid {
+        out.push('|');
+        for cell in row {
+            match cell {
+                Some((c, hex)) if colour => {
+                    let _ = write!(out, "{}", c.with(rgb(hex)));
+                }
+                Some((c, _)) => out.push(c),
+                None => out.push(' '),
+            }
+        }
+        out.push_str("|\n");
+    }
+    let _ = writeln!(out, "{}", border);
+
+    let legend = WAYPOINT_TYPES
+        .iter()
+        .filter(|t| map.waypoints.iter().any(|w| &w.waypoint_type == *t))
+        .map(|t| {
+            let (glyph, hex) = waypoint_style(t);
+            match colour {
+                true => format!("{} {}", glyph.with(rgb(hex)), t),
+                false => format!("{} {}", glyph, t),
+            }
+        })
+        .collect::<Vec<String>>();
+    let _ = writeln!(out, "{}", legend.join("  "));
+    let _ = writeln!(
+        out,
+        "$ marketplace  # shipyard  + fuel  {} ship  {} route",
+        SHIP, ROUTE
+    );
+
+    let now = OffsetDateTime::now_utc();
+    for ship in &map.ships {
+        match ship.status {
+            ShipNavStatus::InTransit => {
+                let left = (ship.arrival - now).whole_seconds().max(0);
+                let _ = writeln!(
+                    out,
+                    "{} {} to {}, arrives in {}m{:02}s",
+                    ship.symbol,
+                    ship.status,
+                    ship.location,
+                    left / 60,
+                    left % 60
+                );
+            }
+            _ => {
+                let _ = writeln!(out, "{} {} at {}", ship.symbol, ship.status, ship.location);
+            }
+        }
+    }
+
+    out
+}
+
+fn in_orbit(waypoint_type: &WaypointType) -> bool {
+    matches!(
+        waypoint_type,
+        WaypointType::Moon | WaypointType::OrbitalStation
+    )
+}
+
+/// Position of the value between min and max on an axis of the given
+/// number of cells.
+fn scale(value: f64, min: i32, max: i32, cells: usize) -> usize {
+    if max <= min {
+        return cells / 2;
+    }
+
+    let position = (value - min as f64) / (max - min) as f64 * (cells - 1) as f64;
+    (position.round() as usize).min(cells - 1)
+}
+
+/// The cells on the straight line between two cells, both included.
+fn line(from: (usize, usize), to: (usize, usize)) -> Vec<(usize, usize)> {
+    let (mut x, mut y) = (from.0 as i64, from.1 as i64);
+    let (x1, y1) = (to.0 as i64, to.1 as i64);
+    let dx = (x1 - x).abs();
+    let dy = -(y1 - y).abs();
+    let sx = if x < x1 { 1 } else { -1 };
+    let sy = if y < y1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    let mut cells = vec![];
+
+    loop {
+        cells.push((x as usize, y as usize));
+        if x == x1 && y == y1 {
+            return cells;
+        }
+
+        let e2 = 2 * error;
+        if e2 >= dy {
+            error += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            error += dx;
+            y += sy;
+        }
+    }
+}
+
+fn rgb(hex: &str) -> Color {
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(255);
+
+    Color::Rgb {
+        r: channel(1),
+        g: channel(3),
+        b: channel(5),
+    }
+}
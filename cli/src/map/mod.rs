@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use common::machines::actions;
+use common::models::{Ship, ShipNavStatus, Waypoint, WaypointTraitSymbol, WaypointType};
+use eyre::{Context, Result};
+use time::OffsetDateTime;
+
+use crate::profile::Profile;
+
+mod ascii;
+mod svg;
+
+/// Everything drawn on a system map.
+pub struct SystemMap {
+    pub system: String,
+    pub waypoints: Vec<MapWaypoint>,
+    pub ships: Vec<MapShip>,
+}
+
+pub struct MapWaypoint {
+    pub symbol: String,
+    pub waypoint_type: WaypointType,
+    pub x: i32,
+    pub y: i32,
+    pub marketplace: bool,
+    pub shipyard: bool,
+    pub fuel: bool,
+}
+
+pub struct MapShip {
+    pub symbol: String,
+    pub status: ShipNavStatus,
+    pub location: String,
+    /// Where the ship is now, between origin and destination while in
+    /// transit.
+    pub x: f64,
+    pub y: f64,
+    /// Origin and destination of the flight the ship is on.
+    pub route: Option<((i32, i32), (i32, i32))>,
+    pub arrival: OffsetDateTime,
+}
+
+/// Draws the stored waypoints of a system with the agent's ships in it, as
+/// a plot in the terminal or as an SVG file.
+pub async fn run(
+    profile: &Profile,
+    system: &str,
+    svg: Option<&Path>,
+    width: usize,
+    height: usize,
+) -> Result<()> {
+    let map = SystemMap::load(profile, system).await?;
+
+    match svg {
+        Some(path) => {
+            fs::write(path, svg::render(&map))
+                .wrap_err_with(|| format!("Error writing {}", path.display()))?;
+            println!("Wrote map of {} to {}", system, path.display());
+        }
+        None => print!("{}", ascii::render(&map, width, height)),
+    }
+
+    Ok(())
+}
+
+impl SystemMap {
+    /// Reads the system's waypoints from the database, fetching them first
+    /// if they aren't stored and the profile has a token. Ships are only
+    /// known with a token.
+    pub async fn load(profile: &Profile, system: &str) -> Result<Self> {
+        let db = profile.connect().await?;
+        let api_config = profile.api_config();
+
+        let waypoints = match &api_config {
+            Some(conf) => actions::system_waypoints(conf, &db, system).await?,
+            None => common::repository::get_system_waypoints(&db, system).await?,
+        };
+        if waypoints.is_empty() {
+            return Err(eyre::eyre!("No waypoints stored for {}", system));
+        }
+
+        let fuel = common::repository::get_latest_market_snapshots(&db, system)
+            .await?
+            .into_iter()
+            .filter(|s| s.trade_goods.iter().any(|g| g.symbol == "FUEL"))
+            .map(|s| s.location.waypoint_ident())
+            .collect::<HashSet<String>>();
+
+        let ships = match &api_config {
+            Some(conf) => actions::get_all_ships(conf).await?,
+            None => vec![],
+        };
+
+        let now = OffsetDateTime::now_utc();
+
+        Ok(Self {
+            system: system.to_string(),
+            waypoints: waypoints
+                .into_iter()
+                .map(|w| MapWaypoint::new(w, &fuel))
+                .collect(),
+            ships: ships
+                .iter()
+                .filter(|s| s.nav.location.system_ident() == system)
+                .map(|s| MapShip::new(s, now))
+                .collect(),
+        })
+    }
+
+    /// Smallest and largest x and y of everything on the map.
+    pub fn bounds(&self) -> (i32, i32, i32, i32) {
+        let xs = self.waypoints.iter().map(|w| w.x);
+        let ys = self.waypoints.iter().map(|w| w.y);
+
+        (
+            xs.clone().min().unwrap_or(0),
+            ys.clone().min().unwrap_or(0),
+            xs.max().unwrap_or(0),
+            ys.max().unwrap_or(0),
+        )
+    }
+}
+
+impl MapWaypoint {
+    fn new(waypoint: Waypoint, fuel: &HashSet<String>) -> Self {
+        let symbol = waypoint.location.waypoint_ident();
+        let has = |t: WaypointTraitSymbol| waypoint.traits.iter().any(|w| w.symbol == t);
+
+        Self {
+            marketplace: has(WaypointTraitSymbol::Marketplace),
+            shipyard: has(WaypointTraitSymbol::Shipyard),
+            fuel: fuel.contains(&symbol) || waypoint.waypoint_type == WaypointType::FuelStation,
+            symbol,
+            waypoint_type: waypoint.waypoint_type,
+            x: waypoint.x,
+            y: waypoint.y,
+        }
+    }
+
+    /// The markers of the waypoint's traits: `$` marketplace, `#` shipyard
+    /// and `+` fuel.
+    pub fn markers(&self) -> String {
+        [
+            (self.marketplace, '$'),
+            (self.shipyard, '#'),
+            (self.fuel, '+'),
+        ]
+        .into_iter()
+        .filter_map(|(has, marker)| has.then_some(marker))
+        .collect()
+    }
+}
+
+impl MapShip {
+    fn new(ship: &Ship, now: OffsetDateTime) -> Self {
+        let route = &ship.nav.route;
+        let origin = (route.origin.x, route.origin.y);
+        let destination = (route.destination.x, route.destination.y);
+
+        let (x, y, route) = match ship.nav.status {
+            ShipNavStatus::InTransit => {
+                let total = (route.arrival - route.departure_time).as_seconds_f64();
+                let flown = (now - route.departure_time).as_seconds_f64();
+                let progress = match total > 0.0 {
+                    true => (flown / total).clamp(0.0, 1.0),
+                    false => 1.0,
+                };
+
+                (
+                    origin.0 as f64 + (destination.0 - origin.0) as f64 * progress,
+                    origin.1 as f64 + (destination.1 - origin.1) as f64 * progress,
+                    Some((origin, destination)),
+                )
+            }
+            _ => (destination.0 as f64, destination.1 as f64, None),
+        };
+
+        Self {
+            symbol: ship.symbol.clone(),
+            status: ship.nav.status.clone(),
+            location: ship.nav.location.waypoint_ident(),
+            x,
+            y,
+            route,
+            arrival: ship.nav.route.arrival,
+        }
+    }
+}
+
+/// A one-letter glyph and a colour for every kind of waypoint.
+pub fn waypoint_style(waypoint_type: &WaypointType) -> (char, &'static str) {
+    match waypoint_type {
+        WaypointType::Planet => ('O', "#4caf50"),
+        WaypointType::GasGiant => ('G', "#ff9800"),
+        WaypointType::Moon => ('o', "#9e9e9e"),
+        WaypointType::OrbitalStation => ('S', "#26c6da"),
+        WaypointType::JumpGate => ('J', "#ab47bc"),
+        WaypointType::AsteroidField => ('A', "#8d6e63"),
+        WaypointType::Asteroid => ('a', "#a1887f"),
+        WaypointType::EngineeredAsteroid => ('E', "#ff7043"),
+        WaypointType::AsteroidBase => ('B', "#d7ccc8"),
+        WaypointType::Nebula => ('N', "#5c6bc0"),
+        WaypointType::DebrisField => ('D', "#607d8b"),
+        WaypointType::GravityWell => ('W', "#3949ab"),
+        WaypointType::ArtificialGravityWell => ('w', "#7986cb"),
+        WaypointType::FuelStation => ('F', "#fdd835"),
+    }
+}
+
+/// Every kind of waypoint in the order the legend lists them.
+pub const WAYPOINT_TYPES: [WaypointType; 14] = [
+    WaypointType::Planet,
+    WaypointType::GasGiant,
+    WaypointType::Moon,
+    WaypointType::OrbitalStation,
+    WaypointType::JumpGate,
+    WaypointType::AsteroidField,
+    WaypointType::Asteroid,
+    WaypointType::EngineeredAsteroid,
+    WaypointType::AsteroidBase,
+    WaypointType::Nebula,
+    WaypointType::DebrisField,
+    WaypointType::GravityWell,
+    WaypointType::ArtificialGravityWell,
+    WaypointType::FuelStation,
+];
@@ -0,0 +1,165 @@
+use std::fmt::Write;
+
+use common::models::ShipNavStatus;
+
+use super::{waypoint_style, SystemMap, WAYPOINT_TYPES};
+
+const BACKGROUND: &str = "#121212";
+const FOREGROUND: &str = "#eeeeee";
+const ROUTE_COLOUR: &str = "#9e9e9e";
+const SHIP_COLOUR: &str = "#ff5252";
+
+/// Draws the system as an SVG image in game coordinates, with y flipped so
+/// it points up like in the terminal plot.
+pub fn render(map: &SystemMap) -> String {
+    let (min_x, min_y, max_x, max_y) = map.bounds();
+    let span = (max_x - min_x).max(max_y - min_y).max(1) as f64;
+    let padding = span * 0.05;
+    let radius = span / 150.0;
+    let font = radius * 2.0;
+
+    let view_x = min_x as f64 - padding;
+    let view_y = -max_y as f64 - padding;
+    let view_width = (max_x - min_x) as f64 + 2.0 * padding;
+    let view_height = (max_y - min_y) as f64 + 2.0 * padding + font * 4.0;
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" font-family="monospace" font-size="{}">"#,
+        view_x, view_y, view_width, view_height, font
+    );
+    let _ = writeln!(
+        out,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+        view_x, view_y, view_width, view_height, BACKGROUND
+    );
+    let _ = writeln!(
+        out,
+        r#"<text x="{}" y="{}" fill="{}">{}</text>"#,
+        view_x + font,
+        view_y + font * 1.5,
+        FOREGROUND,
+        map.system
+    );
+
+    for ship in &map.ships {
+        if let Some(((x0, y0), (x1, y1))) = ship.route {
+            let _ = writeln!(
+                out,
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}" stroke-dasharray="{} {}"/>"#,
+                x0,
+                -y0,
+                x1,
+                -y1,
+                ROUTE_COLOUR,
+                radius / 3.0,
+                radius,
+                radius
+            );
+        }
+    }
+
+    for waypoint in &map.waypoints {
+        let (_, colour) = waypoint_style(&waypoint.waypoint_type);
+        let _ = writeln!(
+            out,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="{}"><title>{} {} {}</title></circle>"#,
+            waypoint.x,
+            -waypoint.y,
+            radius,
+            colour,
+            waypoint.symbol,
+            waypoint.waypoint_type,
+            waypoint.markers()
+        );
+    }
+
+    // Waypoints in orbit share coordinates, so the labels of a spot are
+    // joined into one.
+    let mut labels: Vec<((i32, i32), Vec<String>)> = vec![];
+    for waypoint in &map.waypoints {
+        let name = waypoint.symbol.rsplit('-').next().unwrap_or_default();
+        let label = format!("{}{}", name, waypoint.markers());
+        match labels
+            .iter_mut()
+            .find(|(at, _)| *at == (waypoint.x, waypoint.y))
+        {
+            Some((_, names)) => names.push(label),
+            None => labels.push(((waypoint.x, waypoint.y), vec![label])),
+        }
+    }
+    for ((x, y), names) in labels {
+        let _ = writeln!(
+            out,
+            r#"<text x="{}" y="{}" fill="{}">{}</text>"#,
+            x as f64 + radius * 1.5,
+            -y as f64 + font / 3.0,
+            FOREGROUND,
+            names.join(" ")
+        );
+    }
+
+    for ship in &map.ships {
+        let size = radius * 1.2;
+        let (x, y) = (ship.x, -ship.y);
+        let _ = writeln!(
+            out,
+            r#"<polygon points="{},{} {},{} {},{}" fill="{}"><title>{} {} {}</title></polygon>"#,
+            x,
+            y - size,
+            x - size,
+            y + size,
+            x + size,
+            y + size,
+            SHIP_COLOUR,
+            ship.symbol,
+            ship.status,
+            ship.location
+        );
+        if ship.status == ShipNavStatus::InTransit {
+            let _ = writeln!(
+                out,
+                r#"<text x="{}" y="{}" fill="{}">{}</text>"#,
+                x + size * 1.5,
+                y - size,
+                SHIP_COLOUR,
+                ship.symbol
+            );
+        }
+    }
+
+    // The legend runs along the bottom, below the plot.
+    let legend_y = -min_y as f64 + padding;
+    let mut legend_x = view_x + font;
+    for waypoint_type in WAYPOINT_TYPES
+        .iter()
+        .filter(|t| map.waypoints.iter().any(|w| &w.waypoint_type == *t))
+    {
+        let (_, colour) = waypoint_style(waypoint_type);
+        let name = waypoint_type.to_string();
+        let _ = writeln!(
+            out,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/><text x="{}" y="{}" fill="{}">{}</text>"#,
+            legend_x,
+            legend_y + font,
+            radius,
+            colour,
+            legend_x + radius * 1.5,
+            legend_y + font * 4.0 / 3.0,
+            FOREGROUND,
+            name
+        );
+        legend_x += radius * 3.0 + font * 0.6 * name.len() as f64;
+    }
+    let _ = writeln!(
+        out,
+        r#"<text x="{}" y="{}" fill="{}">$ marketplace  # shipyard  + fuel</text>"#,
+        view_x + font,
+        legend_y + font * 3.0,
+        FOREGROUND
+    );
+
+    out.push_str("</svg>\n");
+    out
+}
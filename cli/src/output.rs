@@ -0,0 +1,74 @@
+use std::io;
+
+use clap::ValueEnum;
+use eyre::Result;
+use serde::Serialize;
+use tabled::{Table, Tabled};
+
+/// How results are printed. Table and CSV have a column per field, with
+/// nested structs flattened the way their `Tabled` impl lays them out. JSON
+/// and YAML have every field, in declaration order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Output {
+    #[default]
+    Table,
+    Json,
+    Yaml,
+    Csv,
+}
+
+impl Output {
+    /// Prints a list, one row per item.
+    pub fn rows<T: Serialize + Tabled>(self, rows: &[T]) -> Result<()> {
+        match self {
+            Self::Table => println!("\n{}\n", Table::new(rows)),
+            Self::Csv => write_csv(rows)?,
+            Self::Json | Self::Yaml => self.serialized(&rows)?,
+        }
+
+        Ok(())
+    }
+
+    /// Prints a single item. JSON and YAML get the item rather than a list
+    /// holding it.
+    pub fn one<T: Serialize + Tabled>(self, item: &T) -> Result<()> {
+        match self {
+            Self::Table | Self::Csv => self.rows(std::slice::from_ref(item)),
+            Self::Json | Self::Yaml => self.serialized(item),
+        }
+    }
+
+    /// Prints something that doesn't fit in rows, with `describe` writing
+    /// the human readable form.
+    pub fn value<T: Serialize>(self, value: &T, describe: impl FnOnce()) -> Result<()> {
+        match self {
+            Self::Table => {
+                describe();
+                Ok(())
+            }
+            Self::Csv => Err(eyre::eyre!("This command has no CSV output")),
+            Self::Json | Self::Yaml => self.serialized(value),
+        }
+    }
+
+    fn serialized<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        match self {
+            Self::Yaml => print!("{}", serde_yaml::to_string(value)?),
+            _ => println!("{}", serde_json::to_string_pretty(value)?),
+        }
+
+        Ok(())
+    }
+}
+
+fn write_csv<T: Tabled>(rows: &[T]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(io::stdout());
+
+    writer.write_record(T::headers().iter().map(|h| h.as_ref()))?;
+    for row in rows {
+        writer.write_record(row.fields().iter().map(|f| f.as_ref()))?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
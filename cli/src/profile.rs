@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{env, fs};
+
+use eyre::{Context, Result};
+use openapi::apis::configuration::Configuration;
+use serde::{Deserialize, Serialize};
+
+const CONFIG_FILE: &str = "agent.toml";
+const DEFAULT_PROFILE: &str = "default";
+
+/// Settings for one agent, a named table in `agent.toml`. Every field is
+/// optional and the `SPACETRADERS_*` variables of the same name win over it.
+///
+/// ```toml
+/// default_profile = "main"
+///
+/// [profiles.main]
+/// agent = "NATINGAR3"
+/// token = "..."
+/// default_system = "X1-GQ23"
+///
+/// [profiles.mock]
+/// base_url = "http://localhost:8080/v2"
+/// database_url = "sqlite://mock.sqlite?mode=rwc"
+/// rate_limit = { per_second = 10, burst = 20 }
+/// ```
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub agent: Option<String>,
+    pub token: Option<String>,
+    pub base_url: Option<String>,
+    pub database_url: Option<String>,
+    pub default_system: Option<String>,
+    pub rate_limit: Option<RateLimit>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub per_second: u32,
+    /// Requests that may be sent at once, defaults to `per_second`.
+    pub burst: Option<u32>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfilesFile {
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl ProfilesFile {
+    /// Reads the file at the path, which may not exist yet. A file with a
+    /// single `token` and `agent` at the top is read as the default profile.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let conf = fs::read_to_string(path)?;
+        let value = toml::from_str::<toml::Table>(&conf)
+            .wrap_err_with(|| format!("Error reading {}", path.display()))?;
+
+        if value.contains_key("profiles") {
+            Ok(value.try_into()?)
+        } else {
+            let profile = value.try_into()?;
+            Ok(Self {
+                default_profile: None,
+                profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), profile)]),
+            })
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        fs::write(path, toml::to_string(self)?)
+            .wrap_err_with(|| format!("Error writing {}", path.display()))
+    }
+
+    /// Name of the profile to use: the one asked for, then the file's
+    /// default, then `default`.
+    pub fn profile_name(&self, name: Option<&str>) -> String {
+        name.or(self.default_profile.as_deref())
+            .unwrap_or(DEFAULT_PROFILE)
+            .to_string()
+    }
+}
+
+impl Profile {
+    /// The profile from the file with the environment applied on top.
+    pub fn resolve(file: &ProfilesFile, name: &str) -> Result<Self> {
+        let profile = match file.profiles.get(name) {
+            Some(profile) => profile.clone(),
+            None if name == DEFAULT_PROFILE => Profile::default(),
+            None => return Err(eyre::eyre!("No profile {} in {}", name, CONFIG_FILE)),
+        };
+
+        Ok(profile.with_env())
+    }
+
+    fn with_env(self) -> Self {
+        Self {
+            agent: env_var("SPACETRADERS_AGENT").or(self.agent),
+            token: env_var("SPACETRADERS_TOKEN").or(self.token),
+            base_url: env_var("SPACETRADERS_BASE_URL").or(self.base_url),
+            database_url: env_var("SPACETRADERS_DATABASE_URL").or(self.database_url),
+            default_system: env_var("SPACETRADERS_DEFAULT_SYSTEM").or(self.default_system),
+            rate_limit: self.rate_limit,
+        }
+    }
+
+    /// API configuration without a token.
+    pub fn unauthenticated(&self) -> Configuration {
+        let mut conf = Configuration::new();
+
+        if let Some(base_url) = &self.base_url {
+            conf.base_path = base_url.trim_end_matches('/').to_string();
+        }
+
+        if let Some(limit) = &self.rate_limit {
+            let rate = NonZeroU32::new(limit.per_second).unwrap_or(NonZeroU32::MIN);
+            let burst = limit.burst.and_then(NonZeroU32::new).unwrap_or(rate);
+            let quota = governor::Quota::per_second(rate).allow_burst(burst);
+            conf.rate_limiter = Some(Arc::new(governor::RateLimiter::direct(quota)));
+        }
+
+        conf
+    }
+
+    /// API configuration with the profile's token, if it has one.
+    pub fn api_config(&self) -> Option<Configuration> {
+        let token = self.token.clone()?;
+
+        Some(Configuration {
+            bearer_access_token: Some(token),
+            ..self.unauthenticated()
+        })
+    }
+
+    pub fn endpoints(&self) -> commander::Endpoints {
+        let defaults = commander::Endpoints::default();
+
+        commander::Endpoints {
+            api: self.unauthenticated(),
+            database_url: self.database_url.clone().unwrap_or(defaults.database_url),
+        }
+    }
+
+    pub async fn connect(&self) -> Result<sea_orm::DatabaseConnection> {
+        match &self.database_url {
+            Some(url) => common::repository::connect_to(url.as_str()).await,
+            None => common::repository::connect().await,
+        }
+    }
+
+    /// The system given on the command line, falling back to the profile's.
+    pub fn system(&self, system: Option<String>) -> Result<String> {
+        system
+            .or_else(|| self.default_system.clone())
+            .ok_or_else(|| eyre::eyre!("No system given and the profile has no default_system"))
+    }
+}
+
+/// Where the profiles are read from: `SPACETRADERS_CONFIG`, then an
+/// `agent.toml` in the working directory, then
+/// `$XDG_CONFIG_HOME/spacetraders/agent.toml`.
+pub fn config_path() -> PathBuf {
+    if let Some(path) = env_var("SPACETRADERS_CONFIG") {
+        return PathBuf::from(path);
+    }
+
+    let local = PathBuf::from(CONFIG_FILE);
+    if local.exists() {
+        return local;
+    }
+
+    let config_home = env_var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env_var("HOME").map(|home| Path::new(&home).join(".config")));
+
+    match config_home {
+        Some(dir) => dir.join("spacetraders").join(CONFIG_FILE),
+        None => local,
+    }
+}
+
+fn env_var(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.is_empty())
+}
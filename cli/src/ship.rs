@@ -0,0 +1,514 @@
+use clap::{Subcommand, ValueEnum};
+use common::models::ship::{ShipCargo, ShipCooldown, ShipMount, ShipNav};
+use common::models::{Contract, MarketTransaction, Ship, ShipNavStatus};
+use eyre::{Context, Result};
+use openapi::apis::configuration::Configuration;
+use openapi::apis::fleet_api;
+use openapi::models;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tabled::Table;
+
+use crate::output::Output;
+
+#[derive(Debug, Subcommand)]
+pub enum ShipCommand {
+    /// Shows the ship.
+    Info,
+    /// Shows where the ship is and where it's headed.
+    Nav,
+    Cargo,
+    Cooldown,
+    Mounts,
+    Orbit,
+    Dock,
+    /// Flies to a waypoint in the same system.
+    Navigate {
+        waypoint: String,
+    },
+    /// Warps to a waypoint in another system.
+    Warp {
+        waypoint: String,
+    },
+    /// Jumps to a jump gate in another system.
+    Jump {
+        waypoint: String,
+    },
+    /// Fills the tank, or buys the given units.
+    Refuel {
+        #[arg(long)]
+        units: Option<i32>,
+        /// Use fuel from the cargo hold instead of the market.
+        #[arg(long)]
+        from_cargo: bool,
+    },
+    /// Sets the flight mode: drift, stealth, cruise or burn.
+    FlightMode {
+        mode: String,
+    },
+    Survey,
+    Extract,
+    Siphon,
+    /// Refines raw goods into the given good, e.g. iron.
+    Refine {
+        produce: String,
+    },
+    Buy {
+        good: String,
+        units: i32,
+    },
+    Sell {
+        good: String,
+        units: i32,
+    },
+    Jettison {
+        good: String,
+        units: i32,
+    },
+    /// Moves cargo to another ship at the same waypoint.
+    Transfer {
+        good: String,
+        units: i32,
+        to: String,
+    },
+    InstallMount {
+        mount: String,
+    },
+    RemoveMount {
+        mount: String,
+    },
+    Scan {
+        #[arg(value_enum)]
+        target: ScanTarget,
+    },
+    /// Charts the waypoint the ship is at.
+    Chart,
+    /// Negotiates a new contract at the faction headquarters the ship is at.
+    Negotiate,
+}
+
+#[derive(Clone, Debug, ValueEnum)]
+pub enum ScanTarget {
+    Ships,
+    Systems,
+    Waypoints,
+}
+
+pub async fn run(
+    conf: &Configuration,
+    output: Output,
+    ship: &str,
+    command: ShipCommand,
+) -> Result<()> {
+    match command {
+        ShipCommand::Info => {
+            let res = fleet_api::get_my_ship(conf, ship).await?;
+            output.one(&Ship::from(res.data))?;
+        }
+
+        ShipCommand::Nav => {
+            let res = fleet_api::get_ship_nav(conf, ship).await?;
+            output.value(&res.data, || print_nav(ship, &res.data))?;
+        }
+
+        ShipCommand::Cargo => {
+            let res = fleet_api::get_my_ship_cargo(conf, ship).await?;
+            output.value(&res.data, || print_cargo(&res.data))?;
+        }
+
+        ShipCommand::Cooldown => {
+            let res = fleet_api::get_ship_cooldown(conf, ship).await?;
+            output.value(&res.data, || print_cooldown(&res.data))?;
+        }
+
+        ShipCommand::Mounts => {
+            let res = fleet_api::get_mounts(conf, ship).await?;
+            output.rows(&mounts(res.data))?;
+        }
+
+        ShipCommand::Orbit => {
+            let res = fleet_api::orbit_ship(conf, ship)
+                .await
+                .wrap_err("Error undocking")?;
+            output.value(&res.data, || print_nav(ship, &res.data.nav))?;
+        }
+
+        ShipCommand::Dock => {
+            let res = fleet_api::dock_ship(conf, ship)
+                .await
+                .wrap_err("Error docking")?;
+            output.value(&res.data, || print_nav(ship, &res.data.nav))?;
+        }
+
+        ShipCommand::Navigate { waypoint } => {
+            let req = models::NavigateShipRequest::new(waypoint.to_uppercase());
+            let res = fleet_api::navigate_ship(conf, ship, Some(req))
+                .await
+                .wrap_err_with(|| format!("Error navigating to {}", waypoint))?;
+            output.value(&res.data, || {
+                print_nav(ship, &res.data.nav);
+                print_fuel(&res.data.fuel);
+            })?;
+        }
+
+        ShipCommand::Warp { waypoint } => {
+            let req = models::NavigateShipRequest::new(waypoint.to_uppercase());
+            let res = fleet_api::warp_ship(conf, ship, Some(req))
+                .await
+                .wrap_err_with(|| format!("Error warping to {}", waypoint))?;
+            output.value(&res.data, || {
+                print_nav(ship, &res.data.nav);
+                print_fuel(&res.data.fuel);
+            })?;
+        }
+
+        ShipCommand::Jump { waypoint } => {
+            let req = models::JumpShipRequest::new(waypoint.to_uppercase());
+            let res = fleet_api::jump_ship(conf, ship, Some(req))
+                .await
+                .wrap_err_with(|| format!("Error jumping to {}", waypoint))?;
+            output.value(&res.data, || {
+                print_nav(ship, &res.data.nav);
+                print_transaction(&res.data.transaction);
+                print_cooldown(&res.data.cooldown);
+                print_credits(&res.data.agent);
+            })?;
+        }
+
+        ShipCommand::Refuel { units, from_cargo } => {
+            let req = models::RefuelShipRequest {
+                units,
+                from_cargo: Some(from_cargo),
+            };
+            let res = fleet_api::refuel_ship(conf, ship, Some(req))
+                .await
+                .wrap_err("Error refueling")?;
+            output.value(&res.data, || {
+                print_transaction(&res.data.transaction);
+                print_fuel(&res.data.fuel);
+                print_credits(&res.data.agent);
+            })?;
+        }
+
+        ShipCommand::FlightMode { mode } => {
+            let req = models::PatchShipNavRequest {
+                flight_mode: Some(parse("flight mode", mode.as_str())?),
+            };
+            let res = fleet_api::patch_ship_nav(conf, ship, Some(req))
+                .await
+                .wrap_err("Error setting flight mode")?;
+            output.value(&res.data, || print_nav(ship, &res.data))?;
+        }
+
+        ShipCommand::Survey => {
+            let res = fleet_api::create_survey(conf, ship)
+                .await
+                .wrap_err("Error surveying")?;
+            output.value(&res.data, || {
+                for survey in res.data.surveys.iter() {
+                    let deposits = survey
+                        .deposits
+                        .iter()
+                        .map(|d| d.symbol.as_str())
+                        .collect::<Vec<&str>>();
+                    println!(
+                        "{} {} at {} until {}: {}",
+                        name(&survey.size),
+                        survey.signature,
+                        survey.symbol,
+                        survey.expiration,
+                        deposits.join(", ")
+                    );
+                }
+                print_cooldown(&res.data.cooldown);
+            })?;
+        }
+
+        ShipCommand::Extract => {
+            let res = fleet_api::extract_resources(conf, ship, None)
+                .await
+                .wrap_err("Error extracting")?;
+            output.value(&res.data, || {
+                let extracted = &res.data.extraction.r#yield;
+                println!(
+                    "Extracted {} {}",
+                    extracted.units,
+                    extracted.symbol.to_string()
+                );
+                print_cargo(&res.data.cargo);
+                print_cooldown(&res.data.cooldown);
+            })?;
+        }
+
+        ShipCommand::Siphon => {
+            let res = fleet_api::siphon_resources(conf, ship)
+                .await
+                .wrap_err("Error siphoning")?;
+            output.value(&res.data, || {
+                let siphoned = &res.data.siphon.r#yield;
+                println!(
+                    "Siphoned {} {}",
+                    siphoned.units,
+                    siphoned.symbol.to_string()
+                );
+                print_cargo(&res.data.cargo);
+                print_cooldown(&res.data.cooldown);
+            })?;
+        }
+
+        ShipCommand::Refine { produce } => {
+            let req = models::ShipRefineRequest::new(parse("refinable good", produce.as_str())?);
+            let res = fleet_api::ship_refine(conf, ship, Some(req))
+                .await
+                .wrap_err_with(|| format!("Error refining {}", produce))?;
+            output.value(&res.data, || {
+                for good in res.data.consumed.iter() {
+                    println!("Consumed {} {}", good.units, good.trade_symbol);
+                }
+                for good in res.data.produced.iter() {
+                    println!("Produced {} {}", good.units, good.trade_symbol);
+                }
+                print_cargo(&res.data.cargo);
+                print_cooldown(&res.data.cooldown);
+            })?;
+        }
+
+        ShipCommand::Buy { good, units } => {
+            let req = models::PurchaseCargoRequest::new(parse("trade good", good.as_str())?, units);
+            let res = fleet_api::purchase_cargo(conf, ship, Some(req))
+                .await
+                .wrap_err_with(|| format!("Error buying {}", good))?;
+            output.value(&res.data, || {
+                print_transaction(&res.data.transaction);
+                print_cargo(&res.data.cargo);
+                print_credits(&res.data.agent);
+            })?;
+        }
+
+        ShipCommand::Sell { good, units } => {
+            let req = models::SellCargoRequest::new(parse("trade good", good.as_str())?, units);
+            let res = fleet_api::sell_cargo(conf, ship, Some(req))
+                .await
+                .wrap_err_with(|| format!("Error selling {}", good))?;
+            output.value(&res.data, || {
+                print_transaction(&res.data.transaction);
+                print_cargo(&res.data.cargo);
+                print_credits(&res.data.agent);
+            })?;
+        }
+
+        ShipCommand::Jettison { good, units } => {
+            let req = models::JettisonRequest::new(parse("trade good", good.as_str())?, units);
+            let res = fleet_api::jettison(conf, ship, Some(req))
+                .await
+                .wrap_err_with(|| format!("Error jettisoning {}", good))?;
+            output.value(&res.data, || {
+                println!("Jettisoned {} {}", units, good.to_uppercase());
+                print_cargo(&res.data.cargo);
+            })?;
+        }
+
+        ShipCommand::Transfer { good, units, to } => {
+            let req = models::TransferCargoRequest::new(
+                parse("trade good", good.as_str())?,
+                units,
+                to.to_uppercase(),
+            );
+            let res = fleet_api::transfer_cargo(conf, ship, Some(req))
+                .await
+                .wrap_err_with(|| format!("Error transferring {} to {}", good, to))?;
+            output.value(&res.data, || {
+                println!(
+                    "Transferred {} {} to {}",
+                    units,
+                    good.to_uppercase(),
+                    to.to_uppercase()
+                );
+                print_cargo(&res.data.cargo);
+            })?;
+        }
+
+        ShipCommand::InstallMount { mount } => {
+            let req = models::InstallMountRequest::new(mount.to_uppercase());
+            let res = fleet_api::install_mount(conf, ship, Some(req))
+                .await
+                .wrap_err_with(|| format!("Error installing {}", mount))?;
+            output.value(&res.data, || {
+                println!(
+                    "Installed {} for {} credits",
+                    res.data.transaction.trade_symbol, res.data.transaction.total_price
+                );
+                println!("\n{}\n", Table::new(mounts(res.data.mounts.clone())));
+                print_credits(&res.data.agent);
+            })?;
+        }
+
+        ShipCommand::RemoveMount { mount } => {
+            let req = models::RemoveMountRequest::new(mount.to_uppercase());
+            let res = fleet_api::remove_mount(conf, ship, Some(req))
+                .await
+                .wrap_err_with(|| format!("Error removing {}", mount))?;
+            output.value(&res.data, || {
+                println!(
+                    "Removed {} for {} credits",
+                    res.data.transaction.trade_symbol, res.data.transaction.total_price
+                );
+                println!("\n{}\n", Table::new(mounts(res.data.mounts.clone())));
+                print_credits(&res.data.agent);
+            })?;
+        }
+
+        ShipCommand::Scan { target } => match target {
+            ScanTarget::Ships => {
+                let res = fleet_api::create_ship_ship_scan(conf, ship)
+                    .await
+                    .wrap_err("Error scanning ships")?;
+                output.value(&res.data, || {
+                    for scanned in res.data.ships.iter() {
+                        println!(
+                            "{} ({}, {}) at {}",
+                            scanned.symbol,
+                            scanned.registration.role.to_string(),
+                            scanned.registration.faction_symbol,
+                            scanned.nav.waypoint_symbol
+                        );
+                    }
+                    print_cooldown(&res.data.cooldown);
+                })?;
+            }
+
+            ScanTarget::Systems => {
+                let res = fleet_api::create_ship_system_scan(conf, ship)
+                    .await
+                    .wrap_err("Error scanning systems")?;
+                output.value(&res.data, || {
+                    for system in res.data.systems.iter() {
+                        println!("{} at distance {}", system.symbol, system.distance);
+                    }
+                    print_cooldown(&res.data.cooldown);
+                })?;
+            }
+
+            ScanTarget::Waypoints => {
+                let res = fleet_api::create_ship_waypoint_scan(conf, ship)
+                    .await
+                    .wrap_err("Error scanning waypoints")?;
+                output.value(&res.data, || {
+                    for waypoint in res.data.waypoints.iter() {
+                        let traits = waypoint
+                            .traits
+                            .iter()
+                            .map(|t| t.symbol.to_string())
+                            .collect::<Vec<String>>();
+                        println!(
+                            "{} ({}): {}",
+                            waypoint.symbol,
+                            waypoint.r#type.to_string(),
+                            traits.join(", ")
+                        );
+                    }
+                    print_cooldown(&res.data.cooldown);
+                })?;
+            }
+        },
+
+        ShipCommand::Chart => {
+            let res = fleet_api::create_chart(conf, ship)
+                .await
+                .wrap_err("Error charting")?;
+            output.value(&res.data, || {
+                println!(
+                    "Charted {} ({})",
+                    res.data.waypoint.symbol,
+                    res.data.waypoint.r#type.to_string()
+                );
+            })?;
+        }
+
+        ShipCommand::Negotiate => {
+            let res = fleet_api::negotiate_contract(conf, ship)
+                .await
+                .wrap_err("Error negotiating contract")?;
+            let contract = Contract::from(res.data.contract);
+            output.one(&contract)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn print_nav(ship: &str, nav: &models::ShipNav) {
+    let nav = ShipNav::from(Box::new(nav.clone()));
+
+    match (&nav.status, nav.route.time_to_arrival) {
+        (ShipNavStatus::InTransit, Some(remaining)) => println!(
+            "{} is flying to {} in {} mode, arriving in {}s",
+            ship,
+            nav.route.destination.location,
+            nav.flight_mode,
+            remaining.whole_seconds()
+        ),
+        _ => println!(
+            "{} is {} at {} in {} mode",
+            ship, nav.status, nav.location, nav.flight_mode
+        ),
+    }
+}
+
+fn print_fuel(fuel: &models::ShipFuel) {
+    println!("Fuel: {}/{}", fuel.current, fuel.capacity);
+}
+
+fn print_cargo(cargo: &models::ShipCargo) {
+    let cargo = ShipCargo::from(Box::new(cargo.clone()));
+
+    println!("Cargo: {}/{}", cargo.current, cargo.capacity);
+    for item in cargo.inventory.iter() {
+        println!("  {} {}", item.units, item.symbol);
+    }
+}
+
+fn print_cooldown(cooldown: &models::Cooldown) {
+    let cooldown = ShipCooldown::from(Box::new(cooldown.clone()));
+    println!("Cooldown: {}s", cooldown.remaining().as_secs());
+}
+
+fn mounts(mounts: Vec<models::ShipMount>) -> Vec<ShipMount> {
+    mounts.into_iter().map(ShipMount::from).collect()
+}
+
+fn print_transaction(transaction: &models::MarketTransaction) {
+    let transaction = MarketTransaction::from(Box::new(transaction.clone()));
+    let verb = match transaction.transaction_type.as_str() {
+        "SELL" => "Sold",
+        _ => "Bought",
+    };
+
+    println!(
+        "{} {} {} at {} for {} credits ({} each)",
+        verb,
+        transaction.units,
+        transaction.trade_symbol,
+        transaction.waypoint,
+        transaction.total_price,
+        transaction.price_per_unit
+    );
+}
+
+fn print_credits(agent: &models::Agent) {
+    println!("Credits: {}", agent.credits);
+}
+
+/// Parses an API enum from its name, ignoring case.
+fn parse<T: DeserializeOwned>(kind: &str, value: &str) -> Result<T> {
+    serde_json::from_value(serde_json::Value::String(value.to_uppercase()))
+        .wrap_err_with(|| format!("Unknown {} {}", kind, value))
+}
+
+/// The name an API enum is serialized as.
+fn name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        _ => String::new(),
+    }
+}
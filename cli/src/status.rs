@@ -0,0 +1,114 @@
+use eyre::Result;
+use openapi::apis::configuration::Configuration;
+use openapi::apis::default_api;
+use openapi::models::GetStatus200Response;
+use sqlx::SqlitePool;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::output::Output;
+use crate::profile::Profile;
+
+/// Prints the server status. The profile's agent is marked on the
+/// leaderboards.
+pub async fn show(conf: &Configuration, output: Output, agent: Option<&str>) -> Result<()> {
+    let res = default_api::get_status(conf).await?;
+    output.value(&res, || describe(&res, agent))
+}
+
+/// Prints the agent's rank on both leaderboards in every snapshot the
+/// commander took.
+pub async fn history(profile: &Profile, output: Output) -> Result<()> {
+    let agent = profile
+        .agent
+        .as_deref()
+        .ok_or_else(|| eyre::eyre!("The profile has no agent"))?;
+
+    let pool = SqlitePool::connect(profile.endpoints().database_url.as_str()).await?;
+    let ranks = commander::status::rank_history(&pool, agent).await?;
+    pool.close().await;
+
+    output.rows(&ranks)
+}
+
+fn describe(res: &GetStatus200Response, agent: Option<&str>) {
+    println!("{} {}", res.status, res.version);
+    println!("{}", res.description);
+    println!();
+
+    let countdown = OffsetDateTime::parse(&res.server_resets.next, &Rfc3339)
+        .map(|next| countdown(next - OffsetDateTime::now_utc()))
+        .unwrap_or_default();
+    println!(
+        "Last reset {}, next reset at {}{} ({})",
+        res.reset_date, res.server_resets.next, countdown, res.server_resets.frequency
+    );
+    println!(
+        "{} agents, {} ships, {} systems, {} waypoints",
+        res.stats.agents, res.stats.ships, res.stats.systems, res.stats.waypoints
+    );
+
+    let credits = res
+        .leaderboards
+        .most_credits
+        .iter()
+        .map(|e| (e.agent_symbol.as_str(), e.credits))
+        .collect::<Vec<(&str, i64)>>();
+    let charts = res
+        .leaderboards
+        .most_submitted_charts
+        .iter()
+        .map(|e| (e.agent_symbol.as_str(), e.chart_count as i64))
+        .collect::<Vec<(&str, i64)>>();
+
+    print_leaderboard("Most credits", &credits, agent);
+    print_leaderboard("Most submitted charts", &charts, agent);
+
+    for announcement in res.announcements.iter() {
+        println!("\n{}\n{}", announcement.title, announcement.body);
+    }
+
+    if !res.links.is_empty() {
+        println!();
+        for link in res.links.iter() {
+            println!("{}: {}", link.name, link.url);
+        }
+    }
+}
+
+fn print_leaderboard(title: &str, entries: &[(&str, i64)], agent: Option<&str>) {
+    println!("\n{}", title);
+
+    let width = entries.iter().map(|(s, _)| s.len()).max().unwrap_or(0);
+    for (index, (symbol, value)) in entries.iter().enumerate() {
+        let marker = if Some(*symbol) == agent { "*" } else { " " };
+        println!(
+            "{}{:>3}. {:<width$} {:>15}",
+            marker,
+            index + 1,
+            symbol,
+            value,
+            width = width
+        );
+    }
+
+    if let Some(agent) = agent {
+        if !entries.iter().any(|(s, _)| *s == agent) {
+            println!("  {} is not on the board", agent);
+        }
+    }
+}
+
+/// " in 3d 04h 12m", or nothing once the time passed.
+fn countdown(left: time::Duration) -> String {
+    if left.is_negative() {
+        return String::new();
+    }
+
+    format!(
+        " in {}d {:02}h {:02}m",
+        left.whole_days(),
+        left.whole_hours() % 24,
+        left.whole_minutes() % 60
+    )
+}
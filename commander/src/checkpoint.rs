@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use common::crds::ShipBehaviour;
+use eyre::Result;
+use sqlx::SqlitePool;
+
+/// The last known state of a ship's behaviour. Machines re-plan from the
+/// game state when they start, so this is only used to skip behaviours that
+/// already finished and to see what every ship was doing, including the
+/// error that stopped it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub ship_symbol: String,
+    pub behaviour: ShipBehaviour,
+    pub state: String,
+    pub finished: bool,
+    pub error: Option<String>,
+}
+
+pub async fn load(pool: &SqlitePool) -> Result<HashMap<String, Checkpoint>> {
+    let rows = sqlx::query!(
+        "SELECT ship_symbol, behaviour, params, state, finished, error FROM ship_checkpoints"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut checkpoints = HashMap::new();
+    for row in rows {
+        let checkpoint = Checkpoint {
+            ship_symbol: row.ship_symbol.clone(),
+            behaviour: ShipBehaviour {
+                name: row.behaviour,
+                params: serde_json::from_str(row.params.as_str())?,
+            },
+            state: row.state,
+            finished: row.finished,
+            error: row.error,
+        };
+        checkpoints.insert(row.ship_symbol, checkpoint);
+    }
+
+    Ok(checkpoints)
+}
+
+pub async fn save(pool: &SqlitePool, checkpoint: &Checkpoint) -> Result<()> {
+    let params = serde_json::to_string(&checkpoint.behaviour.params)?;
+
+    sqlx::query!(
+        "INSERT INTO ship_checkpoints (ship_symbol, behaviour, params, state, finished, error, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, CURRENT_TIMESTAMP)
+         ON CONFLICT (ship_symbol) DO UPDATE SET
+            behaviour = excluded.behaviour,
+            params = excluded.params,
+            state = excluded.state,
+            finished = excluded.finished,
+            error = excluded.error,
+            updated_at = excluded.updated_at",
+        checkpoint.ship_symbol,
+        checkpoint.behaviour.name,
+        params,
+        checkpoint.state,
+        checkpoint.finished,
+        checkpoint.error,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Keeps the error that stopped the ship's behaviour next to its last state.
+pub async fn record_error(pool: &SqlitePool, ship_symbol: &str, error: &str) -> Result<()> {
+    sqlx::query!(
+        "UPDATE ship_checkpoints SET error = ?2, updated_at = CURRENT_TIMESTAMP
+         WHERE ship_symbol = ?1",
+        ship_symbol,
+        error
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
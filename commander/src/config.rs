@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use agent::Registration;
+use common::crds::ShipBehaviour;
+use common::models::Ship;
+use eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+const CONFIG_FILE: &str = "commander.toml";
+
+/// How the commander runs the fleet, read from `commander.toml` next to the
+/// database. Every field is optional.
+///
+/// ```toml
+/// max_parallel = 4
+///
+/// [agent]
+/// symbol = "NATINGAR3"
+/// faction = "COSMIC"
+///
+/// [roles.Excavator]
+/// name = "siphon"
+///
+/// [ships.AGENT-3]
+/// name = "refine"
+/// params = { station = "X1-AB12-C3" }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommanderConfig {
+    /// Agent to run, registered on the first start when it isn't stored yet.
+    pub agent: Option<Registration>,
+    /// Behaviour steps that may run at the same time across the fleet.
+    pub max_parallel: usize,
+    /// Seconds between two looks at the fleet for new ships.
+    pub fleet_poll_interval: u64,
+    /// Seconds given to the ships to stop on shutdown before they are
+    /// aborted.
+    pub shutdown_grace_period: u64,
+    /// Seconds between two snapshots of the server status and leaderboards.
+    pub status_snapshot_interval: u64,
+    /// Behaviour for every ship with the role, keyed by role.
+    pub roles: BTreeMap<String, ShipBehaviour>,
+    /// Behaviour for single ships, keyed by symbol. Takes precedence over
+    /// the role.
+    pub ships: BTreeMap<String, ShipBehaviour>,
+}
+
+impl Default for CommanderConfig {
+    fn default() -> Self {
+        Self {
+            agent: None,
+            max_parallel: 4,
+            fleet_poll_interval: 300,
+            shutdown_grace_period: 30,
+            status_snapshot_interval: 900,
+            roles: BTreeMap::from([
+                ("Satellite".to_string(), behaviour("probe")),
+                ("Excavator".to_string(), behaviour("siphon")),
+            ]),
+            ships: BTreeMap::new(),
+        }
+    }
+}
+
+fn behaviour(name: &str) -> ShipBehaviour {
+    ShipBehaviour {
+        name: name.to_string(),
+        params: BTreeMap::new(),
+    }
+}
+
+impl CommanderConfig {
+    /// Reads the config file, falling back to the defaults without one.
+    pub fn load() -> Result<Self> {
+        let file = Path::new(CONFIG_FILE);
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+
+        let conf = std::fs::read_to_string(file)?;
+        toml::from_str(&conf).wrap_err_with(|| format!("Error reading {}", CONFIG_FILE))
+    }
+
+    pub fn fleet_poll_interval(&self) -> Duration {
+        Duration::from_secs(self.fleet_poll_interval)
+    }
+
+    pub fn shutdown_grace_period(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_period)
+    }
+
+    pub fn status_snapshot_interval(&self) -> Duration {
+        Duration::from_secs(self.status_snapshot_interval)
+    }
+
+    /// The behaviour for each ship that has one. Probes assigned by role
+    /// split their system between them unless an index is configured.
+    pub fn assign(&self, ships: &[Ship]) -> BTreeMap<String, ShipBehaviour> {
+        let mut assignments = BTreeMap::new();
+
+        for ship in ships {
+            let role = serde_json::to_value(&ship.registration.role)
+                .ok()
+                .and_then(|v| v.as_str().map(|s| s.to_string()))
+                .unwrap_or_default();
+
+            let behaviour = match self.ships.get(&ship.symbol) {
+                Some(behaviour) => behaviour.clone(),
+                None => match self.roles.get(&role) {
+                    Some(behaviour) => behaviour.clone(),
+                    None => continue,
+                },
+            };
+
+            assignments.insert(ship.symbol.clone(), behaviour);
+        }
+
+        let probes = assignments
+            .iter()
+            .filter(|(_, b)| b.name == "probe" && !b.params.contains_key("index"))
+            .map(|(symbol, _)| symbol.clone())
+            .collect::<Vec<String>>();
+
+        for (index, symbol) in probes.iter().enumerate() {
+            if let Some(behaviour) = assignments.get_mut(symbol) {
+                behaviour
+                    .params
+                    .insert("index".to_string(), index.to_string());
+                behaviour
+                    .params
+                    .insert("count".to_string(), probes.len().to_string());
+            }
+        }
+
+        assignments
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use common::crds::ShipBehaviour;
+use common::machines::{actions, BehaviourMachine};
+use eyre::Result;
+use openapi::apis::configuration::Configuration;
+use sea_orm::DatabaseConnection;
+use sqlx::SqlitePool;
+use tokio::sync::{watch, Semaphore, SemaphorePermit};
+use tokio::task::JoinHandle;
+
+use crate::checkpoint::{self, Checkpoint};
+use crate::config::CommanderConfig;
+
+struct RunningShip {
+    behaviour: ShipBehaviour,
+    handle: JoinHandle<Result<bool>>,
+}
+
+/// Drives a behaviour machine for every ship that has one. Ships run
+/// concurrently, but only `max_parallel` of them take a step at any time so
+/// a large fleet doesn't queue up far more requests than the rate limit
+/// lets through.
+pub struct Fleet {
+    config: CommanderConfig,
+    api_config: Configuration,
+    pool: SqlitePool,
+    db: Arc<DatabaseConnection>,
+    permits: Arc<Semaphore>,
+    shutdown: watch::Sender<bool>,
+    running: HashMap<String, RunningShip>,
+    /// Behaviours that completed, by ship. They aren't started again.
+    finished: HashMap<String, ShipBehaviour>,
+}
+
+impl Fleet {
+    pub async fn new(
+        config: CommanderConfig,
+        api_config: Configuration,
+        pool: SqlitePool,
+        db: Arc<DatabaseConnection>,
+    ) -> Result<Self> {
+        let finished = checkpoint::load(&pool)
+            .await?
+            .into_values()
+            .filter(|c| c.finished)
+            .map(|c| (c.ship_symbol, c.behaviour))
+            .collect();
+
+        let permits = Arc::new(Semaphore::new(config.max_parallel.max(1)));
+        let (shutdown, _) = watch::channel(false);
+
+        Ok(Self {
+            config,
+            api_config,
+            pool,
+            db,
+            permits,
+            shutdown,
+            running: HashMap::new(),
+            finished,
+        })
+    }
+
+    /// Lists the fleet and starts the behaviour of every ship that isn't
+    /// running one yet, which picks up newly bought ships. Ships whose
+    /// behaviour failed are started again.
+    pub async fn sync(&mut self) -> Result<()> {
+        self.reap().await;
+
+        let ships = actions::get_all_ships(&self.api_config).await?;
+        let assignments = self.config.assign(&ships);
+
+        for (symbol, behaviour) in assignments {
+            if self.running.contains_key(&symbol) || self.finished.get(&symbol) == Some(&behaviour)
+            {
+                continue;
+            }
+
+            self.start(symbol, behaviour);
+        }
+
+        tracing::info!(
+            "{} of {} ships running a behaviour",
+            self.running.len(),
+            ships.len()
+        );
+        Ok(())
+    }
+
+    fn start(&mut self, symbol: String, behaviour: ShipBehaviour) {
+        tracing::info!("starting {} behaviour for {}", behaviour.name, symbol);
+
+        let task = drive(
+            symbol.clone(),
+            behaviour.clone(),
+            self.api_config.clone(),
+            self.db.clone(),
+            self.pool.clone(),
+            self.permits.clone(),
+            self.shutdown.subscribe(),
+        );
+
+        let ship = symbol.clone();
+        let pool = self.pool.clone();
+        let handle = tokio::spawn(async move {
+            let result = task.await;
+            if let Err(e) = &result {
+                tracing::warn!("behaviour of {} stopped: {:?}", ship, e);
+                if let Err(e) = checkpoint::record_error(&pool, &ship, &format!("{:#}", e)).await {
+                    tracing::warn!("error recording error of {}: {:?}", ship, e);
+                }
+            }
+            result
+        });
+
+        self.running
+            .insert(symbol, RunningShip { behaviour, handle });
+    }
+
+    /// Forgets ships whose task ended, remembering the ones that finished.
+    async fn reap(&mut self) {
+        let ended = self
+            .running
+            .iter()
+            .filter(|(_, r)| r.handle.is_finished())
+            .map(|(symbol, _)| symbol.clone())
+            .collect::<Vec<String>>();
+
+        for symbol in ended {
+            let Some(ship) = self.running.remove(&symbol) else {
+                continue;
+            };
+
+            if let Ok(Ok(true)) = ship.handle.await {
+                tracing::info!("{} finished its {} behaviour", symbol, ship.behaviour.name);
+                self.finished.insert(symbol, ship.behaviour);
+            }
+        }
+    }
+
+    /// Switches to another agent after a server reset. Everything running
+    /// for the old agent is stopped first.
+    pub async fn restart(&mut self, api_config: Configuration) {
+        self.stop().await;
+        self.api_config = api_config;
+        self.finished.clear();
+        let (shutdown, _) = watch::channel(false);
+        self.shutdown = shutdown;
+    }
+
+    /// Asks every ship to stop, which interrupts the step it is in, and
+    /// waits for that up to the grace period, then aborts the rest. Each ship
+    /// checkpoints before every step and takes it again from there.
+    pub async fn stop(&mut self) {
+        self.shutdown.send_replace(true);
+
+        let grace = self.config.shutdown_grace_period();
+        let running = self.running.drain().collect::<Vec<(String, RunningShip)>>();
+        let count = running.len();
+
+        let deadline = tokio::time::Instant::now() + grace;
+        for (symbol, ship) in running {
+            let abort = ship.handle.abort_handle();
+            if tokio::time::timeout_at(deadline, ship.handle)
+                .await
+                .is_err()
+            {
+                tracing::warn!("{} did not stop in time, aborting", symbol);
+                abort.abort();
+            }
+        }
+
+        tracing::info!("stopped {} ships", count);
+    }
+
+    pub fn poll_interval(&self) -> std::time::Duration {
+        self.config.fleet_poll_interval()
+    }
+}
+
+/// Runs the behaviour until it finishes or shutdown is requested. Returns
+/// whether the behaviour finished.
+async fn drive(
+    symbol: String,
+    behaviour: ShipBehaviour,
+    api_config: Configuration,
+    db: Arc<DatabaseConnection>,
+    pool: SqlitePool,
+    permits: Arc<Semaphore>,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<bool> {
+    let mut checkpoint = Checkpoint {
+        ship_symbol: symbol.clone(),
+        behaviour: behaviour.clone(),
+        state: "Starting".to_string(),
+        finished: false,
+        error: None,
+    };
+
+    let mut machine = {
+        let Some(_permit) = acquire(&permits, &mut shutdown).await else {
+            return Ok(false);
+        };
+        BehaviourMachine::new(&behaviour, api_config, db.as_ref(), symbol.as_str()).await?
+    };
+
+    loop {
+        checkpoint.state = machine.state().to_string();
+        checkpoint::save(&pool, &checkpoint).await?;
+
+        let Some(permit) = acquire(&permits, &mut shutdown).await else {
+            tracing::info!("{} stopped in state {}", symbol, checkpoint.state);
+            return Ok(false);
+        };
+
+        // Steps wait for arrivals and cooldowns, shutdown doesn't wait for
+        // them. The step is taken again from the checkpoint.
+        let step = tokio::select! {
+            step = machine.step() => step?,
+            _ = shutdown.wait_for(|stop| *stop) => {
+                tracing::info!("{} stopped in state {}", symbol, checkpoint.state);
+                return Ok(false);
+            }
+        };
+        drop(permit);
+
+        match step {
+            Some(next) => machine = next,
+            None => {
+                checkpoint.finished = true;
+                checkpoint::save(&pool, &checkpoint).await?;
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Waits for a free slot, or returns `None` once shutdown was requested.
+async fn acquire<'a>(
+    permits: &'a Semaphore,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Option<SemaphorePermit<'a>> {
+    if *shutdown.borrow() {
+        return None;
+    }
+
+    tokio::select! {
+        permit = permits.acquire() => permit.ok(),
+        _ = shutdown.changed() => None,
+    }
+}
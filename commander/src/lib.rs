@@ -1,31 +1,199 @@
-use std::{str::FromStr, time::Duration};
+use std::sync::Arc;
+use std::time::Duration;
 
-use agent::Agent;
+use agent::{Agent, Registration};
 use eyre::Result;
-use navigation::{location::Location, waypoint::Waypoint, waypoint_type::WaypointType};
+use openapi::apis::agents_api;
+use openapi::apis::configuration::Configuration;
 use sqlx::SqlitePool;
 use tokio::time;
 
+pub mod checkpoint;
+mod config;
+mod fleet;
+mod reset;
+pub mod status;
+
+use crate::config::CommanderConfig;
+use crate::fleet::Fleet;
+
 const DATABASE_URL: &str = "sqlite://spacetraders-db.sqlite?mode=rwc";
 
-pub async fn run() -> Result<()> {
-    let pool = SqlitePool::connect(DATABASE_URL).await?;
-    let agent = Agent::fetch_or_create(&pool).await?;
+/// Where the commander reads and writes: the API, without a token, and the
+/// database.
+#[derive(Clone, Debug)]
+pub struct Endpoints {
+    pub api: Configuration,
+    pub database_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            api: Configuration::new(),
+            database_url: DATABASE_URL.to_string(),
+        }
+    }
+}
+
+/// How often the server is asked whether a reset happened.
+const RESET_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Loads the agent and keeps every ship busy with the behaviour its role or
+/// the config gives it until SIGINT or SIGTERM. The agent is picked by
+/// symbol, falling back to the one in the config.
+pub async fn run(endpoints: &Endpoints, symbol: Option<String>) -> Result<()> {
+    let pool = SqlitePool::connect(endpoints.database_url.as_str()).await?;
+    if let Some(reset) = reset::pending_reset(&pool, &endpoints.api).await? {
+        reset::start_over(&pool, &endpoints.api, &reset).await?;
+    }
+
+    let config = CommanderConfig::load()?;
+    let mut agent = select_agent(&pool, &endpoints.api, symbol, &config).await?;
     tracing::info!("agent: {:#?}", agent);
 
-    let waypoint = Waypoint {
-        location: Location::from_str("X1-C46-A1")?,
-        waypoint_type: WaypointType::Moon,
-        traits: vec![],
-        x: 0i32,
-        y: 32i32,
-    };
+    let db = Arc::new(common::repository::connect_to(endpoints.database_url.as_str()).await?);
+    let mut status_interval = time::interval(config.status_snapshot_interval());
+    let mut fleet = Fleet::new(config, api_config(endpoints, &agent), pool.clone(), db).await?;
 
-    waypoint.save(&pool).await;
+    let mut fleet_interval = time::interval(fleet.poll_interval());
+    let mut reset_interval = time::interval(RESET_CHECK_INTERVAL);
+    reset_interval.reset();
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
 
-    let mut interval = time::interval(Duration::from_secs(10));
     loop {
-        interval.tick().await;
-        println!("Tick");
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = fleet_interval.tick() => {
+                if let Err(e) = fleet.sync().await {
+                    tracing::warn!("error syncing fleet: {:?}", e);
+                }
+                if let Err(e) = record_credits(&pool, &api_config(endpoints, &agent), &agent).await {
+                    tracing::warn!("error recording credits: {:?}", e);
+                }
+            }
+            _ = status_interval.tick() => {
+                if let Err(e) = status::take_snapshot(&pool, &endpoints.api).await {
+                    tracing::warn!("error recording server status: {:?}", e);
+                }
+            }
+            _ = reset_interval.tick() => {
+                let reset = match reset::pending_reset(&pool, &endpoints.api).await {
+                    Ok(reset) => reset,
+                    Err(e) => {
+                        tracing::warn!("error checking for a server reset: {:?}", e);
+                        continue;
+                    }
+                };
+                let Some(reset) = reset else {
+                    continue;
+                };
+
+                // The old agent's ships would keep writing into the tables
+                // being cleared.
+                fleet.stop().await;
+                match start_over(&pool, endpoints, &reset, &agent).await {
+                    Ok(new_agent) => {
+                        agent = new_agent;
+                        tracing::info!("agent: {:#?}", agent);
+                        fleet.restart(api_config(endpoints, &agent)).await;
+                        fleet_interval.reset_immediately();
+                    }
+                    Err(e) => tracing::warn!("error starting over after a server reset: {:?}", e),
+                }
+            }
+        }
     }
+
+    tracing::info!("shutting down");
+    fleet.stop().await;
+    pool.close().await;
+
+    Ok(())
+}
+
+/// Starts the database over after a server reset and returns the agent
+/// registered again in place of the given one.
+async fn start_over(
+    pool: &SqlitePool,
+    endpoints: &Endpoints,
+    reset: &reset::Reset,
+    agent: &Agent,
+) -> Result<Agent> {
+    reset::start_over(pool, &endpoints.api, reset).await?;
+
+    Agent::fetch(pool, agent.symbol.as_str())
+        .await?
+        .ok_or_else(|| eyre::eyre!("{} was not registered again", agent.symbol))
+}
+
+/// Registers a new agent and stores it, so it can be run by symbol.
+pub async fn register(endpoints: &Endpoints, registration: &Registration) -> Result<Agent> {
+    let pool = SqlitePool::connect(endpoints.database_url.as_str()).await?;
+    let agent = Agent::register(&pool, &endpoints.api, registration).await?;
+    pool.close().await;
+
+    Ok(agent)
+}
+
+async fn select_agent(
+    pool: &SqlitePool,
+    conf: &Configuration,
+    symbol: Option<String>,
+    config: &CommanderConfig,
+) -> Result<Agent> {
+    match (symbol, &config.agent) {
+        (Some(symbol), Some(registration)) if symbol == registration.symbol => {
+            Ok(Agent::fetch_or_register(pool, conf, registration).await?)
+        }
+
+        (Some(symbol), _) => Agent::fetch(pool, symbol.as_str())
+            .await?
+            .ok_or_else(|| eyre::eyre!("No agent {} stored, register it first", symbol)),
+
+        (None, Some(registration)) => Ok(Agent::fetch_or_register(pool, conf, registration).await?),
+
+        (None, None) => {
+            let mut agents = Agent::list(pool).await?;
+            match agents.len() {
+                1 => Ok(agents.remove(0)),
+                0 => Err(eyre::eyre!(
+                    "No agent stored, register one or configure it in commander.toml"
+                )),
+                _ => Err(eyre::eyre!(
+                    "{} agents stored, pick one by symbol",
+                    agents.len()
+                )),
+            }
+        }
+    }
+}
+
+/// Stores the agent's credits, so they can be charted over time.
+async fn record_credits(pool: &SqlitePool, conf: &Configuration, agent: &Agent) -> Result<()> {
+    let res = agents_api::get_my_agent(conf).await?;
+    agent.record_credits(pool, res.data.credits).await?;
+
+    Ok(())
+}
+
+fn api_config(endpoints: &Endpoints, agent: &Agent) -> Configuration {
+    Configuration {
+        bearer_access_token: Some(agent.token.clone()),
+        ..endpoints.api.clone()
+    }
+}
+
+/// Resolves on the first SIGINT or SIGTERM.
+async fn shutdown_signal() -> Result<()> {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => res?,
+        _ = terminate.recv() => {}
+    }
+
+    Ok(())
 }
@@ -0,0 +1,174 @@
+use std::path::Path;
+
+use agent::Agent;
+use eyre::{Context, Result};
+use navigation::location::Location;
+use openapi::apis::configuration::Configuration;
+use openapi::apis::{agents_api, default_api};
+use sqlx::{Row, SqlitePool};
+
+/// Tables that survive a server reset. Status snapshots carry the reset
+/// they belong to, so ranks of earlier resets stay comparable. Agents are
+/// kept until they are registered again, which replaces their tokens.
+const KEPT_TABLES: [&str; 5] = [
+    "_sqlx_migrations",
+    "server_resets",
+    "status_snapshots",
+    "leaderboard_entries",
+    "agents",
+];
+
+/// A server reset the database wasn't started over for yet.
+pub struct Reset {
+    reset_date: String,
+    next_reset: String,
+    /// The reset the database still holds the data of.
+    last_reset: String,
+}
+
+/// Compares the reset date reported by the server with the last one seen
+/// and returns the reset when they differ. The first check only records the
+/// current reset.
+pub async fn pending_reset(pool: &SqlitePool, conf: &Configuration) -> Result<Option<Reset>> {
+    let res = default_api::get_status(conf)
+        .await
+        .wrap_err("Error fetching server status")?;
+
+    let reset_date = res.reset_date;
+    let next_reset = res.server_resets.next;
+
+    let last_reset =
+        sqlx::query!("SELECT reset_date FROM server_resets ORDER BY detected_at DESC LIMIT 1")
+            .fetch_optional(pool)
+            .await?
+            .map(|r| r.reset_date);
+
+    match last_reset {
+        Some(last_reset) if last_reset == reset_date => Ok(None),
+
+        None => {
+            record_reset(pool, &reset_date, &next_reset, None).await?;
+            Ok(None)
+        }
+
+        Some(last_reset) => Ok(Some(Reset {
+            reset_date,
+            next_reset,
+            last_reset,
+        })),
+    }
+}
+
+/// Archives and clears the database, registers every stored agent again
+/// with the same symbol, faction and email, and maps their headquarters
+/// systems again. Nothing may write to the database meanwhile, so the
+/// fleet has to be stopped first.
+///
+/// The reset is only recorded once every agent is registered again, so a
+/// failure part way is retried on the next check. Agents that were already
+/// registered again by an earlier attempt keep their new token.
+pub async fn start_over(pool: &SqlitePool, conf: &Configuration, reset: &Reset) -> Result<()> {
+    tracing::info!("server reset on {} detected", reset.reset_date);
+
+    let agents = Agent::list(pool).await?;
+    let archive = archive_database(pool, &reset.last_reset).await?;
+    clear_database(pool).await?;
+
+    for agent in agents {
+        let agent = match registered_since_reset(conf, &agent).await {
+            true => agent,
+            false => {
+                let registration = agent
+                    .registration()
+                    .ok_or_else(|| eyre::eyre!("Unknown faction {}", agent.starting_faction))?;
+                Agent::register(pool, conf, &registration).await?
+            }
+        };
+
+        let config = Configuration {
+            bearer_access_token: Some(agent.token.clone()),
+            ..conf.clone()
+        };
+
+        let system = Location::parse(agent.headquarters.clone()).system_ident();
+        let count =
+            navigation::waypoint::initialize_system_waypoints(&config, pool, system).await?;
+        tracing::info!("{} re-registered, {} waypoints mapped", agent.symbol, count);
+    }
+
+    record_reset(pool, &reset.reset_date, &reset.next_reset, Some(archive)).await?;
+
+    Ok(())
+}
+
+/// Tokens of the previous reset are rejected, so an agent whose stored
+/// token works was already registered again.
+async fn registered_since_reset(conf: &Configuration, agent: &Agent) -> bool {
+    let config = Configuration {
+        bearer_access_token: Some(agent.token.clone()),
+        ..conf.clone()
+    };
+
+    agents_api::get_my_agent(&config).await.is_ok()
+}
+
+async fn record_reset(
+    pool: &SqlitePool,
+    reset_date: &str,
+    next_reset: &str,
+    archive: Option<String>,
+) -> Result<()> {
+    sqlx::query!(
+        "INSERT OR IGNORE INTO server_resets (reset_date, next_reset, archive, detected_at)
+         VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)",
+        reset_date,
+        next_reset,
+        archive
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+fn archive_name(reset_date: &str) -> String {
+    format!("spacetraders-db-{}.sqlite", reset_date)
+}
+
+/// Copies the whole database next to the live one, named after the reset
+/// date it belongs to. An archive left by an earlier attempt is kept, it
+/// was taken before anything was cleared.
+async fn archive_database(pool: &SqlitePool, reset_date: &str) -> Result<String> {
+    let archive = archive_name(reset_date);
+    if Path::new(&archive).exists() {
+        tracing::info!("database already archived to {}", archive);
+        return Ok(archive);
+    }
+
+    sqlx::query("VACUUM INTO ?1")
+        .bind(&archive)
+        .execute(pool)
+        .await
+        .wrap_err_with(|| format!("Error archiving database to {}", archive))?;
+
+    tracing::info!("database archived to {}", archive);
+    Ok(archive)
+}
+
+async fn clear_database(pool: &SqlitePool) -> Result<()> {
+    let tables = sqlx::query("SELECT name FROM sqlite_master WHERE type = 'table'")
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|r| r.get::<String, _>("name"))
+        .filter(|t| !KEPT_TABLES.contains(&t.as_str()) && !t.starts_with("sqlite_"))
+        .collect::<Vec<String>>();
+
+    for table in tables {
+        sqlx::query(format!("DELETE FROM {}", table).as_str())
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use std::fmt::Display;
+
+use chrono::NaiveDateTime;
+use eyre::{Context, Result};
+use openapi::apis::configuration::Configuration;
+use openapi::apis::default_api;
+use openapi::models::GetStatus200Response;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tabled::Tabled;
+
+const MOST_CREDITS: &str = "mostCredits";
+const MOST_SUBMITTED_CHARTS: &str = "mostSubmittedCharts";
+
+/// An agent's place on both leaderboards when a status snapshot was taken.
+/// Ranks are empty while the agent isn't on a board.
+#[derive(Clone, Debug, PartialEq, Serialize, Tabled)]
+pub struct RankSnapshot {
+    pub recorded_at: NaiveDateTime,
+    pub reset_date: String,
+    #[tabled(display_with = "display_option")]
+    pub credits_rank: Option<i64>,
+    #[tabled(display_with = "display_option")]
+    pub credits: Option<i64>,
+    #[tabled(display_with = "display_option")]
+    pub charts_rank: Option<i64>,
+    #[tabled(display_with = "display_option")]
+    pub charts: Option<i64>,
+}
+
+fn display_option<T: Display>(o: &Option<T>) -> String {
+    match o {
+        Some(t) => t.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+/// Fetches the server status and stores it with both leaderboards.
+pub async fn take_snapshot(pool: &SqlitePool, conf: &Configuration) -> Result<()> {
+    let status = default_api::get_status(conf)
+        .await
+        .wrap_err("Error fetching server status")?;
+
+    record_snapshot(pool, &status).await
+}
+
+pub async fn record_snapshot(pool: &SqlitePool, status: &GetStatus200Response) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let snapshot_id = sqlx::query!(
+        "INSERT INTO status_snapshots
+            (reset_date, version, agents, ships, systems, waypoints, recorded_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, CURRENT_TIMESTAMP)",
+        status.reset_date,
+        status.version,
+        status.stats.agents,
+        status.stats.ships,
+        status.stats.systems,
+        status.stats.waypoints
+    )
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+
+    let boards = [
+        (
+            MOST_CREDITS,
+            status
+                .leaderboards
+                .most_credits
+                .iter()
+                .map(|e| (e.agent_symbol.as_str(), e.credits))
+                .collect::<Vec<(&str, i64)>>(),
+        ),
+        (
+            MOST_SUBMITTED_CHARTS,
+            status
+                .leaderboards
+                .most_submitted_charts
+                .iter()
+                .map(|e| (e.agent_symbol.as_str(), e.chart_count as i64))
+                .collect::<Vec<(&str, i64)>>(),
+        ),
+    ];
+
+    for (board, entries) in boards {
+        for (index, (agent_symbol, value)) in entries.into_iter().enumerate() {
+            let rank = index as i64 + 1;
+            sqlx::query!(
+                "INSERT INTO leaderboard_entries (snapshot_id, board, rank, agent_symbol, value)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                snapshot_id,
+                board,
+                rank,
+                agent_symbol,
+                value
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// The agent's rank on both leaderboards in every stored snapshot, oldest
+/// first.
+pub async fn rank_history(pool: &SqlitePool, agent_symbol: &str) -> Result<Vec<RankSnapshot>> {
+    let rows = sqlx::query_as!(
+        RankSnapshot,
+        r#"SELECT
+            s.recorded_at AS "recorded_at!: NaiveDateTime",
+            s.reset_date,
+            c.rank AS "credits_rank?: i64",
+            c.value AS "credits?: i64",
+            m.rank AS "charts_rank?: i64",
+            m.value AS "charts?: i64"
+         FROM status_snapshots s
+         LEFT JOIN leaderboard_entries c
+            ON c.snapshot_id = s.id AND c.board = ?2 AND c.agent_symbol = ?1
+         LEFT JOIN leaderboard_entries m
+            ON m.snapshot_id = s.id AND m.board = ?3 AND m.agent_symbol = ?1
+         ORDER BY s.recorded_at, s.id"#,
+        agent_symbol,
+        MOST_CREDITS,
+        MOST_SUBMITTED_CHARTS
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use k8s_openapi::chrono::{DateTime, Utc};
 use kube::CustomResource;
 use schemars::JsonSchema;
@@ -10,47 +12,139 @@ use crate::models::{self, faction::FactionSymbol};
     kind = "Manager",
     group = "spacetraders.io",
     version = "v1",
-    namespaced
+    namespaced,
+    printcolumn = r#"{"name":"Symbol", "type":"string", "jsonPath":".spec.symbol"}"#,
+    printcolumn = r#"{"name":"Faction", "type":"string", "jsonPath":".spec.faction"}"#,
+    printcolumn = r#"{"name":"Namespace", "type":"string", "jsonPath":".spec.namespace"}"#,
+    printcolumn = r#"{"name":"Ready", "type":"string", "jsonPath":".status.conditions[?(@.type==\"Ready\")].status"}"#,
+    printcolumn = r#"{"name":"Age", "type":"date", "jsonPath":".metadata.creationTimestamp"}"#
 )]
 #[kube(status = "ManagerStatus")]
 pub struct ManagerSpec {
+    #[schemars(length(min = 3, max = 14), regex(pattern = r"^[A-Za-z0-9_-]+$"))]
     pub symbol: String,
     pub faction: FactionSymbol,
+    #[schemars(length(max = 63), regex(pattern = r"^[a-z0-9]([-a-z0-9]*[a-z0-9])?$"))]
     pub namespace: String,
+    /// Whether the agent's namespace is deleted along with the manager.
+    #[serde(default)]
+    pub delete_namespace: bool,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
 pub struct ManagerStatus {
+    /// Empty until the manager has been applied once. Defaults so that a
+    /// status holding only conditions is valid.
+    #[serde(default)]
     pub checksum: String,
     pub last_updated: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
 }
 
 #[derive(Deserialize, CustomResource, Serialize, Clone, Debug, JsonSchema)]
-#[kube(kind = "Agent", group = "spacetraders.io", version = "v1", namespaced)]
+#[kube(
+    kind = "Agent",
+    group = "spacetraders.io",
+    version = "v1",
+    namespaced,
+    printcolumn = r#"{"name":"Symbol", "type":"string", "jsonPath":".spec.symbol"}"#,
+    printcolumn = r#"{"name":"Credits", "type":"integer", "jsonPath":".status.credits"}"#,
+    printcolumn = r#"{"name":"Ships", "type":"integer", "jsonPath":".status.ship_count"}"#,
+    printcolumn = r#"{"name":"Headquarters", "type":"string", "jsonPath":".status.headquarters"}"#,
+    printcolumn = r#"{"name":"Ready", "type":"string", "jsonPath":".status.conditions[?(@.type==\"Ready\")].status"}"#,
+    printcolumn = r#"{"name":"Age", "type":"date", "jsonPath":".metadata.creationTimestamp"}"#
+)]
 #[kube(status = "AgentStatus")]
 pub struct AgentSpec {
+    #[schemars(length(min = 3, max = 14), regex(pattern = r"^[A-Za-z0-9_-]+$"))]
     pub symbol: String,
     pub faction: FactionSymbol,
+    /// Deprecated: plaintext tokens are moved into the token secret by the
+    /// operator and removed from the spec.
     pub token: Option<String>,
+    /// Name of the secret in the agent's namespace holding the bearer token
+    /// under the `token` key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_secret: Option<String>,
     pub reset_date: Option<DateTime<Utc>>,
 }
 #[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
 pub struct AgentStatus {
+    #[serde(default)]
     pub checksum: String,
+    #[serde(default)]
     pub ships_initialized: bool,
     pub last_updated: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credits: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headquarters: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ship_count: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observed_generation: Option<i64>,
+    /// When the fleet is due to be synced again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_sync: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
 }
 
 #[derive(Deserialize, CustomResource, Serialize, Clone, Debug, JsonSchema)]
-#[kube(kind = "Ship", group = "spacetraders.io", version = "v1", namespaced)]
+#[kube(
+    kind = "Ship",
+    group = "spacetraders.io",
+    version = "v1",
+    namespaced,
+    printcolumn = r#"{"name":"Symbol", "type":"string", "jsonPath":".spec.symbol"}"#,
+    printcolumn = r#"{"name":"Role", "type":"string", "jsonPath":".spec.role"}"#,
+    printcolumn = r#"{"name":"Location", "type":"string", "jsonPath":".status.location.waypoint"}"#,
+    printcolumn = r#"{"name":"Status", "type":"string", "jsonPath":".status.status"}"#,
+    printcolumn = r#"{"name":"Phase", "type":"string", "jsonPath":".status.phase"}"#,
+    printcolumn = r#"{"name":"Fuel", "type":"integer", "priority":1, "jsonPath":".status.fuel.current"}"#,
+    printcolumn = r#"{"name":"ETA", "type":"date", "priority":1, "jsonPath":".status.eta"}"#,
+    printcolumn = r#"{"name":"Ready", "type":"string", "jsonPath":".status.conditions[?(@.type==\"Ready\")].status"}"#,
+    printcolumn = r#"{"name":"Age", "type":"date", "jsonPath":".metadata.creationTimestamp"}"#
+)]
 #[kube(status = "ShipStatus")]
 pub struct ShipSpec {
+    #[schemars(length(min = 1), regex(pattern = r"^[A-Za-z0-9_-]+$"))]
     pub symbol: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub role: Option<models::ShipRole>,
+    /// Waypoint the ship should travel to and stay at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<models::Location>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flight_mode: Option<models::ShipNavFlightMode>,
+    /// Whether the ship should be docked once it is at its destination.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub docked: Option<bool>,
+    /// A long running behaviour that takes over control of the ship. The
+    /// destination and docking state are ignored while one is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub behaviour: Option<ShipBehaviour>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ShipBehaviour {
+    /// One of `probe`, `siphon`, `refine`, `construction` or `contract`.
+    #[schemars(regex(pattern = r"^(probe|siphon|refine|construction|contract)$"))]
+    pub name: String,
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum ShipPhase {
+    Idle,
+    InTransit,
+    Running,
+    Failed,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
 pub struct ShipStatus {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub location: Option<models::Location>,
@@ -58,6 +152,42 @@ pub struct ShipStatus {
     pub status: Option<models::ShipNavStatus>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub flight_mode: Option<models::ShipNavFlightMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<ShipPhase>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eta: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fuel: Option<ShipFuelStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cargo: Option<ShipCargoStatus>,
+    /// When the reactor cooldown expires, if one is active.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_synced: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observed_generation: Option<i64>,
+    /// When the controller is due to look at the ship again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_sync: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ShipFuelStatus {
+    pub current: i32,
+    pub capacity: i32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ShipCargoStatus {
+    pub units: i32,
+    pub capacity: i32,
+    #[serde(default)]
+    pub inventory: BTreeMap<String, i32>,
 }
 
 #[derive(Deserialize, CustomResource, Serialize, Clone, Debug, JsonSchema)]
@@ -65,14 +195,244 @@ pub struct ShipStatus {
     kind = "PurchaseOrder",
     group = "spacetraders.io",
     version = "v1",
-    namespaced
+    namespaced,
+    printcolumn = r#"{"name":"Type", "type":"string", "jsonPath":".spec.ship_type"}"#,
+    printcolumn = r#"{"name":"Waypoint", "type":"string", "jsonPath":".spec.location.waypoint"}"#,
+    printcolumn = r#"{"name":"Complete", "type":"boolean", "jsonPath":".status.complete"}"#,
+    printcolumn = r#"{"name":"Ship", "type":"string", "jsonPath":".status.ship_symbol"}"#,
+    printcolumn = r#"{"name":"Ready", "type":"string", "jsonPath":".status.conditions[?(@.type==\"Ready\")].status"}"#,
+    printcolumn = r#"{"name":"Age", "type":"date", "jsonPath":".metadata.creationTimestamp"}"#
 )]
 #[kube(status = "PurchaseOrderStatus")]
 pub struct PurchaseOrderSpec {
     pub location: models::Location,
+    pub ship_type: models::ShipType,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
 pub struct PurchaseOrderStatus {
     pub complete: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ship_symbol: Option<String>,
+    /// Credits paid for the ship.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price: Option<i64>,
+    /// Recorded right before the ship is bought and cleared once the order
+    /// is complete. While it is set the purchase may have gone through
+    /// without the result being recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purchasing: Option<PurchaseIntent>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PurchaseIntent {
+    pub location: models::Location,
+    pub ship_type: models::ShipType,
+    /// Frame of the ship type as listed by the shipyard, the bought ship has
+    /// the same.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame: Option<String>,
+    /// Listed price of the ship when the purchase was started.
+    pub price: i64,
+    /// Symbols of the agent's ships before the purchase. A ship at the
+    /// shipyard with the frame that isn't one of them is the one bought.
+    pub known_ships: Vec<String>,
+}
+
+#[derive(Deserialize, CustomResource, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    kind = "Fleet",
+    group = "spacetraders.io",
+    version = "v1",
+    namespaced,
+    printcolumn = r#"{"name":"Spent", "type":"integer", "jsonPath":".status.spent"}"#,
+    printcolumn = r#"{"name":"Budget", "type":"integer", "jsonPath":".spec.budget"}"#,
+    printcolumn = r#"{"name":"Ready", "type":"string", "jsonPath":".status.conditions[?(@.type==\"Ready\")].status"}"#,
+    printcolumn = r#"{"name":"Age", "type":"date", "jsonPath":".metadata.creationTimestamp"}"#
+)]
+#[kube(status = "FleetStatus")]
+pub struct FleetSpec {
+    pub targets: Vec<FleetTarget>,
+    /// Credits that are never spent on ships.
+    #[serde(default)]
+    #[schemars(range(min = 0))]
+    pub credit_reserve: i64,
+    /// Total credits the fleet may spend on ships. Unlimited when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0))]
+    pub budget: Option<i64>,
+}
+
+/// A number of ships to keep in the fleet. With a role every ship of the
+/// agent with that role counts, otherwise only ships the fleet bought as
+/// this type do. The type must register with the role, the fleet stops
+/// buying once it bought a ship that doesn't.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FleetTarget {
+    /// The type bought when ships are missing.
+    pub ship_type: models::ShipType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<models::ShipRole>,
+    #[schemars(range(min = 0))]
+    pub count: u32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct FleetStatus {
+    /// Ships counted for each target, in the order of the spec.
+    #[serde(default)]
+    pub targets: Vec<FleetTargetStatus>,
+    /// Credits spent on completed purchase orders of the fleet.
+    #[serde(default)]
+    pub spent: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observed_generation: Option<i64>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FleetTargetStatus {
+    pub ship_type: models::ShipType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<models::ShipRole>,
+    pub desired: u32,
+    pub actual: u32,
+    pub pending: u32,
+}
+
+#[derive(Deserialize, CustomResource, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    kind = "Contract",
+    group = "spacetraders.io",
+    version = "v1",
+    namespaced,
+    printcolumn = r#"{"name":"Type", "type":"string", "jsonPath":".spec.contract_type"}"#,
+    printcolumn = r#"{"name":"Faction", "type":"string", "jsonPath":".spec.faction_symbol"}"#,
+    printcolumn = r#"{"name":"Accepted", "type":"boolean", "jsonPath":".status.accepted"}"#,
+    printcolumn = r#"{"name":"Fulfilled", "type":"boolean", "jsonPath":".status.fulfilled"}"#,
+    printcolumn = r#"{"name":"Progress", "type":"integer", "jsonPath":".status.progress"}"#,
+    printcolumn = r#"{"name":"Ship", "type":"string", "jsonPath":".spec.ship"}"#,
+    printcolumn = r#"{"name":"Ready", "type":"string", "jsonPath":".status.conditions[?(@.type==\"Ready\")].status"}"#,
+    printcolumn = r#"{"name":"Age", "type":"date", "jsonPath":".metadata.creationTimestamp"}"#
+)]
+#[kube(status = "ContractStatus")]
+pub struct ContractSpec {
+    pub id: String,
+    pub faction_symbol: String,
+    pub contract_type: models::ContractType,
+    pub terms: ContractTermsSpec,
+    pub expiration: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline_to_accept: Option<DateTime<Utc>>,
+    /// Set to accept the contract. Accepting can't be undone.
+    #[serde(default)]
+    pub accept: bool,
+    /// Symbol of the ship that buys and delivers the goods once the contract
+    /// is accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(regex(pattern = r"^[A-Za-z0-9_-]+$"))]
+    pub ship: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractTermsSpec {
+    pub deadline: DateTime<Utc>,
+    pub payment: models::ContractPayment,
+    #[serde(default)]
+    pub deliver: Vec<ContractDeliverySpec>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractDeliverySpec {
+    pub trade_symbol: String,
+    pub destination: models::Location,
+    pub units_required: i32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct ContractStatus {
+    pub accepted: bool,
+    pub fulfilled: bool,
+    #[serde(default)]
+    pub deliveries: Vec<ContractDeliveryStatus>,
+    /// Percentage of all required units that have been delivered.
+    #[serde(default)]
+    pub progress: u8,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractDeliveryStatus {
+    pub trade_symbol: String,
+    pub units_fulfilled: i32,
+    pub units_required: i32,
+}
+
+/// A single observation about a resource, following the shape of the
+/// conditions used by the built in Kubernetes resources.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Condition {
+    #[serde(rename = "type")]
+    pub condition_type: String,
+    pub status: String,
+    pub reason: String,
+    pub message: String,
+    pub last_transition_time: Option<DateTime<Utc>>,
+}
+
+impl Condition {
+    pub const READY: &'static str = "Ready";
+    pub const REGISTERED: &'static str = "Registered";
+    pub const SYNCED: &'static str = "Synced";
+    pub const DEGRADED: &'static str = "Degraded";
+
+    pub fn new(condition_type: &str, status: bool, reason: &str, message: String) -> Self {
+        Self {
+            condition_type: condition_type.to_string(),
+            status: match status {
+                true => "True".to_string(),
+                false => "False".to_string(),
+            },
+            reason: reason.to_string(),
+            message,
+            last_transition_time: Some(Utc::now()),
+        }
+    }
+
+    pub fn is_true(&self) -> bool {
+        self.status == "True"
+    }
+}
+
+/// Adds or replaces the condition of the same type. The transition time is
+/// only moved when the status actually changes. Returns whether anything
+/// besides the timestamp changed.
+pub fn set_condition(conditions: &mut Vec<Condition>, condition: Condition) -> bool {
+    match conditions
+        .iter_mut()
+        .find(|c| c.condition_type == condition.condition_type)
+    {
+        Some(existing) => {
+            let changed = existing.status != condition.status
+                || existing.reason != condition.reason
+                || existing.message != condition.message;
+
+            if existing.status != condition.status {
+                existing.last_transition_time = condition.last_transition_time;
+            }
+            existing.status = condition.status;
+            existing.reason = condition.reason;
+            existing.message = condition.message;
+
+            changed
+        }
+        None => {
+            conditions.push(condition);
+            true
+        }
+    }
 }
@@ -0,0 +1,398 @@
+use eyre::{Context, Ok, Result};
+use openapi::apis::configuration::Configuration;
+use openapi::apis::{contracts_api, fleet_api, systems_api};
+use openapi::models::TradeSymbol;
+use sea_orm::DatabaseConnection;
+
+use crate::models::ship::{ShipCargo, ShipCargoItem, ShipCooldown, ShipFuel, ShipNav};
+use crate::models::{
+    Contract, Location, MarketSnapshot, MarketTransaction, Ship, ShipNavFlightMode, ShipNavStatus,
+    Waypoint, WaypointTraitSymbol,
+};
+
+/// Sleeps until the ship's reactor cooldown has expired.
+pub async fn wait_for_cooldown(cooldown: &ShipCooldown) {
+    let remaining = cooldown.remaining();
+    if !remaining.is_zero() {
+        println!("Waiting {}s for cooldown", remaining.as_secs());
+        tokio::time::sleep(remaining).await;
+    }
+}
+
+pub fn trade_symbol(symbol: &str) -> Result<TradeSymbol> {
+    serde_json::from_value(serde_json::Value::String(symbol.to_string()))
+        .wrap_err_with(|| format!("Unknown trade symbol {}", symbol))
+}
+
+pub async fn orbit(config: &Configuration, ship: &mut Ship) -> Result<()> {
+    if ship.nav.status != ShipNavStatus::Docked {
+        return Ok(());
+    }
+
+    let res = fleet_api::orbit_ship(config, ship.symbol.as_str())
+        .await
+        .wrap_err("Error undocking")?;
+
+    ship.update_nav(ShipNav::from(res.data.nav));
+    Ok(())
+}
+
+pub async fn dock(config: &Configuration, ship: &mut Ship) -> Result<()> {
+    if ship.nav.status != ShipNavStatus::InOrbit {
+        return Ok(());
+    }
+
+    let res = fleet_api::dock_ship(config, ship.symbol.as_str())
+        .await
+        .wrap_err("Error docking")?;
+
+    ship.update_nav(ShipNav::from(res.data.nav));
+    Ok(())
+}
+
+pub async fn set_flight_mode(
+    config: &Configuration,
+    ship: &mut Ship,
+    flight_mode: ShipNavFlightMode,
+) -> Result<()> {
+    if ship.nav.flight_mode == flight_mode {
+        return Ok(());
+    }
+
+    let req = openapi::models::PatchShipNavRequest {
+        flight_mode: Some(flight_mode.into()),
+    };
+
+    let res = fleet_api::patch_ship_nav(config, ship.symbol.as_str(), Some(req))
+        .await
+        .wrap_err("Error setting flight mode")?;
+
+    ship.update_nav(ShipNav::from(res.data));
+    Ok(())
+}
+
+/// Tops up the fuel tank if the ship carries fuel at all and is not full.
+pub async fn refuel(config: &Configuration, ship: &mut Ship) -> Result<Option<MarketTransaction>> {
+    if ship.fuel.capacity == 0 || ship.fuel.current >= ship.fuel.capacity {
+        return Ok(None);
+    }
+
+    let res = fleet_api::refuel_ship(config, ship.symbol.as_str(), None)
+        .await
+        .wrap_err("Error refueling")?;
+
+    ship.update_fuel(ShipFuel::from(res.data.fuel));
+    Ok(Some(MarketTransaction::from(res.data.transaction)))
+}
+
+/// Sells every cargo item accepted by `should_sell` at the market the ship is
+/// docked at, splitting sales by the market's trade volume. The market is
+/// recorded as a snapshot on the way since the prices are fresh.
+pub async fn sell_cargo<F>(
+    config: &Configuration,
+    db: &DatabaseConnection,
+    ship: &mut Ship,
+    should_sell: F,
+) -> Result<Vec<MarketTransaction>>
+where
+    F: Fn(&str) -> bool,
+{
+    let location = ship.nav.location.clone();
+    let res = systems_api::get_market(
+        config,
+        location.system_ident().as_str(),
+        location.waypoint_ident().as_str(),
+    )
+    .await
+    .wrap_err("Error fetching market")?;
+
+    let market = MarketSnapshot::from(*res.data);
+    crate::repository::insert_market_snapshot(db, market.clone()).await?;
+
+    let to_sell = ship
+        .cargo
+        .inventory
+        .iter()
+        .filter(|item| should_sell(item.symbol.as_str()))
+        .cloned()
+        .collect::<Vec<ShipCargoItem>>();
+
+    let mut transactions = vec![];
+    for item in to_sell {
+        let good = match market.trade_goods.iter().find(|g| g.symbol == item.symbol) {
+            Some(good) => good,
+            None => continue,
+        };
+
+        let mut remaining = item.units;
+        while remaining > 0 {
+            let units = remaining.min(good.trade_volume.max(1));
+            let req = openapi::models::SellCargoRequest::new(trade_symbol(&item.symbol)?, units);
+            let res = fleet_api::sell_cargo(config, ship.symbol.as_str(), Some(req))
+                .await
+                .wrap_err_with(|| format!("Error selling {}", item.symbol))?;
+
+            ship.update_cargo(ShipCargo::from(res.data.cargo));
+            let transaction = MarketTransaction::from(res.data.transaction);
+            println!(
+                "Sold {} {} for {} credits",
+                transaction.units, transaction.trade_symbol, transaction.total_price
+            );
+
+            crate::repository::insert_transaction(db, &transaction).await?;
+            transactions.push(transaction);
+            remaining -= units;
+        }
+    }
+
+    Ok(transactions)
+}
+
+/// Buys `units` of a good at the market the ship is docked at, splitting the
+/// purchase by the market's trade volume.
+pub async fn buy_cargo(
+    config: &Configuration,
+    db: &DatabaseConnection,
+    ship: &mut Ship,
+    symbol: &str,
+    units: i32,
+) -> Result<Vec<MarketTransaction>> {
+    let location = ship.nav.location.clone();
+    let res = systems_api::get_market(
+        config,
+        location.system_ident().as_str(),
+        location.waypoint_ident().as_str(),
+    )
+    .await
+    .wrap_err("Error fetching market")?;
+
+    let market = MarketSnapshot::from(*res.data);
+    crate::repository::insert_market_snapshot(db, market.clone()).await?;
+
+    let good = market
+        .trade_goods
+        .iter()
+        .find(|g| g.symbol == symbol)
+        .ok_or_else(|| eyre::eyre!("{} is not traded at {}", symbol, location))?;
+
+    let mut transactions = vec![];
+    let mut remaining = units.min(ship.cargo.available());
+    while remaining > 0 {
+        let batch = remaining.min(good.trade_volume.max(1));
+        let req = openapi::models::PurchaseCargoRequest::new(trade_symbol(symbol)?, batch);
+        let res = fleet_api::purchase_cargo(config, ship.symbol.as_str(), Some(req))
+            .await
+            .wrap_err_with(|| format!("Error buying {}", symbol))?;
+
+        ship.update_cargo(ShipCargo::from(res.data.cargo));
+        let transaction = MarketTransaction::from(res.data.transaction);
+        println!(
+            "Bought {} {} for {} credits",
+            transaction.units, transaction.trade_symbol, transaction.total_price
+        );
+
+        crate::repository::insert_transaction(db, &transaction).await?;
+        transactions.push(transaction);
+        remaining -= batch;
+    }
+
+    Ok(transactions)
+}
+
+/// The market in the system selling a good for the lowest price, based on the
+/// latest recorded snapshots.
+pub async fn cheapest_market(
+    db: &DatabaseConnection,
+    system: &str,
+    symbol: &str,
+) -> Result<Option<(Location, i32)>> {
+    let snapshots = crate::repository::get_latest_market_snapshots(db, system).await?;
+
+    Ok(snapshots
+        .iter()
+        .filter_map(|s| {
+            s.trade_goods
+                .iter()
+                .find(|g| g.symbol == symbol && g.trade_type != "IMPORT")
+                .map(|g| (s.location.clone(), g.purchase_price))
+        })
+        .min_by_key(|(_, price)| *price))
+}
+
+/// Picks the market in the system that pays the most for the given cargo,
+/// based on the latest recorded snapshots.
+pub async fn best_market(
+    db: &DatabaseConnection,
+    system: &str,
+    cargo: &[ShipCargoItem],
+) -> Result<Option<Location>> {
+    let snapshots = crate::repository::get_latest_market_snapshots(db, system).await?;
+
+    let value = |snapshot: &MarketSnapshot| -> i64 {
+        cargo
+            .iter()
+            .filter_map(|item| {
+                snapshot
+                    .trade_goods
+                    .iter()
+                    .find(|g| g.symbol == item.symbol)
+                    .map(|g| g.sell_price as i64 * item.units as i64)
+            })
+            .sum()
+    };
+
+    Ok(snapshots
+        .iter()
+        .map(|s| (value(s), s))
+        .filter(|(v, _)| *v > 0)
+        .max_by_key(|(v, _)| *v)
+        .map(|(_, s)| s.location.clone()))
+}
+
+/// The best paying market for the cargo, falling back to the nearest
+/// marketplace when no snapshot has a price for any of it.
+pub async fn choose_market(
+    db: &DatabaseConnection,
+    waypoints: &[Waypoint],
+    from: &Location,
+    cargo: &[ShipCargoItem],
+) -> Result<Location> {
+    let system = from.system_ident();
+    if let Some(market) = best_market(db, system.as_str(), cargo).await? {
+        return Ok(market);
+    }
+
+    let marketplaces = waypoints
+        .iter()
+        .filter(|w| {
+            w.traits
+                .iter()
+                .any(|t| t.symbol == WaypointTraitSymbol::Marketplace)
+        })
+        .cloned()
+        .collect::<Vec<Waypoint>>();
+
+    Ok(nearest_waypoint(&marketplaces, from, waypoints)
+        .ok_or_else(|| eyre::eyre!("No marketplace found in {}", system))?
+        .location
+        .clone())
+}
+
+pub fn nearest_waypoint<'w>(
+    waypoints: &'w [Waypoint],
+    from: &Location,
+    all: &[Waypoint],
+) -> Option<&'w Waypoint> {
+    let position = all
+        .iter()
+        .find(|w| &w.location == from)
+        .map(|w| (w.x, w.y))
+        .unwrap_or((0, 0));
+
+    waypoints.iter().min_by_key(|w| {
+        let dx = (w.x - position.0) as i64;
+        let dy = (w.y - position.1) as i64;
+        dx * dx + dy * dy
+    })
+}
+
+pub async fn get_all_ships(config: &Configuration) -> Result<Vec<Ship>> {
+    let page = 1;
+    let limit = 20;
+    let res = fleet_api::get_my_ships(config, Some(page), Some(limit))
+        .await
+        .wrap_err("Error fetching ships")?;
+
+    let mut ships = res.data.into_iter().map(Ship::from).collect::<Vec<Ship>>();
+
+    let total = res.meta.total;
+    let num_pages = (total as f32 / limit as f32).ceil() as i32;
+
+    for n in (page + 1)..=num_pages {
+        let res = fleet_api::get_my_ships(config, Some(n), Some(limit))
+            .await
+            .wrap_err("Error fetching ships")?;
+
+        ships.extend(res.data.into_iter().map(Ship::from));
+    }
+
+    Ok(ships)
+}
+
+pub async fn get_all_contracts(config: &Configuration) -> Result<Vec<Contract>> {
+    let page = 1;
+    let limit = 20;
+    let res = contracts_api::get_contracts(config, Some(page), Some(limit))
+        .await
+        .wrap_err("Error fetching contracts")?;
+
+    let mut contracts = res
+        .data
+        .into_iter()
+        .map(Contract::from)
+        .collect::<Vec<Contract>>();
+
+    let total = res.meta.total;
+    let num_pages = (total as f32 / limit as f32).ceil() as i32;
+
+    for n in (page + 1)..=num_pages {
+        let res = contracts_api::get_contracts(config, Some(n), Some(limit))
+            .await
+            .wrap_err("Error fetching contracts")?;
+
+        contracts.extend(res.data.into_iter().map(Contract::from));
+    }
+
+    Ok(contracts)
+}
+
+/// Fetches every waypoint in the system from the API and stores it.
+pub async fn refresh_system_waypoints(
+    config: &Configuration,
+    db: &DatabaseConnection,
+    system: &str,
+) -> Result<()> {
+    let page = 1;
+    let limit = 20;
+
+    let res =
+        systems_api::get_system_waypoints(config, system, Some(page), Some(limit), None, None)
+            .await
+            .wrap_err("Error fetching system waypoints")?;
+
+    let mut waypoints = res
+        .data
+        .into_iter()
+        .map(Waypoint::from)
+        .collect::<Vec<Waypoint>>();
+
+    let total = res.meta.total;
+    let num_pages = (total as f32 / limit as f32).ceil() as i32;
+
+    for n in (page + 1)..=num_pages {
+        let res =
+            systems_api::get_system_waypoints(config, system, Some(n), Some(limit), None, None)
+                .await
+                .wrap_err("Error fetching system waypoints")?;
+
+        waypoints.extend(res.data.into_iter().map(Waypoint::from));
+    }
+
+    crate::repository::insert_waypoints(db, waypoints).await
+}
+
+/// Stored waypoints for the system, fetching them from the API first if the
+/// system has not been refreshed yet.
+pub async fn system_waypoints(
+    config: &Configuration,
+    db: &DatabaseConnection,
+    system: &str,
+) -> Result<Vec<Waypoint>> {
+    let waypoints = crate::repository::get_system_waypoints(db, system).await?;
+    if !waypoints.is_empty() {
+        return Ok(waypoints);
+    }
+
+    refresh_system_waypoints(config, db, system).await?;
+    crate::repository::get_system_waypoints(db, system).await
+}
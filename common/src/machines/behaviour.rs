@@ -0,0 +1,144 @@
+use eyre::{Ok, Result};
+use openapi::apis::configuration::Configuration;
+use sea_orm::DatabaseConnection;
+
+use super::{
+    ConstructionMachineWrapper, ContractMachineWrapper, ProbeMachineWrapper,
+    RefineryMachineWrapper, SiphonMachineWrapper,
+};
+use crate::crds::ShipBehaviour;
+use crate::models::Location;
+
+/// Any of the long running machines a ship can be handed to, so callers can
+/// drive them one step at a time without knowing which one it is.
+pub enum BehaviourMachine<'a> {
+    Probe(ProbeMachineWrapper<'a>),
+    Siphon(SiphonMachineWrapper<'a>),
+    Refine(RefineryMachineWrapper<'a>),
+    Construction(ConstructionMachineWrapper<'a>),
+    Contract(ContractMachineWrapper<'a>),
+}
+
+impl<'a> BehaviourMachine<'a> {
+    /// Sets up the machine named by the behaviour, reading its parameters.
+    pub async fn new(
+        behaviour: &ShipBehaviour,
+        config: Configuration,
+        db: &'a DatabaseConnection,
+        ship_symbol: &str,
+    ) -> Result<Self> {
+        match behaviour.name.as_str() {
+            "probe" => {
+                let index = param(behaviour, "index")?.unwrap_or(0);
+                let count = param(behaviour, "count")?.unwrap_or(1);
+                Ok(Self::Probe(
+                    ProbeMachineWrapper::new(config, db, ship_symbol, index, count).await?,
+                ))
+            }
+
+            "siphon" => Ok(Self::Siphon(
+                SiphonMachineWrapper::new(config, db, ship_symbol).await?,
+            )),
+
+            "refine" => {
+                let station = param::<Location>(behaviour, "station")?
+                    .ok_or_else(|| eyre::eyre!("refine behaviour requires a station"))?;
+                Ok(Self::Refine(
+                    RefineryMachineWrapper::new(config, db, ship_symbol, station).await?,
+                ))
+            }
+
+            "construction" => Ok(Self::Construction(
+                ConstructionMachineWrapper::new(config, db, ship_symbol).await?,
+            )),
+
+            "contract" => {
+                let contract = param::<String>(behaviour, "contract")?
+                    .ok_or_else(|| eyre::eyre!("contract behaviour requires a contract"))?;
+                Ok(Self::Contract(
+                    ContractMachineWrapper::new(config, db, ship_symbol, contract.as_str()).await?,
+                ))
+            }
+
+            name => Err(eyre::eyre!("unknown behaviour {}", name)),
+        }
+    }
+
+    /// Takes one step. Returns `None` once the machine has nothing left to
+    /// do, machines that loop forever never do.
+    pub async fn step(self) -> Result<Option<Self>> {
+        let machine = match self {
+            Self::Probe(m) => Self::Probe(m.step().await?),
+            Self::Siphon(m) => Self::Siphon(m.step().await?),
+            Self::Refine(m) => Self::Refine(m.step().await?),
+            Self::Construction(ConstructionMachineWrapper::ConstructionComplete) => {
+                return Ok(None)
+            }
+            Self::Construction(m) => Self::Construction(m.step().await?),
+            Self::Contract(ContractMachineWrapper::ContractFulfilled) => return Ok(None),
+            Self::Contract(m) => Self::Contract(m.step().await?),
+        };
+
+        Ok(Some(machine))
+    }
+
+    pub async fn run(self) -> Result<()> {
+        let mut machine = self;
+        while let Some(next) = machine.step().await? {
+            machine = next;
+        }
+
+        Ok(())
+    }
+
+    /// Name of the state the machine is in, for logs and checkpoints.
+    pub fn state(&self) -> &'static str {
+        match self {
+            Self::Probe(m) => match m {
+                ProbeMachineWrapper::Selecting(_) => "Selecting",
+                ProbeMachineWrapper::Travelling(_) => "Travelling",
+                ProbeMachineWrapper::Recording(_) => "Recording",
+            },
+            Self::Siphon(m) => match m {
+                SiphonMachineWrapper::Travelling(_) => "Travelling",
+                SiphonMachineWrapper::Siphoning(_) => "Siphoning",
+                SiphonMachineWrapper::Selling(_) => "Selling",
+            },
+            Self::Refine(m) => match m {
+                RefineryMachineWrapper::Travelling(_) => "Travelling",
+                RefineryMachineWrapper::Collecting(_) => "Collecting",
+                RefineryMachineWrapper::Refining(_) => "Refining",
+                RefineryMachineWrapper::Selling(_) => "Selling",
+            },
+            Self::Construction(m) => match m {
+                ConstructionMachineWrapper::Planning(_) => "Planning",
+                ConstructionMachineWrapper::Travelling(_) => "Travelling",
+                ConstructionMachineWrapper::Buying(_) => "Buying",
+                ConstructionMachineWrapper::Supplying(_) => "Supplying",
+                ConstructionMachineWrapper::ConstructionComplete => "ConstructionComplete",
+            },
+            Self::Contract(m) => match m {
+                ContractMachineWrapper::Planning(_) => "Planning",
+                ContractMachineWrapper::Travelling(_) => "Travelling",
+                ContractMachineWrapper::Buying(_) => "Buying",
+                ContractMachineWrapper::Delivering(_) => "Delivering",
+                ContractMachineWrapper::ContractFulfilled => "ContractFulfilled",
+            },
+        }
+    }
+}
+
+fn param<T>(behaviour: &ShipBehaviour, key: &str) -> Result<Option<T>>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    behaviour
+        .params
+        .get(key)
+        .map(|v| {
+            v.parse::<T>()
+                .map_err(|e| eyre::eyre!("invalid {} parameter {}: {}", key, v, e))
+        })
+        .transpose()
+}
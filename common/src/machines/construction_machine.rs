@@ -0,0 +1,314 @@
+use eyre::{Context, Ok, Result};
+use openapi::apis::configuration::Configuration;
+use openapi::apis::{fleet_api, systems_api};
+use openapi::models::SupplyConstructionRequest;
+use sea_orm::DatabaseConnection;
+
+use super::{actions, TravelMachineWrapper};
+use crate::models::ship::ShipCargo;
+use crate::models::{Construction, Location, Ship, WaypointType};
+
+/// Buys the materials the system's jump gate still needs and hauls them to
+/// the gate until construction is fulfilled. The machine only buys, it
+/// doesn't mine: materials already in the hold, e.g. handed over by a mining
+/// ship, are delivered first.
+pub enum ConstructionMachineWrapper<'a> {
+    Planning(ConstructionMachine<'a, Planning>),
+    Travelling(Box<ConstructionMachine<'a, Travelling<'a>>>),
+    Buying(ConstructionMachine<'a, Buying>),
+    Supplying(ConstructionMachine<'a, Supplying>),
+    ConstructionComplete,
+}
+
+impl<'a> ConstructionMachineWrapper<'a> {
+    pub async fn new(
+        config: Configuration,
+        db: &'a DatabaseConnection,
+        ship_symbol: &str,
+    ) -> Result<Self> {
+        let res = fleet_api::get_my_ship(&config, ship_symbol).await?;
+        let ship = Ship::from(res.data);
+
+        let system = ship.nav.location.system_ident();
+        let gate = actions::system_waypoints(&config, db, system.as_str())
+            .await?
+            .into_iter()
+            .find(|w| w.waypoint_type == WaypointType::JumpGate)
+            .ok_or_else(|| eyre::eyre!("No jump gate found in {}", system))?
+            .location;
+
+        Ok(Self::Planning(ConstructionMachine {
+            state: Planning,
+            db,
+            config,
+            ship,
+            gate,
+        }))
+    }
+
+    pub async fn step(self) -> Result<Self> {
+        match self {
+            ConstructionMachineWrapper::Planning(val) => val.plan().await,
+
+            ConstructionMachineWrapper::Travelling(val) => match val.state.machine {
+                TravelMachineWrapper::TravelComplete => val.arrive().await,
+                _ => Ok(ConstructionMachineWrapper::Travelling(Box::new(
+                    val.travel().await?,
+                ))),
+            },
+
+            ConstructionMachineWrapper::Buying(val) => Ok(ConstructionMachineWrapper::Travelling(
+                Box::new(val.buy().await?),
+            )),
+
+            ConstructionMachineWrapper::Supplying(val) => {
+                Ok(ConstructionMachineWrapper::Planning(val.supply().await?))
+            }
+
+            ConstructionMachineWrapper::ConstructionComplete => {
+                Err(eyre::eyre!("Construction has already been completed"))
+            }
+        }
+    }
+
+    /// Drives the machine until every material has been supplied.
+    pub async fn run(self) -> Result<()> {
+        let mut machine = self;
+        loop {
+            match machine {
+                ConstructionMachineWrapper::ConstructionComplete => {
+                    println!("Construction materials fulfilled!");
+                    return Ok(());
+                }
+                _ => machine = machine.step().await?,
+            }
+        }
+    }
+}
+
+pub struct ConstructionMachine<'a, S> {
+    pub state: S,
+    db: &'a DatabaseConnection,
+    config: Configuration,
+    ship: Ship,
+    gate: Location,
+}
+
+impl<'a, S> ConstructionMachine<'a, S> {
+    fn with_state<T>(self, state: T) -> ConstructionMachine<'a, T> {
+        ConstructionMachine {
+            state,
+            db: self.db,
+            config: self.config,
+            ship: self.ship,
+            gate: self.gate,
+        }
+    }
+
+    async fn travel_to(
+        self,
+        destination: Location,
+        leg: Leg,
+    ) -> Result<ConstructionMachine<'a, Travelling<'a>>> {
+        let machine = TravelMachineWrapper::new(
+            self.config.clone(),
+            self.db,
+            destination,
+            self.ship.symbol.as_str(),
+        )
+        .await?;
+
+        Ok(self.with_state(Travelling { machine, leg }))
+    }
+}
+
+pub struct Planning;
+impl<'a> ConstructionMachine<'a, Planning> {
+    /// Refreshes construction progress and decides what to fetch next.
+    pub async fn plan(self) -> Result<ConstructionMachineWrapper<'a>> {
+        let res = systems_api::get_construction(
+            &self.config,
+            self.gate.system_ident().as_str(),
+            self.gate.waypoint_ident().as_str(),
+        )
+        .await
+        .wrap_err("Error fetching construction")?;
+
+        let construction = Construction::from(res.data);
+        crate::repository::save_construction(self.db, &construction).await?;
+
+        let material = match construction.next_material() {
+            Some(material) if !construction.is_fulfilled() => material.clone(),
+            _ => return Ok(ConstructionMachineWrapper::ConstructionComplete),
+        };
+
+        let remaining = material.remaining();
+        let held = self.ship.cargo.units_of(&material.trade_symbol);
+        let wanted = remaining.min(held + self.ship.cargo.available());
+
+        // A hold full of something else leaves no room to buy. Other
+        // materials the gate still needs are delivered, anything else has to
+        // be cleared by hand.
+        if wanted <= 0 {
+            let other_material = construction
+                .materials
+                .iter()
+                .any(|m| m.remaining() > 0 && self.ship.cargo.units_of(&m.trade_symbol) > 0);
+            if !other_material {
+                return Err(eyre::eyre!(
+                    "The hold of {} is full of cargo the gate doesn't need",
+                    self.ship.symbol
+                ));
+            }
+
+            let gate = self.gate.clone();
+            return Ok(ConstructionMachineWrapper::Travelling(Box::new(
+                self.travel_to(gate, Leg::Gate).await?,
+            )));
+        }
+
+        if held >= wanted && held > 0 {
+            let gate = self.gate.clone();
+            return Ok(ConstructionMachineWrapper::Travelling(Box::new(
+                self.travel_to(gate, Leg::Gate).await?,
+            )));
+        }
+
+        let system = self.gate.system_ident();
+        let market = actions::cheapest_market(self.db, system.as_str(), &material.trade_symbol)
+            .await?
+            .map(|(location, _)| location);
+
+        match market {
+            Some(market) => {
+                println!(
+                    "Buying {} {} at {}",
+                    wanted - held,
+                    material.trade_symbol,
+                    market
+                );
+                let leg = Leg::Market {
+                    symbol: material.trade_symbol,
+                    units: wanted - held,
+                };
+                Ok(ConstructionMachineWrapper::Travelling(Box::new(
+                    self.travel_to(market, leg).await?,
+                )))
+            }
+            None if held > 0 => {
+                let gate = self.gate.clone();
+                Ok(ConstructionMachineWrapper::Travelling(Box::new(
+                    self.travel_to(gate, Leg::Gate).await?,
+                )))
+            }
+            None => Err(eyre::eyre!(
+                "No known market sells {} in {}",
+                material.trade_symbol,
+                system
+            )),
+        }
+    }
+}
+
+pub enum Leg {
+    Market { symbol: String, units: i32 },
+    Gate,
+}
+
+pub struct Travelling<'a> {
+    machine: TravelMachineWrapper<'a>,
+    leg: Leg,
+}
+
+impl<'a> ConstructionMachine<'a, Travelling<'a>> {
+    pub async fn travel(mut self) -> Result<Self> {
+        self.state.machine = self.state.machine.step().await?;
+        Ok(self)
+    }
+
+    pub async fn arrive(mut self) -> Result<ConstructionMachineWrapper<'a>> {
+        let res = fleet_api::get_my_ship(&self.config, self.ship.symbol.as_str())
+            .await
+            .wrap_err("Error refreshing ship")?;
+        self.ship = Ship::from(res.data);
+
+        match std::mem::replace(&mut self.state.leg, Leg::Gate) {
+            Leg::Market { symbol, units } => Ok(ConstructionMachineWrapper::Buying(
+                self.with_state(Buying { symbol, units }),
+            )),
+            Leg::Gate => Ok(ConstructionMachineWrapper::Supplying(
+                self.with_state(Supplying),
+            )),
+        }
+    }
+}
+
+pub struct Buying {
+    symbol: String,
+    units: i32,
+}
+
+impl<'a> ConstructionMachine<'a, Buying> {
+    pub async fn buy(mut self) -> Result<ConstructionMachine<'a, Travelling<'a>>> {
+        actions::dock(&self.config, &mut self.ship).await?;
+        actions::buy_cargo(
+            &self.config,
+            self.db,
+            &mut self.ship,
+            self.state.symbol.as_str(),
+            self.state.units,
+        )
+        .await?;
+        actions::refuel(&self.config, &mut self.ship).await?;
+
+        let gate = self.gate.clone();
+        self.travel_to(gate, Leg::Gate).await
+    }
+}
+
+pub struct Supplying;
+impl<'a> ConstructionMachine<'a, Supplying> {
+    pub async fn supply(mut self) -> Result<ConstructionMachine<'a, Planning>> {
+        actions::dock(&self.config, &mut self.ship).await?;
+
+        let res = systems_api::get_construction(
+            &self.config,
+            self.gate.system_ident().as_str(),
+            self.gate.waypoint_ident().as_str(),
+        )
+        .await
+        .wrap_err("Error fetching construction")?;
+        let mut construction = Construction::from(res.data);
+
+        for material in construction.materials.clone() {
+            let units = material
+                .remaining()
+                .min(self.ship.cargo.units_of(&material.trade_symbol));
+            if units <= 0 {
+                continue;
+            }
+
+            let req = SupplyConstructionRequest::new(
+                self.ship.symbol.clone(),
+                material.trade_symbol.clone(),
+                units,
+            );
+            let res = systems_api::supply_construction(
+                &self.config,
+                self.gate.system_ident().as_str(),
+                self.gate.waypoint_ident().as_str(),
+                Some(req),
+            )
+            .await
+            .wrap_err_with(|| format!("Error supplying {}", material.trade_symbol))?;
+
+            println!("Supplied {} {}", units, material.trade_symbol);
+            self.ship.update_cargo(ShipCargo::from(res.data.cargo));
+            construction = Construction::from(res.data.construction);
+        }
+
+        crate::repository::save_construction(self.db, &construction).await?;
+
+        Ok(self.with_state(Planning))
+    }
+}
@@ -0,0 +1,305 @@
+use eyre::{Context, Ok, Result};
+use openapi::apis::configuration::Configuration;
+use openapi::apis::{contracts_api, fleet_api};
+use openapi::models::DeliverContractRequest;
+use sea_orm::DatabaseConnection;
+
+use super::{actions, TravelMachineWrapper};
+use crate::models::ship::ShipCargo;
+use crate::models::{Contract, Location, Ship};
+
+/// Buys the goods a procurement contract asks for and delivers them until
+/// every delivery is complete, then fulfills the contract. Goods already in
+/// the hold are delivered first.
+pub enum ContractMachineWrapper<'a> {
+    Planning(ContractMachine<'a, Planning>),
+    Travelling(Box<ContractMachine<'a, Travelling<'a>>>),
+    Buying(ContractMachine<'a, Buying>),
+    Delivering(ContractMachine<'a, Delivering>),
+    ContractFulfilled,
+}
+
+impl<'a> ContractMachineWrapper<'a> {
+    pub async fn new(
+        config: Configuration,
+        db: &'a DatabaseConnection,
+        ship_symbol: &str,
+        contract_id: &str,
+    ) -> Result<Self> {
+        let res = fleet_api::get_my_ship(&config, ship_symbol).await?;
+        let ship = Ship::from(res.data);
+
+        let res = contracts_api::get_contract(&config, contract_id)
+            .await
+            .wrap_err("Error fetching contract")?;
+        let contract = Contract::from(res.data);
+
+        if !contract.accepted {
+            return Err(eyre::eyre!(
+                "Contract {} has not been accepted",
+                contract.id
+            ));
+        }
+
+        Ok(Self::Planning(ContractMachine {
+            state: Planning,
+            db,
+            config,
+            ship,
+            contract,
+        }))
+    }
+
+    pub async fn step(self) -> Result<Self> {
+        match self {
+            ContractMachineWrapper::Planning(val) => val.plan().await,
+
+            ContractMachineWrapper::Travelling(val) => match val.state.machine {
+                TravelMachineWrapper::TravelComplete => val.arrive().await,
+                _ => Ok(ContractMachineWrapper::Travelling(Box::new(
+                    val.travel().await?,
+                ))),
+            },
+
+            ContractMachineWrapper::Buying(val) => Ok(ContractMachineWrapper::Travelling(
+                Box::new(val.buy().await?),
+            )),
+
+            ContractMachineWrapper::Delivering(val) => {
+                Ok(ContractMachineWrapper::Planning(val.deliver().await?))
+            }
+
+            ContractMachineWrapper::ContractFulfilled => {
+                Err(eyre::eyre!("Contract has already been fulfilled"))
+            }
+        }
+    }
+
+    /// Drives the machine until the contract is fulfilled.
+    pub async fn run(self) -> Result<()> {
+        let mut machine = self;
+        loop {
+            match machine {
+                ContractMachineWrapper::ContractFulfilled => {
+                    println!("Contract fulfilled!");
+                    return Ok(());
+                }
+                _ => machine = machine.step().await?,
+            }
+        }
+    }
+}
+
+pub struct ContractMachine<'a, S> {
+    pub state: S,
+    db: &'a DatabaseConnection,
+    config: Configuration,
+    ship: Ship,
+    contract: Contract,
+}
+
+impl<'a, S> ContractMachine<'a, S> {
+    fn with_state<T>(self, state: T) -> ContractMachine<'a, T> {
+        ContractMachine {
+            state,
+            db: self.db,
+            config: self.config,
+            ship: self.ship,
+            contract: self.contract,
+        }
+    }
+
+    async fn travel_to(
+        self,
+        destination: Location,
+        leg: Leg,
+    ) -> Result<ContractMachine<'a, Travelling<'a>>> {
+        let machine = TravelMachineWrapper::new(
+            self.config.clone(),
+            self.db,
+            destination,
+            self.ship.symbol.as_str(),
+        )
+        .await?;
+
+        Ok(self.with_state(Travelling { machine, leg }))
+    }
+}
+
+pub struct Planning;
+impl<'a> ContractMachine<'a, Planning> {
+    /// Refreshes the contract and decides what to fetch next.
+    pub async fn plan(mut self) -> Result<ContractMachineWrapper<'a>> {
+        let res = contracts_api::get_contract(&self.config, self.contract.id.as_str())
+            .await
+            .wrap_err("Error fetching contract")?;
+        self.contract = Contract::from(res.data);
+
+        if self.contract.fulfilled {
+            return Ok(ContractMachineWrapper::ContractFulfilled);
+        }
+
+        let delivery = match self.contract.next_delivery() {
+            Some(delivery) => delivery.clone(),
+            None => {
+                contracts_api::fulfill_contract(&self.config, self.contract.id.as_str())
+                    .await
+                    .wrap_err("Error fulfilling contract")?;
+                return Ok(ContractMachineWrapper::ContractFulfilled);
+            }
+        };
+
+        let remaining = delivery.remaining();
+        let held = self.ship.cargo.units_of(&delivery.trade_symbol);
+        let wanted = remaining.min(held + self.ship.cargo.available());
+
+        if held >= wanted && held > 0 {
+            return Ok(ContractMachineWrapper::Travelling(Box::new(
+                self.travel_to(delivery.destination, Leg::Destination)
+                    .await?,
+            )));
+        }
+
+        // Nothing held and no room left, a trip would deliver nothing.
+        if wanted <= 0 {
+            return Err(eyre::eyre!(
+                "The hold of {} is full of cargo the contract doesn't need",
+                self.ship.symbol
+            ));
+        }
+
+        let system = delivery.destination.system_ident();
+        let market = actions::cheapest_market(self.db, system.as_str(), &delivery.trade_symbol)
+            .await?
+            .map(|(location, _)| location);
+
+        match market {
+            Some(market) => {
+                println!(
+                    "Buying {} {} at {}",
+                    wanted - held,
+                    delivery.trade_symbol,
+                    market
+                );
+                let leg = Leg::Market {
+                    symbol: delivery.trade_symbol,
+                    units: wanted - held,
+                    destination: delivery.destination,
+                };
+                Ok(ContractMachineWrapper::Travelling(Box::new(
+                    self.travel_to(market, leg).await?,
+                )))
+            }
+            None if held > 0 => Ok(ContractMachineWrapper::Travelling(Box::new(
+                self.travel_to(delivery.destination, Leg::Destination)
+                    .await?,
+            ))),
+            None => Err(eyre::eyre!(
+                "No known market sells {} in {}",
+                delivery.trade_symbol,
+                system
+            )),
+        }
+    }
+}
+
+pub enum Leg {
+    Market {
+        symbol: String,
+        units: i32,
+        destination: Location,
+    },
+    Destination,
+}
+
+pub struct Travelling<'a> {
+    machine: TravelMachineWrapper<'a>,
+    leg: Leg,
+}
+
+impl<'a> ContractMachine<'a, Travelling<'a>> {
+    pub async fn travel(mut self) -> Result<Self> {
+        self.state.machine = self.state.machine.step().await?;
+        Ok(self)
+    }
+
+    pub async fn arrive(mut self) -> Result<ContractMachineWrapper<'a>> {
+        let res = fleet_api::get_my_ship(&self.config, self.ship.symbol.as_str())
+            .await
+            .wrap_err("Error refreshing ship")?;
+        self.ship = Ship::from(res.data);
+
+        match std::mem::replace(&mut self.state.leg, Leg::Destination) {
+            Leg::Market {
+                symbol,
+                units,
+                destination,
+            } => Ok(ContractMachineWrapper::Buying(self.with_state(Buying {
+                symbol,
+                units,
+                destination,
+            }))),
+            Leg::Destination => Ok(ContractMachineWrapper::Delivering(
+                self.with_state(Delivering),
+            )),
+        }
+    }
+}
+
+pub struct Buying {
+    symbol: String,
+    units: i32,
+    destination: Location,
+}
+
+impl<'a> ContractMachine<'a, Buying> {
+    pub async fn buy(mut self) -> Result<ContractMachine<'a, Travelling<'a>>> {
+        actions::dock(&self.config, &mut self.ship).await?;
+        actions::buy_cargo(
+            &self.config,
+            self.db,
+            &mut self.ship,
+            self.state.symbol.as_str(),
+            self.state.units,
+        )
+        .await?;
+        actions::refuel(&self.config, &mut self.ship).await?;
+
+        let destination = self.state.destination.clone();
+        self.travel_to(destination, Leg::Destination).await
+    }
+}
+
+pub struct Delivering;
+impl<'a> ContractMachine<'a, Delivering> {
+    /// Hands over everything in the hold that is still owed at the current
+    /// waypoint.
+    pub async fn deliver(mut self) -> Result<ContractMachine<'a, Planning>> {
+        actions::dock(&self.config, &mut self.ship).await?;
+
+        for delivery in self.contract.terms.deliver.clone() {
+            let units = delivery
+                .remaining()
+                .min(self.ship.cargo.units_of(&delivery.trade_symbol));
+            if units <= 0 || delivery.destination != self.ship.nav.location {
+                continue;
+            }
+
+            let req = DeliverContractRequest::new(
+                self.ship.symbol.clone(),
+                delivery.trade_symbol.clone(),
+                units,
+            );
+            let res =
+                contracts_api::deliver_contract(&self.config, self.contract.id.as_str(), Some(req))
+                    .await
+                    .wrap_err_with(|| format!("Error delivering {}", delivery.trade_symbol))?;
+
+            println!("Delivered {} {}", units, delivery.trade_symbol);
+            self.ship.update_cargo(ShipCargo::from(res.data.cargo));
+            self.contract = Contract::from(res.data.contract);
+        }
+
+        Ok(self.with_state(Planning))
+    }
+}
@@ -1,2 +1,15 @@
+pub mod actions;
+pub mod behaviour;
+pub mod construction_machine;
+pub mod contract_machine;
+pub mod probe_machine;
+pub mod refinery_machine;
+pub mod siphon_machine;
 pub mod travel_machine;
+pub use self::behaviour::BehaviourMachine;
+pub use self::construction_machine::ConstructionMachineWrapper;
+pub use self::contract_machine::ContractMachineWrapper;
+pub use self::probe_machine::ProbeMachineWrapper;
+pub use self::refinery_machine::RefineryMachineWrapper;
+pub use self::siphon_machine::SiphonMachineWrapper;
 pub use self::travel_machine::TravelMachineWrapper;
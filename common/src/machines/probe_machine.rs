@@ -0,0 +1,288 @@
+use std::time::Duration;
+
+use eyre::{Context, Ok, Result};
+use openapi::apis::configuration::Configuration;
+use openapi::apis::{fleet_api, systems_api};
+use sea_orm::DatabaseConnection;
+
+use super::{actions, TravelMachineWrapper};
+use crate::models::{
+    Location, MarketSnapshot, Ship, ShipyardSnapshot, Waypoint, WaypointStaleness,
+    WaypointTraitSymbol,
+};
+
+/// A waypoint is not revisited until at least this long after the last visit,
+/// so a probe with a single waypoint does not hammer the market endpoint.
+const MIN_REVISIT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Cycles a satellite through the marketplaces and shipyards of its system,
+/// recording a market and shipyard snapshot on every visit.
+pub enum ProbeMachineWrapper<'a> {
+    Selecting(ProbeMachine<'a, Selecting>),
+    Travelling(Box<ProbeMachine<'a, Travelling<'a>>>),
+    Recording(ProbeMachine<'a, Recording>),
+}
+
+impl<'a> ProbeMachineWrapper<'a> {
+    /// Creates a probe for `ship_symbol`. When several probes share a system,
+    /// each is given a distinct `probe_index` out of `probe_count` and only
+    /// visits its own share of the waypoints.
+    pub async fn new(
+        config: Configuration,
+        db: &'a DatabaseConnection,
+        ship_symbol: &str,
+        probe_index: usize,
+        probe_count: usize,
+    ) -> Result<Self> {
+        let res = fleet_api::get_my_ship(&config, ship_symbol).await?;
+        let ship = Ship::from(res.data);
+        let system = ship.nav.location.system_ident();
+
+        let mut waypoints = crate::repository::get_probe_waypoints(db, system.as_str()).await?;
+        if waypoints.is_empty() {
+            actions::refresh_system_waypoints(&config, db, system.as_str()).await?;
+            waypoints = crate::repository::get_probe_waypoints(db, system.as_str()).await?;
+        }
+
+        let route = assign_waypoints(waypoints, probe_index, probe_count);
+        if route.is_empty() {
+            return Err(eyre::eyre!(
+                "No marketplaces or shipyards assigned to probe {} in {}",
+                ship_symbol,
+                system
+            ));
+        }
+
+        Ok(Self::Selecting(ProbeMachine {
+            state: Selecting,
+            config,
+            db,
+            ship,
+            system,
+            route,
+        }))
+    }
+
+    pub async fn step(self) -> Result<Self> {
+        match self {
+            ProbeMachineWrapper::Selecting(val) => Ok(ProbeMachineWrapper::Travelling(Box::new(
+                val.select().await?,
+            ))),
+
+            ProbeMachineWrapper::Travelling(val) => match val.state.machine {
+                TravelMachineWrapper::TravelComplete => {
+                    Ok(ProbeMachineWrapper::Recording(val.arrive()))
+                }
+                _ => Ok(ProbeMachineWrapper::Travelling(Box::new(
+                    val.travel().await?,
+                ))),
+            },
+
+            ProbeMachineWrapper::Recording(val) => {
+                Ok(ProbeMachineWrapper::Selecting(val.record().await?))
+            }
+        }
+    }
+
+    /// Drives the probe until an error occurs. Probes never complete on their own.
+    pub async fn run(self) -> Result<()> {
+        let mut machine = self;
+        loop {
+            machine = machine.step().await?;
+        }
+    }
+}
+
+pub struct ProbeMachine<'a, S> {
+    pub state: S,
+    db: &'a DatabaseConnection,
+    config: Configuration,
+    ship: Ship,
+    system: String,
+    route: Vec<Waypoint>,
+}
+
+impl<'a, S> ProbeMachine<'a, S> {
+    fn with_state<T>(self, state: T) -> ProbeMachine<'a, T> {
+        ProbeMachine {
+            state,
+            db: self.db,
+            config: self.config,
+            ship: self.ship,
+            system: self.system,
+            route: self.route,
+        }
+    }
+}
+
+pub struct Selecting;
+impl<'a> ProbeMachine<'a, Selecting> {
+    pub async fn select(self) -> Result<ProbeMachine<'a, Travelling<'a>>> {
+        let staleness =
+            crate::repository::get_waypoint_staleness(self.db, self.system.as_str()).await?;
+
+        let current = self.ship.nav.location.clone();
+        let target = next_waypoint(&self.route, &staleness, &current)
+            .ok_or_else(|| eyre::eyre!("Probe {} has no waypoints", self.ship.symbol))?;
+
+        if let Some(age) = staleness
+            .iter()
+            .find(|s| s.location == target.location)
+            .and_then(|s| s.staleness)
+        {
+            if let std::result::Result::Ok(age) = Duration::try_from(age) {
+                if age < MIN_REVISIT_INTERVAL {
+                    tokio::time::sleep(MIN_REVISIT_INTERVAL - age).await;
+                }
+            }
+        }
+
+        println!("Probe {} heading to {}", self.ship.symbol, target.location);
+        let destination = target.location.clone();
+        let machine = TravelMachineWrapper::new(
+            self.config.clone(),
+            self.db,
+            destination.clone(),
+            self.ship.symbol.as_str(),
+        )
+        .await?;
+
+        Ok(self.with_state(Travelling {
+            machine,
+            destination,
+        }))
+    }
+}
+
+pub struct Travelling<'a> {
+    machine: TravelMachineWrapper<'a>,
+    destination: Location,
+}
+
+impl<'a> ProbeMachine<'a, Travelling<'a>> {
+    pub async fn travel(mut self) -> Result<Self> {
+        self.state.machine = self.state.machine.step().await?;
+        Ok(self)
+    }
+
+    pub fn arrive(mut self) -> ProbeMachine<'a, Recording> {
+        let location = self.state.destination.clone();
+        self.ship.nav.location = location.clone();
+        self.with_state(Recording { location })
+    }
+}
+
+pub struct Recording {
+    location: Location,
+}
+
+impl<'a> ProbeMachine<'a, Recording> {
+    pub async fn record(self) -> Result<ProbeMachine<'a, Selecting>> {
+        let location = &self.state.location;
+        let waypoint = self
+            .route
+            .iter()
+            .find(|w| &w.location == location)
+            .ok_or_else(|| eyre::eyre!("Waypoint {} is not on the probe route", location))?;
+
+        let has_trait =
+            |symbol: WaypointTraitSymbol| waypoint.traits.iter().any(|t| t.symbol == symbol);
+        let system = location.system_ident();
+        let waypoint_symbol = location.waypoint_ident();
+
+        if has_trait(WaypointTraitSymbol::Marketplace) {
+            let res =
+                systems_api::get_market(&self.config, system.as_str(), waypoint_symbol.as_str())
+                    .await
+                    .wrap_err("Error fetching market")?;
+
+            let snapshot = MarketSnapshot::from(*res.data);
+            println!(
+                "Recorded {} trade goods at {}",
+                snapshot.trade_goods.len(),
+                location
+            );
+            crate::repository::insert_market_snapshot(self.db, snapshot).await?;
+        }
+
+        if has_trait(WaypointTraitSymbol::Shipyard) {
+            let res =
+                systems_api::get_shipyard(&self.config, system.as_str(), waypoint_symbol.as_str())
+                    .await
+                    .wrap_err("Error fetching shipyard")?;
+
+            let snapshot = ShipyardSnapshot::from(*res.data);
+            println!("Recorded {} ships at {}", snapshot.ships.len(), location);
+            crate::repository::insert_shipyard_snapshot(self.db, snapshot).await?;
+        }
+
+        Ok(self.with_state(Selecting))
+    }
+}
+
+/// Splits the waypoints of a system between `count` probes. Waypoints are
+/// ordered by angle around the centre of the system and cut into contiguous
+/// arcs, so each probe covers a compact region and no waypoint is shared.
+pub fn assign_waypoints(waypoints: Vec<Waypoint>, index: usize, count: usize) -> Vec<Waypoint> {
+    if count == 0 || index >= count || waypoints.is_empty() {
+        return vec![];
+    }
+
+    let n = waypoints.len() as f64;
+    let cx = waypoints.iter().map(|w| w.x as f64).sum::<f64>() / n;
+    let cy = waypoints.iter().map(|w| w.y as f64).sum::<f64>() / n;
+
+    let mut waypoints = waypoints;
+    waypoints.sort_by(|a, b| {
+        let angle_a = (a.y as f64 - cy).atan2(a.x as f64 - cx);
+        let angle_b = (b.y as f64 - cy).atan2(b.x as f64 - cx);
+        angle_a.total_cmp(&angle_b).then_with(|| {
+            a.location
+                .waypoint_ident()
+                .cmp(&b.location.waypoint_ident())
+        })
+    });
+
+    let len = waypoints.len();
+    let start = index * len / count;
+    let end = (index + 1) * len / count;
+
+    waypoints
+        .into_iter()
+        .skip(start)
+        .take(end - start)
+        .collect()
+}
+
+/// Picks the waypoint on the route that has gone longest without a visit.
+/// Unvisited waypoints come first and ties are broken by distance from the
+/// probe, which makes the first lap a nearest-neighbour tour that later laps
+/// then repeat.
+fn next_waypoint<'w>(
+    route: &'w [Waypoint],
+    staleness: &[WaypointStaleness],
+    current: &Location,
+) -> Option<&'w Waypoint> {
+    let position = route
+        .iter()
+        .find(|w| &w.location == current)
+        .map(|w| (w.x, w.y));
+
+    let last_visited = |w: &Waypoint| {
+        staleness
+            .iter()
+            .find(|s| s.location == w.location)
+            .and_then(|s| s.last_visited)
+    };
+
+    let distance = |w: &Waypoint| match position {
+        Some((x, y)) => (((w.x - x).pow(2) + (w.y - y).pow(2)) as f64).sqrt(),
+        None => 0f64,
+    };
+
+    route.iter().min_by(|a, b| {
+        last_visited(a)
+            .cmp(&last_visited(b))
+            .then_with(|| distance(a).total_cmp(&distance(b)))
+    })
+}
@@ -0,0 +1,345 @@
+use std::time::Duration;
+
+use eyre::{Context, Ok, Result};
+use openapi::apis::configuration::Configuration;
+use openapi::apis::fleet_api;
+use openapi::models::ship_refine_request::Produce;
+use openapi::models::{ShipRefineRequest, TransferCargoRequest};
+use sea_orm::DatabaseConnection;
+
+use super::{actions, TravelMachineWrapper};
+use crate::models::ship::{ShipCargo, ShipCooldown};
+use crate::models::{Location, Ship, ShipNavStatus, ShipRole, Waypoint};
+
+const REFINERY_MODULE: &str = "MODULE_ORE_REFINERY";
+
+/// Units of an ore consumed by a single refine.
+const REFINE_INPUT_UNITS: i32 = 30;
+
+/// How long the refinery waits for haulers before checking again.
+const COLLECT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Parks a refinery at a station waypoint, pulls ore out of the haulers that
+/// arrive there, refines it and takes the finished goods to market.
+pub enum RefineryMachineWrapper<'a> {
+    Travelling(Box<RefineryMachine<'a, Travelling<'a>>>),
+    Collecting(RefineryMachine<'a, Collecting>),
+    Refining(RefineryMachine<'a, Refining>),
+    Selling(RefineryMachine<'a, Selling>),
+}
+
+impl<'a> RefineryMachineWrapper<'a> {
+    pub async fn new(
+        config: Configuration,
+        db: &'a DatabaseConnection,
+        ship_symbol: &str,
+        station: Location,
+    ) -> Result<Self> {
+        let res = fleet_api::get_my_ship(&config, ship_symbol).await?;
+        let ship = Ship::from(res.data);
+
+        if !ship.has_module(REFINERY_MODULE) {
+            return Err(eyre::eyre!("Ship {} has no ore refinery", ship_symbol));
+        }
+
+        let system = station.system_ident();
+        let waypoints = actions::system_waypoints(&config, db, system.as_str()).await?;
+
+        let machine = RefineryMachine {
+            state: Collecting,
+            db,
+            config,
+            ship,
+            station,
+            waypoints,
+        };
+
+        if machine.ship.nav.location == machine.station
+            && machine.ship.nav.status != ShipNavStatus::InTransit
+        {
+            Ok(Self::Collecting(machine))
+        } else {
+            let destination = machine.station.clone();
+            Ok(Self::Travelling(Box::new(
+                machine.travel_to(destination, Leg::Station).await?,
+            )))
+        }
+    }
+
+    pub async fn step(self) -> Result<Self> {
+        match self {
+            RefineryMachineWrapper::Travelling(val) => match val.state.machine {
+                TravelMachineWrapper::TravelComplete => val.arrive().await,
+                _ => Ok(RefineryMachineWrapper::Travelling(Box::new(
+                    val.travel().await?,
+                ))),
+            },
+
+            RefineryMachineWrapper::Collecting(val) => val.collect().await,
+
+            RefineryMachineWrapper::Refining(val) => {
+                Ok(RefineryMachineWrapper::Collecting(val.refine().await?))
+            }
+
+            RefineryMachineWrapper::Selling(val) => Ok(RefineryMachineWrapper::Travelling(
+                Box::new(val.sell().await?),
+            )),
+        }
+    }
+
+    /// Drives the refinery loop until an error occurs.
+    pub async fn run(self) -> Result<()> {
+        let mut machine = self;
+        loop {
+            machine = machine.step().await?;
+        }
+    }
+}
+
+pub struct RefineryMachine<'a, S> {
+    pub state: S,
+    db: &'a DatabaseConnection,
+    config: Configuration,
+    ship: Ship,
+    station: Location,
+    waypoints: Vec<Waypoint>,
+}
+
+impl<'a, S> RefineryMachine<'a, S> {
+    fn with_state<T>(self, state: T) -> RefineryMachine<'a, T> {
+        RefineryMachine {
+            state,
+            db: self.db,
+            config: self.config,
+            ship: self.ship,
+            station: self.station,
+            waypoints: self.waypoints,
+        }
+    }
+
+    async fn travel_to(
+        self,
+        destination: Location,
+        leg: Leg,
+    ) -> Result<RefineryMachine<'a, Travelling<'a>>> {
+        let machine = TravelMachineWrapper::new(
+            self.config.clone(),
+            self.db,
+            destination,
+            self.ship.symbol.as_str(),
+        )
+        .await?;
+
+        Ok(self.with_state(Travelling { machine, leg }))
+    }
+
+    /// The next ore in the hold with enough units for a refine.
+    fn refinable(&self) -> Option<(String, Produce)> {
+        self.ship
+            .cargo
+            .inventory
+            .iter()
+            .filter(|i| i.units >= REFINE_INPUT_UNITS)
+            .find_map(|i| produce_for(&i.symbol).map(|p| (i.symbol.clone(), p)))
+    }
+
+    fn has_outputs(&self) -> bool {
+        self.ship
+            .cargo
+            .inventory
+            .iter()
+            .any(|i| is_refined_good(&i.symbol))
+    }
+}
+
+pub enum Leg {
+    Station,
+    Market,
+}
+
+pub struct Travelling<'a> {
+    machine: TravelMachineWrapper<'a>,
+    leg: Leg,
+}
+
+impl<'a> RefineryMachine<'a, Travelling<'a>> {
+    pub async fn travel(mut self) -> Result<Self> {
+        self.state.machine = self.state.machine.step().await?;
+        Ok(self)
+    }
+
+    pub async fn arrive(mut self) -> Result<RefineryMachineWrapper<'a>> {
+        let res = fleet_api::get_my_ship(&self.config, self.ship.symbol.as_str())
+            .await
+            .wrap_err("Error refreshing ship")?;
+        self.ship = Ship::from(res.data);
+
+        match self.state.leg {
+            Leg::Station => Ok(RefineryMachineWrapper::Collecting(
+                self.with_state(Collecting),
+            )),
+            Leg::Market => Ok(RefineryMachineWrapper::Selling(self.with_state(Selling))),
+        }
+    }
+}
+
+pub struct Collecting;
+impl<'a> RefineryMachine<'a, Collecting> {
+    /// Transfers ore from every hauler waiting at the station, then decides
+    /// whether to refine, sell or keep waiting.
+    pub async fn collect(mut self) -> Result<RefineryMachineWrapper<'a>> {
+        actions::orbit(&self.config, &mut self.ship).await?;
+
+        let haulers = actions::get_all_ships(&self.config)
+            .await?
+            .into_iter()
+            .filter(|s| {
+                s.symbol != self.ship.symbol
+                    && s.registration.role == ShipRole::Hauler
+                    && s.nav.location == self.station
+                    && s.nav.status != ShipNavStatus::InTransit
+            })
+            .collect::<Vec<Ship>>();
+
+        let mut collected = false;
+        for mut hauler in haulers {
+            actions::orbit(&self.config, &mut hauler).await?;
+
+            for item in hauler.cargo.inventory.clone() {
+                let units = item.units.min(self.ship.cargo.available());
+                if units <= 0 || produce_for(&item.symbol).is_none() {
+                    continue;
+                }
+
+                let req = TransferCargoRequest::new(
+                    actions::trade_symbol(&item.symbol)?,
+                    units,
+                    self.ship.symbol.clone(),
+                );
+
+                let res =
+                    fleet_api::transfer_cargo(&self.config, hauler.symbol.as_str(), Some(req))
+                        .await
+                        .wrap_err_with(|| {
+                            format!("Error transferring cargo from {}", hauler.symbol)
+                        })?;
+
+                hauler.update_cargo(ShipCargo::from(res.data.cargo));
+                self.ship.cargo.current += units;
+                collected = true;
+                println!("Collected {} {} from {}", units, item.symbol, hauler.symbol);
+            }
+        }
+
+        // Transfers only report the hauler's cargo, so refresh our own hold
+        // before deciding what to refine.
+        if collected {
+            let res = fleet_api::get_my_ship_cargo(&self.config, self.ship.symbol.as_str())
+                .await
+                .wrap_err("Error fetching cargo")?;
+            self.ship.update_cargo(ShipCargo::from(res.data));
+        }
+
+        if self.refinable().is_some() {
+            return Ok(RefineryMachineWrapper::Refining(self.with_state(Refining)));
+        }
+
+        if self.has_outputs() {
+            let outputs = self
+                .ship
+                .cargo
+                .inventory
+                .iter()
+                .filter(|i| is_refined_good(&i.symbol))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let market =
+                actions::choose_market(self.db, &self.waypoints, &self.station, &outputs).await?;
+
+            println!("Selling refined goods at {}", market);
+            return Ok(RefineryMachineWrapper::Travelling(Box::new(
+                self.travel_to(market, Leg::Market).await?,
+            )));
+        }
+
+        tokio::time::sleep(COLLECT_INTERVAL).await;
+        Ok(RefineryMachineWrapper::Collecting(self))
+    }
+}
+
+pub struct Refining;
+impl<'a> RefineryMachine<'a, Refining> {
+    pub async fn refine(mut self) -> Result<RefineryMachine<'a, Collecting>> {
+        let (ore, produce) = match self.refinable() {
+            Some(refinable) => refinable,
+            None => return Ok(self.with_state(Collecting)),
+        };
+
+        actions::wait_for_cooldown(&self.ship.cooldown).await;
+
+        let req = ShipRefineRequest::new(produce);
+        let res = fleet_api::ship_refine(&self.config, self.ship.symbol.as_str(), Some(req))
+            .await
+            .wrap_err_with(|| format!("Error refining {}", ore))?;
+
+        let data = res.data;
+        for produced in data.produced.iter() {
+            println!("Refined {} {}", produced.units, produced.trade_symbol);
+        }
+
+        self.ship.update_cargo(ShipCargo::from(data.cargo));
+        self.ship.cooldown = ShipCooldown::from(data.cooldown);
+        Ok(self.with_state(Collecting))
+    }
+}
+
+pub struct Selling;
+impl<'a> RefineryMachine<'a, Selling> {
+    pub async fn sell(mut self) -> Result<RefineryMachine<'a, Travelling<'a>>> {
+        actions::dock(&self.config, &mut self.ship).await?;
+        let transactions =
+            actions::sell_cargo(&self.config, self.db, &mut self.ship, is_refined_good).await?;
+        actions::refuel(&self.config, &mut self.ship).await?;
+
+        if transactions.is_empty() {
+            return Err(eyre::eyre!(
+                "Could not sell any refined goods at {}",
+                self.ship.nav.location
+            ));
+        }
+
+        let destination = self.station.clone();
+        self.travel_to(destination, Leg::Station).await
+    }
+}
+
+fn produce_for(ore: &str) -> Option<Produce> {
+    match ore {
+        "IRON_ORE" => Some(Produce::Iron),
+        "COPPER_ORE" => Some(Produce::Copper),
+        "SILVER_ORE" => Some(Produce::Silver),
+        "GOLD_ORE" => Some(Produce::Gold),
+        "ALUMINUM_ORE" => Some(Produce::Aluminum),
+        "PLATINUM_ORE" => Some(Produce::Platinum),
+        "URANITE_ORE" => Some(Produce::Uranite),
+        "MERITIUM_ORE" => Some(Produce::Meritium),
+        "HYDROCARBON" => Some(Produce::Fuel),
+        _ => None,
+    }
+}
+
+fn is_refined_good(symbol: &str) -> bool {
+    matches!(
+        symbol,
+        "IRON"
+            | "COPPER"
+            | "SILVER"
+            | "GOLD"
+            | "ALUMINUM"
+            | "PLATINUM"
+            | "URANITE"
+            | "MERITIUM"
+            | "FUEL"
+    )
+}
@@ -0,0 +1,221 @@
+use eyre::{Context, Ok, Result};
+use openapi::apis::configuration::Configuration;
+use openapi::apis::fleet_api;
+use sea_orm::DatabaseConnection;
+
+use super::{actions, TravelMachineWrapper};
+use crate::models::ship::{ShipCargo, ShipCooldown};
+use crate::models::{Location, Ship, ShipNavStatus, Waypoint, WaypointType};
+
+const GAS_SIPHON_MOUNT: &str = "MOUNT_GAS_SIPHON";
+
+/// Siphons gas at the nearest gas giant until the hold is full, sells the
+/// haul at the best paying market and heads back out.
+pub enum SiphonMachineWrapper<'a> {
+    Travelling(Box<SiphonMachine<'a, Travelling<'a>>>),
+    Siphoning(SiphonMachine<'a, Siphoning>),
+    Selling(SiphonMachine<'a, Selling>),
+}
+
+impl<'a> SiphonMachineWrapper<'a> {
+    pub async fn new(
+        config: Configuration,
+        db: &'a DatabaseConnection,
+        ship_symbol: &str,
+    ) -> Result<Self> {
+        let res = fleet_api::get_my_ship(&config, ship_symbol).await?;
+        let ship = Ship::from(res.data);
+
+        if !ship.has_mount(GAS_SIPHON_MOUNT) {
+            return Err(eyre::eyre!(
+                "Ship {} has no gas siphon mounted",
+                ship_symbol
+            ));
+        }
+
+        let system = ship.nav.location.system_ident();
+        let waypoints = actions::system_waypoints(&config, db, system.as_str()).await?;
+        let gas_giants = waypoints
+            .iter()
+            .filter(|w| w.waypoint_type == WaypointType::GasGiant)
+            .cloned()
+            .collect::<Vec<Waypoint>>();
+
+        let gas_giant = actions::nearest_waypoint(&gas_giants, &ship.nav.location, &waypoints)
+            .ok_or_else(|| eyre::eyre!("No gas giant found in {}", system))?
+            .location
+            .clone();
+
+        let machine = SiphonMachine {
+            state: Siphoning,
+            db,
+            config,
+            ship,
+            gas_giant,
+            waypoints,
+        };
+
+        if machine.ship.nav.location == machine.gas_giant
+            && machine.ship.nav.status != ShipNavStatus::InTransit
+        {
+            Ok(Self::Siphoning(machine))
+        } else {
+            let destination = machine.gas_giant.clone();
+            Ok(Self::Travelling(Box::new(
+                machine.travel_to(destination, Leg::GasGiant).await?,
+            )))
+        }
+    }
+
+    pub async fn step(self) -> Result<Self> {
+        match self {
+            SiphonMachineWrapper::Travelling(val) => match val.state.machine {
+                TravelMachineWrapper::TravelComplete => val.arrive().await,
+                _ => Ok(SiphonMachineWrapper::Travelling(Box::new(
+                    val.travel().await?,
+                ))),
+            },
+
+            SiphonMachineWrapper::Siphoning(val) => match val.ship.cargo.is_full() {
+                true => Ok(SiphonMachineWrapper::Travelling(Box::new(
+                    val.head_to_market().await?,
+                ))),
+                false => Ok(SiphonMachineWrapper::Siphoning(val.siphon().await?)),
+            },
+
+            SiphonMachineWrapper::Selling(val) => Ok(SiphonMachineWrapper::Travelling(Box::new(
+                val.sell().await?,
+            ))),
+        }
+    }
+
+    /// Drives the siphon loop until an error occurs.
+    pub async fn run(self) -> Result<()> {
+        let mut machine = self;
+        loop {
+            machine = machine.step().await?;
+        }
+    }
+}
+
+pub struct SiphonMachine<'a, S> {
+    pub state: S,
+    db: &'a DatabaseConnection,
+    config: Configuration,
+    ship: Ship,
+    gas_giant: Location,
+    waypoints: Vec<Waypoint>,
+}
+
+impl<'a, S> SiphonMachine<'a, S> {
+    fn with_state<T>(self, state: T) -> SiphonMachine<'a, T> {
+        SiphonMachine {
+            state,
+            db: self.db,
+            config: self.config,
+            ship: self.ship,
+            gas_giant: self.gas_giant,
+            waypoints: self.waypoints,
+        }
+    }
+
+    async fn travel_to(
+        self,
+        destination: Location,
+        leg: Leg,
+    ) -> Result<SiphonMachine<'a, Travelling<'a>>> {
+        let machine = TravelMachineWrapper::new(
+            self.config.clone(),
+            self.db,
+            destination,
+            self.ship.symbol.as_str(),
+        )
+        .await?;
+
+        Ok(self.with_state(Travelling { machine, leg }))
+    }
+}
+
+pub enum Leg {
+    GasGiant,
+    Market,
+}
+
+pub struct Travelling<'a> {
+    machine: TravelMachineWrapper<'a>,
+    leg: Leg,
+}
+
+impl<'a> SiphonMachine<'a, Travelling<'a>> {
+    pub async fn travel(mut self) -> Result<Self> {
+        self.state.machine = self.state.machine.step().await?;
+        Ok(self)
+    }
+
+    pub async fn arrive(mut self) -> Result<SiphonMachineWrapper<'a>> {
+        let res = fleet_api::get_my_ship(&self.config, self.ship.symbol.as_str())
+            .await
+            .wrap_err("Error refreshing ship")?;
+        self.ship = Ship::from(res.data);
+
+        match self.state.leg {
+            Leg::GasGiant => Ok(SiphonMachineWrapper::Siphoning(self.with_state(Siphoning))),
+            Leg::Market => Ok(SiphonMachineWrapper::Selling(self.with_state(Selling))),
+        }
+    }
+}
+
+pub struct Siphoning;
+impl<'a> SiphonMachine<'a, Siphoning> {
+    pub async fn siphon(mut self) -> Result<Self> {
+        actions::orbit(&self.config, &mut self.ship).await?;
+        actions::wait_for_cooldown(&self.ship.cooldown).await;
+
+        let res = fleet_api::siphon_resources(&self.config, self.ship.symbol.as_str())
+            .await
+            .wrap_err("Error siphoning resources")?;
+
+        let data = res.data;
+        println!(
+            "Siphoned {} {}",
+            data.siphon.r#yield.units,
+            data.siphon.r#yield.symbol.to_string()
+        );
+
+        self.ship.update_cargo(ShipCargo::from(data.cargo));
+        self.ship.cooldown = ShipCooldown::from(data.cooldown);
+        Ok(self)
+    }
+
+    pub async fn head_to_market(self) -> Result<SiphonMachine<'a, Travelling<'a>>> {
+        let market = actions::choose_market(
+            self.db,
+            &self.waypoints,
+            &self.gas_giant,
+            &self.ship.cargo.inventory,
+        )
+        .await?;
+
+        println!("Hold full, selling at {}", market);
+        self.travel_to(market, Leg::Market).await
+    }
+}
+
+pub struct Selling;
+impl<'a> SiphonMachine<'a, Selling> {
+    pub async fn sell(mut self) -> Result<SiphonMachine<'a, Travelling<'a>>> {
+        actions::dock(&self.config, &mut self.ship).await?;
+        actions::sell_cargo(&self.config, self.db, &mut self.ship, |_| true).await?;
+        actions::refuel(&self.config, &mut self.ship).await?;
+
+        if self.ship.cargo.is_full() {
+            return Err(eyre::eyre!(
+                "Could not sell any cargo at {}",
+                self.ship.nav.location
+            ));
+        }
+
+        let destination = self.gas_giant.clone();
+        self.travel_to(destination, Leg::GasGiant).await
+    }
+}
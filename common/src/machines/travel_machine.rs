@@ -4,6 +4,7 @@ use eyre::{Context, Ok, Result};
 use openapi::apis::configuration::Configuration;
 use openapi::apis::fleet_api;
 use sea_orm::DatabaseConnection;
+use time::OffsetDateTime;
 
 use crate::models::ship::{ShipNav, ShipNavStatus};
 use crate::models::{Location, Ship};
@@ -87,11 +88,11 @@ impl<'a> TravelMachineWrapper<'a> {
             TravelMachineWrapper::InTransit(val) => {
                 if let Some(t) = val.ship.nav.route.time_to_arrival {
                     println!("Ship is in transit. The ship will arrive in {}", t);
-                    Ok(TravelMachineWrapper::InTransit(val))
                 } else {
                     println!("Ship is in transit. Arrival time not available");
-                    Ok(TravelMachineWrapper::InTransit(val))
                 }
+
+                Ok(TravelMachineWrapper::Arrived(val.arrive().await?))
             }
             TravelMachineWrapper::Arrived(val) => {
                 Ok(TravelMachineWrapper::Docked(val.dock().await?))
@@ -219,6 +220,32 @@ impl<'a> TravelMachine<'a, InTransit> {
             state: InTransit,
         }
     }
+
+    pub async fn arrive(mut self) -> Result<TravelMachine<'a, Arrived>> {
+        let remaining = self.ship.nav.route.arrival - OffsetDateTime::now_utc();
+        if remaining.is_positive() {
+            tokio::time::sleep(remaining.unsigned_abs()).await;
+        }
+
+        let res = fleet_api::get_ship_nav(&self.config, self.ship.symbol.as_str())
+            .await
+            .wrap_err("Error fetching ship nav")?;
+
+        let ship_nav = ShipNav::from(res.data);
+        if ship_nav.status == ShipNavStatus::InTransit {
+            return Err(eyre::eyre!("Ship has not arrived yet!"));
+        }
+
+        self.ship.update_nav(ship_nav);
+        println!("Ship arrived");
+
+        Ok(TravelMachine::<Arrived>::new(
+            self.config,
+            self.db,
+            self.destination,
+            self.ship,
+        ))
+    }
 }
 
 pub struct Arrived;
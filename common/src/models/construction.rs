@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use tabled::Tabled;
+
+use super::Location;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Construction {
+    pub location: Location,
+    pub materials: Vec<ConstructionMaterial>,
+    pub is_complete: bool,
+}
+
+impl Construction {
+    /// True once every material has been fully supplied, even if the server
+    /// has not flagged the construction as complete yet.
+    pub fn is_fulfilled(&self) -> bool {
+        self.is_complete || self.materials.iter().all(|m| m.remaining() == 0)
+    }
+
+    pub fn next_material(&self) -> Option<&ConstructionMaterial> {
+        self.materials.iter().find(|m| m.remaining() > 0)
+    }
+}
+
+impl From<Box<openapi::models::Construction>> for Construction {
+    fn from(value: Box<openapi::models::Construction>) -> Self {
+        Self {
+            location: Location::parse(value.symbol),
+            materials: value
+                .materials
+                .into_iter()
+                .map(ConstructionMaterial::from)
+                .collect(),
+            is_complete: value.is_complete,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct ConstructionMaterial {
+    pub trade_symbol: String,
+    pub required: i32,
+    pub fulfilled: i32,
+}
+
+impl ConstructionMaterial {
+    pub fn remaining(&self) -> i32 {
+        (self.required - self.fulfilled).max(0)
+    }
+}
+
+impl From<openapi::models::ConstructionMaterial> for ConstructionMaterial {
+    fn from(value: openapi::models::ConstructionMaterial) -> Self {
+        Self {
+            trade_symbol: value.trade_symbol.to_string(),
+            required: value.required,
+            fulfilled: value.fulfilled,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct ConstructionMaterialStatus {
+    pub trade_symbol: String,
+    pub required: i32,
+    pub fulfilled: i32,
+    pub remaining: i32,
+    #[tabled(display_with = "super::display_option")]
+    pub unit_price: Option<i32>,
+    #[tabled(display_with = "super::display_option")]
+    pub estimated_cost: Option<i64>,
+}
+
+impl ConstructionMaterialStatus {
+    pub fn new(material: &ConstructionMaterial, unit_price: Option<i32>) -> Self {
+        let remaining = material.remaining();
+
+        Self {
+            trade_symbol: material.trade_symbol.clone(),
+            required: material.required,
+            fulfilled: material.fulfilled,
+            remaining,
+            unit_price,
+            estimated_cost: unit_price.map(|p| p as i64 * remaining as i64),
+        }
+    }
+}
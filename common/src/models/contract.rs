@@ -1,9 +1,12 @@
 use core::panic;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use tabled::Tabled;
 use time::format_description::well_known::Iso8601;
 use time::OffsetDateTime;
 
+use super::Location;
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
 pub struct Contract {
     pub id: String,
@@ -12,53 +15,140 @@ pub struct Contract {
     #[tabled(skip)]
     pub contract_type: ContractType,
 
-    // pub terms: Box<crate::models::ContractTerms>,
+    #[tabled(display_with = "display_terms")]
+    pub terms: ContractTerms,
     pub accepted: bool,
     pub fulfilled: bool,
+    #[serde(with = "time::serde::rfc3339")]
     pub expiration: OffsetDateTime,
 
     #[tabled(display_with = "super::display_option")]
+    #[serde(with = "time::serde::rfc3339::option")]
     pub deadline_to_accept: Option<OffsetDateTime>,
 }
 
+impl Contract {
+    /// True once every good has been fully delivered, even if the contract
+    /// has not been fulfilled yet.
+    pub fn is_delivered(&self) -> bool {
+        self.terms.deliver.iter().all(|d| d.remaining() == 0)
+    }
+
+    pub fn next_delivery(&self) -> Option<&ContractDeliverGood> {
+        self.terms.deliver.iter().find(|d| d.remaining() > 0)
+    }
+}
+
+/// Payment and deliveries on one line, so the table stays flat.
+fn display_terms(terms: &ContractTerms) -> String {
+    let deliveries = terms
+        .deliver
+        .iter()
+        .map(|d| {
+            format!(
+                "{} {}/{} to {}",
+                d.trade_symbol, d.units_fulfilled, d.units_required, d.destination
+            )
+        })
+        .collect::<Vec<String>>();
+
+    format!(
+        "{}+{} credits: {}",
+        terms.payment.on_accepted,
+        terms.payment.on_fulfilled,
+        deliveries.join(", ")
+    )
+}
+
+fn parse_date(date: &str) -> OffsetDateTime {
+    match OffsetDateTime::parse(date, &Iso8601::DEFAULT) {
+        Ok(date) => date,
+        Err(e) => {
+            println!("Error formatting date: {:?}", e);
+            panic!()
+        }
+    }
+}
+
 impl From<Box<openapi::models::Contract>> for Contract {
     fn from(value: Box<openapi::models::Contract>) -> Self {
-        let expiration = OffsetDateTime::parse(value.expiration.as_str(), &Iso8601::DEFAULT);
-        let expiration = match expiration {
-            Ok(date) => date,
-            Err(e) => {
-                println!("Error formatting date: {:?}", e);
-                panic!()
-            }
-        };
-
-        let deadline_to_accept = match value.deadline_to_accept {
-            Some(date) => {
-                let date = OffsetDateTime::parse(date.as_str(), &Iso8601::DEFAULT);
-                match date {
-                    Ok(date) => Some(date),
-                    Err(e) => {
-                        println!("Error formatting date: {:?}", e);
-                        panic!()
-                    }
-                }
-            }
-            None => None,
-        };
-
         Self {
             id: value.id,
             faction_symbol: value.faction_symbol,
+            terms: ContractTerms::from(value.terms),
             accepted: value.accepted,
             fulfilled: value.fulfilled,
-            expiration,
-            deadline_to_accept,
+            expiration: parse_date(value.expiration.as_str()),
+            deadline_to_accept: value.deadline_to_accept.as_deref().map(parse_date),
             contract_type: value.r#type.into(),
         }
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Tabled)]
+impl From<openapi::models::Contract> for Contract {
+    fn from(value: openapi::models::Contract) -> Self {
+        Self::from(Box::new(value))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ContractTerms {
+    #[serde(with = "time::serde::rfc3339")]
+    pub deadline: OffsetDateTime,
+    pub payment: ContractPayment,
+    pub deliver: Vec<ContractDeliverGood>,
+}
+
+impl From<Box<openapi::models::ContractTerms>> for ContractTerms {
+    fn from(value: Box<openapi::models::ContractTerms>) -> Self {
+        Self {
+            deadline: parse_date(value.deadline.as_str()),
+            payment: ContractPayment {
+                on_accepted: value.payment.on_accepted,
+                on_fulfilled: value.payment.on_fulfilled,
+            },
+            deliver: value
+                .deliver
+                .unwrap_or_default()
+                .into_iter()
+                .map(ContractDeliverGood::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled, JsonSchema)]
+pub struct ContractPayment {
+    pub on_accepted: i32,
+    pub on_fulfilled: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct ContractDeliverGood {
+    pub trade_symbol: String,
+    pub destination: Location,
+    pub units_required: i32,
+    pub units_fulfilled: i32,
+}
+
+impl ContractDeliverGood {
+    pub fn remaining(&self) -> i32 {
+        (self.units_required - self.units_fulfilled).max(0)
+    }
+}
+
+impl From<openapi::models::ContractDeliverGood> for ContractDeliverGood {
+    fn from(value: openapi::models::ContractDeliverGood) -> Self {
+        Self {
+            trade_symbol: value.trade_symbol,
+            destination: Location::parse(value.destination_symbol),
+            units_required: value.units_required,
+            units_fulfilled: value.units_fulfilled,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Tabled, JsonSchema)]
 pub enum ContractType {
     Procurement,
     Transport,
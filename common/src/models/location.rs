@@ -6,8 +6,11 @@ use tabled::Tabled;
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled, JsonSchema)]
 pub struct Location {
+    #[schemars(regex(pattern = r"^[A-Z0-9]+$"))]
     pub sector: String,
+    #[schemars(regex(pattern = r"^[A-Z0-9]+$"))]
     pub system: String,
+    #[schemars(regex(pattern = r"^[A-Z0-9]+$"))]
     pub waypoint: String,
 }
 
@@ -57,12 +60,20 @@ impl FromStr for Location {
 pub struct Waypoint {
     pub location: Location,
     pub waypoint_type: WaypointType,
-    #[tabled(skip)]
+    #[tabled(display_with = "display_traits")]
     pub traits: Vec<WaypointTrait>,
     pub x: i32,
     pub y: i32,
 }
 
+fn display_traits(traits: &[WaypointTrait]) -> String {
+    traits
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
 impl From<openapi::models::Waypoint> for Waypoint {
     fn from(value: openapi::models::Waypoint) -> Self {
         let traits = value
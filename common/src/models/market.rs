@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use tabled::Tabled;
+use time::format_description::well_known::Iso8601;
+use time::{Duration, OffsetDateTime};
+
+use super::Location;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MarketSnapshot {
+    pub location: Location,
+    pub trade_goods: Vec<MarketTradeGood>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub recorded_at: OffsetDateTime,
+}
+
+impl From<openapi::models::Market> for MarketSnapshot {
+    fn from(value: openapi::models::Market) -> Self {
+        let trade_goods = value
+            .trade_goods
+            .unwrap_or_default()
+            .into_iter()
+            .map(MarketTradeGood::from)
+            .collect::<Vec<MarketTradeGood>>();
+
+        Self {
+            location: Location::parse(value.symbol),
+            trade_goods,
+            recorded_at: OffsetDateTime::now_utc(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct MarketTradeGood {
+    pub symbol: String,
+    pub trade_type: String,
+    pub supply: String,
+    pub trade_volume: i32,
+    pub purchase_price: i32,
+    pub sell_price: i32,
+}
+
+impl From<openapi::models::MarketTradeGood> for MarketTradeGood {
+    fn from(value: openapi::models::MarketTradeGood) -> Self {
+        let trade_type = match value.r#type {
+            openapi::models::market_trade_good::Type::Export => "EXPORT",
+            openapi::models::market_trade_good::Type::Import => "IMPORT",
+            openapi::models::market_trade_good::Type::Exchange => "EXCHANGE",
+        };
+
+        Self {
+            symbol: value.symbol.to_string(),
+            trade_type: trade_type.to_string(),
+            supply: value.supply.to_string(),
+            trade_volume: value.trade_volume,
+            purchase_price: value.purchase_price,
+            sell_price: value.sell_price,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ShipyardSnapshot {
+    pub location: Location,
+    pub ships: Vec<ShipyardShip>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub recorded_at: OffsetDateTime,
+}
+
+impl From<openapi::models::Shipyard> for ShipyardSnapshot {
+    fn from(value: openapi::models::Shipyard) -> Self {
+        let ships = value
+            .ships
+            .unwrap_or_default()
+            .into_iter()
+            .map(ShipyardShip::from)
+            .collect::<Vec<ShipyardShip>>();
+
+        Self {
+            location: Location::parse(value.symbol),
+            ships,
+            recorded_at: OffsetDateTime::now_utc(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct ShipyardShip {
+    pub ship_type: String,
+    pub supply: String,
+    pub purchase_price: i32,
+}
+
+impl From<openapi::models::ShipyardShip> for ShipyardShip {
+    fn from(value: openapi::models::ShipyardShip) -> Self {
+        Self {
+            ship_type: value.r#type.to_string(),
+            supply: value.supply.to_string(),
+            purchase_price: value.purchase_price,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct WaypointStaleness {
+    pub location: Location,
+    #[tabled(display_with = "super::display_option")]
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_visited: Option<OffsetDateTime>,
+    #[tabled(display_with = "super::display_option")]
+    pub staleness: Option<Duration>,
+}
+
+impl WaypointStaleness {
+    pub fn new(location: Location, last_visited: Option<OffsetDateTime>) -> Self {
+        let staleness = last_visited.map(|t| OffsetDateTime::now_utc() - t);
+
+        Self {
+            location,
+            last_visited,
+            staleness,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct MarketTransaction {
+    pub waypoint: Location,
+    pub ship_symbol: String,
+    pub trade_symbol: String,
+    pub transaction_type: String,
+    pub units: i32,
+    pub price_per_unit: i32,
+    pub total_price: i32,
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+}
+
+impl From<Box<openapi::models::MarketTransaction>> for MarketTransaction {
+    fn from(value: Box<openapi::models::MarketTransaction>) -> Self {
+        let transaction_type = match value.r#type {
+            openapi::models::market_transaction::Type::Purchase => "PURCHASE",
+            openapi::models::market_transaction::Type::Sell => "SELL",
+        };
+
+        let timestamp = OffsetDateTime::parse(value.timestamp.as_str(), &Iso8601::DEFAULT)
+            .unwrap_or_else(|_| OffsetDateTime::now_utc());
+
+        Self {
+            waypoint: Location::parse(value.waypoint_symbol),
+            ship_symbol: value.ship_symbol,
+            trade_symbol: value.trade_symbol,
+            transaction_type: transaction_type.to_string(),
+            units: value.units,
+            price_per_unit: value.price_per_unit,
+            total_price: value.total_price,
+            timestamp,
+        }
+    }
+}
@@ -1,8 +1,13 @@
 pub mod agent;
 pub use self::agent::Agent;
 
+pub mod construction;
+pub use self::construction::{Construction, ConstructionMaterial, ConstructionMaterialStatus};
+
 pub mod contract;
-pub use self::contract::{Contract, ContractType};
+pub use self::contract::{
+    Contract, ContractDeliverGood, ContractPayment, ContractTerms, ContractType,
+};
 
 pub mod faction;
 pub use self::faction::FactionSymbol;
@@ -10,12 +15,17 @@ pub use self::faction::FactionSymbol;
 pub mod location;
 pub use self::location::{Location, Waypoint, WaypointTrait, WaypointTraitSymbol, WaypointType};
 
+pub mod market;
+pub use self::market::{MarketSnapshot, MarketTransaction, ShipyardSnapshot, WaypointStaleness};
+
 pub mod ship;
 pub use self::ship::Ship;
 pub use self::ship::ShipNavFlightMode;
 pub use self::ship::ShipNavStatus;
 pub use self::ship::ShipRole;
+pub use self::ship::ShipType;
 
+use serde::Serialize;
 use std::fmt::Display;
 
 fn display_option<T: Display>(o: &Option<T>) -> String {
@@ -24,3 +34,11 @@ fn display_option<T: Display>(o: &Option<T>) -> String {
         None => format!("{}", "N/A"),
     }
 }
+
+/// The wire name of a generated enum that has no `ToString` implementation.
+fn symbol_string<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        _ => String::new(),
+    }
+}
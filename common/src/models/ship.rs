@@ -14,12 +14,22 @@ pub struct Ship {
     pub symbol: String,
     #[tabled(inline)]
     pub registration: ShipRegistration,
+    /// Symbol of the ship's frame, e.g. `FRAME_PROBE`.
+    #[tabled(skip)]
+    #[serde(default)]
+    pub frame: String,
     #[tabled(inline)]
     pub nav: ShipNav,
     #[tabled(inline)]
     pub cargo: ShipCargo,
     #[tabled(inline)]
     pub fuel: ShipFuel,
+    #[tabled(skip)]
+    pub cooldown: ShipCooldown,
+    #[tabled(skip)]
+    pub modules: Vec<ShipModule>,
+    #[tabled(skip)]
+    pub mounts: Vec<ShipMount>,
 }
 
 impl From<Box<openapi::models::Ship>> for Ship {
@@ -33,9 +43,13 @@ impl From<openapi::models::Ship> for Ship {
         Self {
             symbol: value.symbol,
             registration: ShipRegistration::from(value.registration),
+            frame: super::symbol_string(&value.frame.symbol),
             nav: ShipNav::from(value.nav),
             cargo: ShipCargo::from(value.cargo),
             fuel: ShipFuel::from(value.fuel),
+            cooldown: ShipCooldown::from(value.cooldown),
+            modules: value.modules.into_iter().map(ShipModule::from).collect(),
+            mounts: value.mounts.into_iter().map(ShipMount::from).collect(),
         }
     }
 }
@@ -44,6 +58,22 @@ impl Ship {
     pub fn update_nav(&mut self, nav: ShipNav) {
         self.nav = nav;
     }
+
+    pub fn update_cargo(&mut self, cargo: ShipCargo) {
+        self.cargo = cargo;
+    }
+
+    pub fn update_fuel(&mut self, fuel: ShipFuel) {
+        self.fuel = fuel;
+    }
+
+    pub fn has_mount(&self, prefix: &str) -> bool {
+        self.mounts.iter().any(|m| m.symbol.starts_with(prefix))
+    }
+
+    pub fn has_module(&self, prefix: &str) -> bool {
+        self.modules.iter().any(|m| m.symbol.starts_with(prefix))
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
@@ -103,6 +133,17 @@ impl From<openapi::models::ShipNavFlightMode> for ShipNavFlightMode {
     }
 }
 
+impl From<ShipNavFlightMode> for openapi::models::ShipNavFlightMode {
+    fn from(value: ShipNavFlightMode) -> Self {
+        match value {
+            ShipNavFlightMode::Drift => Self::Drift,
+            ShipNavFlightMode::Stealth => Self::Stealth,
+            ShipNavFlightMode::Cruise => Self::Cruise,
+            ShipNavFlightMode::Burn => Self::Burn,
+        }
+    }
+}
+
 impl Display for ShipNavFlightMode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let string = match self {
@@ -126,8 +167,10 @@ pub struct ShipNavRoute {
     #[tabled(skip)]
     pub origin: ShipNavRouteWayPoint,
     #[tabled(skip)]
+    #[serde(with = "time::serde::rfc3339")]
     pub arrival: OffsetDateTime,
     #[tabled(skip)]
+    #[serde(with = "time::serde::rfc3339")]
     pub departure_time: OffsetDateTime,
 }
 
@@ -283,6 +326,104 @@ impl FromStr for ShipRole {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled, JsonSchema)]
+pub enum ShipType {
+    Probe,
+    MiningDrone,
+    SiphonDrone,
+    Interceptor,
+    LightHauler,
+    CommandFrigate,
+    Explorer,
+    HeavyFreighter,
+    LightShuttle,
+    OreHound,
+    RefiningFreighter,
+    Surveyor,
+}
+
+impl From<openapi::models::ShipType> for ShipType {
+    fn from(value: openapi::models::ShipType) -> Self {
+        match value {
+            openapi::models::ShipType::Probe => Self::Probe,
+            openapi::models::ShipType::MiningDrone => Self::MiningDrone,
+            openapi::models::ShipType::SiphonDrone => Self::SiphonDrone,
+            openapi::models::ShipType::Interceptor => Self::Interceptor,
+            openapi::models::ShipType::LightHauler => Self::LightHauler,
+            openapi::models::ShipType::CommandFrigate => Self::CommandFrigate,
+            openapi::models::ShipType::Explorer => Self::Explorer,
+            openapi::models::ShipType::HeavyFreighter => Self::HeavyFreighter,
+            openapi::models::ShipType::LightShuttle => Self::LightShuttle,
+            openapi::models::ShipType::OreHound => Self::OreHound,
+            openapi::models::ShipType::RefiningFreighter => Self::RefiningFreighter,
+            openapi::models::ShipType::Surveyor => Self::Surveyor,
+        }
+    }
+}
+
+impl From<ShipType> for openapi::models::ShipType {
+    fn from(value: ShipType) -> Self {
+        match value {
+            ShipType::Probe => Self::Probe,
+            ShipType::MiningDrone => Self::MiningDrone,
+            ShipType::SiphonDrone => Self::SiphonDrone,
+            ShipType::Interceptor => Self::Interceptor,
+            ShipType::LightHauler => Self::LightHauler,
+            ShipType::CommandFrigate => Self::CommandFrigate,
+            ShipType::Explorer => Self::Explorer,
+            ShipType::HeavyFreighter => Self::HeavyFreighter,
+            ShipType::LightShuttle => Self::LightShuttle,
+            ShipType::OreHound => Self::OreHound,
+            ShipType::RefiningFreighter => Self::RefiningFreighter,
+            ShipType::Surveyor => Self::Surveyor,
+        }
+    }
+}
+
+impl Display for ShipType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let string = match self {
+            ShipType::Probe => "SHIP_PROBE",
+            ShipType::MiningDrone => "SHIP_MINING_DRONE",
+            ShipType::SiphonDrone => "SHIP_SIPHON_DRONE",
+            ShipType::Interceptor => "SHIP_INTERCEPTOR",
+            ShipType::LightHauler => "SHIP_LIGHT_HAULER",
+            ShipType::CommandFrigate => "SHIP_COMMAND_FRIGATE",
+            ShipType::Explorer => "SHIP_EXPLORER",
+            ShipType::HeavyFreighter => "SHIP_HEAVY_FREIGHTER",
+            ShipType::LightShuttle => "SHIP_LIGHT_SHUTTLE",
+            ShipType::OreHound => "SHIP_ORE_HOUND",
+            ShipType::RefiningFreighter => "SHIP_REFINING_FREIGHTER",
+            ShipType::Surveyor => "SHIP_SURVEYOR",
+        };
+
+        write!(f, "{}", string)
+    }
+}
+
+impl FromStr for ShipType {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "SHIP_PROBE" => Ok(Self::Probe),
+            "SHIP_MINING_DRONE" => Ok(Self::MiningDrone),
+            "SHIP_SIPHON_DRONE" => Ok(Self::SiphonDrone),
+            "SHIP_INTERCEPTOR" => Ok(Self::Interceptor),
+            "SHIP_LIGHT_HAULER" => Ok(Self::LightHauler),
+            "SHIP_COMMAND_FRIGATE" => Ok(Self::CommandFrigate),
+            "SHIP_EXPLORER" => Ok(Self::Explorer),
+            "SHIP_HEAVY_FREIGHTER" => Ok(Self::HeavyFreighter),
+            "SHIP_LIGHT_SHUTTLE" => Ok(Self::LightShuttle),
+            "SHIP_ORE_HOUND" => Ok(Self::OreHound),
+            "SHIP_REFINING_FREIGHTER" => Ok(Self::RefiningFreighter),
+            "SHIP_SURVEYOR" => Ok(Self::Surveyor),
+
+            _ => Err(eyre::eyre!("Unknown ship type {}", s)),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled, JsonSchema)]
 pub enum ShipNavStatus {
     InTransit,
@@ -335,6 +476,26 @@ pub struct ShipCargo {
     pub current: i32,
     #[tabled(rename = "Cargo Capacity")]
     pub capacity: i32,
+    #[tabled(skip)]
+    pub inventory: Vec<ShipCargoItem>,
+}
+
+impl ShipCargo {
+    pub fn is_full(&self) -> bool {
+        self.current >= self.capacity
+    }
+
+    pub fn available(&self) -> i32 {
+        self.capacity - self.current
+    }
+
+    pub fn units_of(&self, symbol: &str) -> i32 {
+        self.inventory
+            .iter()
+            .filter(|i| i.symbol == symbol)
+            .map(|i| i.units)
+            .sum()
+    }
 }
 
 impl From<Box<openapi::models::ShipCargo>> for ShipCargo {
@@ -342,6 +503,90 @@ impl From<Box<openapi::models::ShipCargo>> for ShipCargo {
         Self {
             current: value.units,
             capacity: value.capacity,
+            inventory: value
+                .inventory
+                .into_iter()
+                .map(ShipCargoItem::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct ShipCargoItem {
+    pub symbol: String,
+    pub units: i32,
+}
+
+impl From<openapi::models::ShipCargoItem> for ShipCargoItem {
+    fn from(value: openapi::models::ShipCargoItem) -> Self {
+        Self {
+            symbol: value.symbol.to_string(),
+            units: value.units,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct ShipCooldown {
+    pub remaining_seconds: i32,
+    #[tabled(display_with = "super::display_option")]
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expiration: Option<OffsetDateTime>,
+}
+
+impl ShipCooldown {
+    /// Time left until the reactor is ready again, measured from now rather
+    /// than from when the cooldown was fetched.
+    pub fn remaining(&self) -> std::time::Duration {
+        match self.expiration {
+            Some(expiration) => {
+                let remaining = expiration - OffsetDateTime::now_utc();
+                match remaining.is_positive() {
+                    true => remaining.unsigned_abs(),
+                    false => std::time::Duration::ZERO,
+                }
+            }
+            None => std::time::Duration::from_secs(self.remaining_seconds.max(0) as u64),
+        }
+    }
+}
+
+impl From<Box<openapi::models::Cooldown>> for ShipCooldown {
+    fn from(value: Box<openapi::models::Cooldown>) -> Self {
+        let expiration = value
+            .expiration
+            .and_then(|date| OffsetDateTime::parse(date.as_str(), &Iso8601::DEFAULT).ok());
+
+        Self {
+            remaining_seconds: value.remaining_seconds,
+            expiration,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct ShipModule {
+    pub symbol: String,
+}
+
+impl From<openapi::models::ShipModule> for ShipModule {
+    fn from(value: openapi::models::ShipModule) -> Self {
+        Self {
+            symbol: super::symbol_string(&value.symbol),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct ShipMount {
+    pub symbol: String,
+}
+
+impl From<openapi::models::ShipMount> for ShipMount {
+    fn from(value: openapi::models::ShipMount) -> Self {
+        Self {
+            symbol: super::symbol_string(&value.symbol),
         }
     }
 }
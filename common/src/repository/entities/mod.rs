@@ -2,4 +2,10 @@
 
 pub mod prelude;
 
+pub mod construction;
+pub mod construction_material;
+pub mod market_snapshot;
+pub mod market_transaction;
+pub mod shipyard_snapshot;
 pub mod waypoint;
+pub mod waypoint_visit;
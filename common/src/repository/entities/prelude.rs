@@ -1,3 +1,9 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.10
 
+pub use super::construction::Entity as Construction;
+pub use super::construction_material::Entity as ConstructionMaterial;
+pub use super::market_snapshot::Entity as MarketSnapshot;
+pub use super::market_transaction::Entity as MarketTransaction;
+pub use super::shipyard_snapshot::Entity as ShipyardSnapshot;
 pub use super::waypoint::Entity as Waypoint;
+pub use super::waypoint_visit::Entity as WaypointVisit;
@@ -4,13 +4,17 @@ use sea_orm::*;
 pub mod entities;
 use entities::{prelude::*, *};
 
-use crate::models::WaypointTraitSymbol::Marketplace;
-use crate::models::WaypointType;
+use crate::models::WaypointTraitSymbol::{Marketplace, Shipyard};
+use crate::models::{Location, WaypointStaleness, WaypointType};
 
 const DATABASE_URL: &str = "sqlite://spacetraders-db.sqlite?mode=rwc";
 
 pub async fn connect() -> Result<DatabaseConnection> {
-    let db = Database::connect(DATABASE_URL).await?;
+    connect_to(DATABASE_URL).await
+}
+
+pub async fn connect_to(url: &str) -> Result<DatabaseConnection> {
+    let db = Database::connect(url).await?;
     Ok(db)
 }
 
@@ -38,7 +42,23 @@ pub async fn insert_waypoints(
         })
         .collect::<Vec<waypoint::ActiveModel>>();
 
-    let res = Waypoint::insert_many(to_insert).exec(db).await?;
+    if to_insert.is_empty() {
+        return Ok(());
+    }
+
+    let res = Waypoint::insert_many(to_insert)
+        .on_conflict(
+            sea_query::OnConflict::column(waypoint::Column::Location)
+                .update_columns([
+                    waypoint::Column::Type,
+                    waypoint::Column::Traits,
+                    waypoint::Column::X,
+                    waypoint::Column::Y,
+                ])
+                .to_owned(),
+        )
+        .exec(db)
+        .await?;
     println!("Result: {:?}", res);
 
     Ok(())
@@ -53,12 +73,397 @@ pub async fn get_marketplace_waypoints(
         .await
         .wrap_err("Failed to query waypoints")?
         .into_iter()
-        .map(|w| crate::models::Waypoint {
-            location: crate::models::Location::parse(w.location),
-            waypoint_type: WaypointType::parse(w.r#type),
-            traits: crate::models::WaypointTrait::parse_vec(w.traits),
-            x: w.x,
-            y: w.y,
-        })
+        .map(to_waypoint)
+        .collect::<Vec<crate::models::Waypoint>>())
+}
+
+pub async fn get_system_waypoints(
+    db: &DatabaseConnection,
+    system: &str,
+) -> Result<Vec<crate::models::Waypoint>> {
+    Ok(Waypoint::find()
+        .filter(waypoint::Column::Location.starts_with(format!("{}-", system)))
+        .all(db)
+        .await
+        .wrap_err("Failed to query waypoints")?
+        .into_iter()
+        .map(to_waypoint)
         .collect::<Vec<crate::models::Waypoint>>())
 }
+
+/// Waypoints in the system that have a marketplace or a shipyard, i.e. the
+/// waypoints a probe should keep visiting.
+pub async fn get_probe_waypoints(
+    db: &DatabaseConnection,
+    system: &str,
+) -> Result<Vec<crate::models::Waypoint>> {
+    Ok(Waypoint::find()
+        .filter(waypoint::Column::Location.starts_with(format!("{}-", system)))
+        .filter(
+            Condition::any()
+                .add(waypoint::Column::Traits.contains(Marketplace.to_string()))
+                .add(waypoint::Column::Traits.contains(Shipyard.to_string())),
+        )
+        .all(db)
+        .await
+        .wrap_err("Failed to query waypoints")?
+        .into_iter()
+        .map(to_waypoint)
+        .collect::<Vec<crate::models::Waypoint>>())
+}
+
+pub async fn insert_market_snapshot(
+    db: &DatabaseConnection,
+    snapshot: crate::models::MarketSnapshot,
+) -> Result<()> {
+    let location = snapshot.location.waypoint_ident();
+    let to_insert = snapshot
+        .trade_goods
+        .into_iter()
+        .map(|g| market_snapshot::ActiveModel {
+            location: ActiveValue::Set(location.clone()),
+            symbol: ActiveValue::Set(g.symbol),
+            trade_type: ActiveValue::Set(g.trade_type),
+            supply: ActiveValue::Set(g.supply),
+            trade_volume: ActiveValue::Set(g.trade_volume),
+            purchase_price: ActiveValue::Set(g.purchase_price),
+            sell_price: ActiveValue::Set(g.sell_price),
+            recorded_at: ActiveValue::Set(snapshot.recorded_at),
+            ..Default::default()
+        })
+        .collect::<Vec<market_snapshot::ActiveModel>>();
+
+    if !to_insert.is_empty() {
+        MarketSnapshot::insert_many(to_insert)
+            .exec(db)
+            .await
+            .wrap_err("Failed to insert market snapshot")?;
+    }
+
+    record_waypoint_visit(db, &snapshot.location, snapshot.recorded_at).await
+}
+
+pub async fn insert_shipyard_snapshot(
+    db: &DatabaseConnection,
+    snapshot: crate::models::ShipyardSnapshot,
+) -> Result<()> {
+    let location = snapshot.location.waypoint_ident();
+    let to_insert = snapshot
+        .ships
+        .into_iter()
+        .map(|s| shipyard_snapshot::ActiveModel {
+            location: ActiveValue::Set(location.clone()),
+            ship_type: ActiveValue::Set(s.ship_type),
+            supply: ActiveValue::Set(s.supply),
+            purchase_price: ActiveValue::Set(s.purchase_price),
+            recorded_at: ActiveValue::Set(snapshot.recorded_at),
+            ..Default::default()
+        })
+        .collect::<Vec<shipyard_snapshot::ActiveModel>>();
+
+    if !to_insert.is_empty() {
+        ShipyardSnapshot::insert_many(to_insert)
+            .exec(db)
+            .await
+            .wrap_err("Failed to insert shipyard snapshot")?;
+    }
+
+    record_waypoint_visit(db, &snapshot.location, snapshot.recorded_at).await
+}
+
+/// The latest known price of the ship type at every shipyard selling it.
+pub async fn get_shipyard_prices(
+    db: &DatabaseConnection,
+    ship_type: &str,
+) -> Result<Vec<(Location, i32)>> {
+    let rows = ShipyardSnapshot::find()
+        .filter(shipyard_snapshot::Column::ShipType.eq(ship_type))
+        .order_by_desc(shipyard_snapshot::Column::RecordedAt)
+        .all(db)
+        .await
+        .wrap_err("Failed to query shipyard snapshots")?;
+
+    let mut prices: Vec<(Location, i32)> = vec![];
+    for row in rows {
+        let location = Location::parse(row.location);
+        if !prices.iter().any(|(l, _)| l == &location) {
+            prices.push((location, row.purchase_price));
+        }
+    }
+
+    Ok(prices)
+}
+
+/// The most recent market snapshot of every marketplace in the system that a
+/// probe (or any other ship) has visited.
+pub async fn get_latest_market_snapshots(
+    db: &DatabaseConnection,
+    system: &str,
+) -> Result<Vec<crate::models::MarketSnapshot>> {
+    let rows = MarketSnapshot::find()
+        .filter(market_snapshot::Column::Location.starts_with(format!("{}-", system)))
+        .order_by_desc(market_snapshot::Column::RecordedAt)
+        .all(db)
+        .await
+        .wrap_err("Failed to query market snapshots")?;
+
+    let mut snapshots: Vec<crate::models::MarketSnapshot> = vec![];
+    for row in rows {
+        let location = Location::parse(row.location.clone());
+        let good = crate::models::market::MarketTradeGood {
+            symbol: row.symbol,
+            trade_type: row.trade_type,
+            supply: row.supply,
+            trade_volume: row.trade_volume,
+            purchase_price: row.purchase_price,
+            sell_price: row.sell_price,
+        };
+
+        match snapshots.iter_mut().find(|s| s.location == location) {
+            Some(snapshot) if snapshot.recorded_at == row.recorded_at => {
+                snapshot.trade_goods.push(good)
+            }
+            Some(_) => {}
+            None => snapshots.push(crate::models::MarketSnapshot {
+                location,
+                trade_goods: vec![good],
+                recorded_at: row.recorded_at,
+            }),
+        }
+    }
+
+    Ok(snapshots)
+}
+
+pub async fn insert_transaction(
+    db: &DatabaseConnection,
+    transaction: &crate::models::MarketTransaction,
+) -> Result<()> {
+    let to_insert = market_transaction::ActiveModel {
+        ship_symbol: ActiveValue::Set(transaction.ship_symbol.clone()),
+        location: ActiveValue::Set(transaction.waypoint.waypoint_ident()),
+        trade_symbol: ActiveValue::Set(transaction.trade_symbol.clone()),
+        transaction_type: ActiveValue::Set(transaction.transaction_type.clone()),
+        units: ActiveValue::Set(transaction.units),
+        price_per_unit: ActiveValue::Set(transaction.price_per_unit),
+        total_price: ActiveValue::Set(transaction.total_price),
+        recorded_at: ActiveValue::Set(transaction.timestamp),
+        ..Default::default()
+    };
+
+    MarketTransaction::insert(to_insert)
+        .exec(db)
+        .await
+        .wrap_err("Failed to insert market transaction")?;
+
+    Ok(())
+}
+
+/// The latest transactions of every ship, newest first.
+pub async fn get_recent_transactions(
+    db: &DatabaseConnection,
+    limit: u64,
+) -> Result<Vec<crate::models::MarketTransaction>> {
+    let rows = MarketTransaction::find()
+        .order_by_desc(market_transaction::Column::RecordedAt)
+        .limit(limit)
+        .all(db)
+        .await
+        .wrap_err("Failed to query market transactions")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|t| crate::models::MarketTransaction {
+            waypoint: Location::parse(t.location),
+            ship_symbol: t.ship_symbol,
+            trade_symbol: t.trade_symbol,
+            transaction_type: t.transaction_type,
+            units: t.units,
+            price_per_unit: t.price_per_unit,
+            total_price: t.total_price,
+            timestamp: t.recorded_at,
+        })
+        .collect())
+}
+
+pub async fn record_waypoint_visit(
+    db: &DatabaseConnection,
+    location: &Location,
+    visited_at: time::OffsetDateTime,
+) -> Result<()> {
+    let visit = waypoint_visit::ActiveModel {
+        location: ActiveValue::Set(location.waypoint_ident()),
+        visited_at: ActiveValue::Set(visited_at),
+    };
+
+    WaypointVisit::insert(visit)
+        .on_conflict(
+            sea_query::OnConflict::column(waypoint_visit::Column::Location)
+                .update_column(waypoint_visit::Column::VisitedAt)
+                .to_owned(),
+        )
+        .exec(db)
+        .await
+        .wrap_err("Failed to record waypoint visit")?;
+
+    Ok(())
+}
+
+/// How long ago each marketplace or shipyard in the system was last visited.
+/// Waypoints that have never been visited have no staleness and sort first.
+pub async fn get_waypoint_staleness(
+    db: &DatabaseConnection,
+    system: &str,
+) -> Result<Vec<WaypointStaleness>> {
+    let waypoints = get_probe_waypoints(db, system).await?;
+    let visits = WaypointVisit::find()
+        .filter(waypoint_visit::Column::Location.starts_with(format!("{}-", system)))
+        .all(db)
+        .await
+        .wrap_err("Failed to query waypoint visits")?;
+
+    let mut staleness = waypoints
+        .into_iter()
+        .map(|w| {
+            let last_visited = visits
+                .iter()
+                .find(|v| v.location == w.location.waypoint_ident())
+                .map(|v| v.visited_at);
+
+            WaypointStaleness::new(w.location, last_visited)
+        })
+        .collect::<Vec<WaypointStaleness>>();
+
+    staleness.sort_by_key(|s| s.last_visited);
+    Ok(staleness)
+}
+
+fn to_waypoint(w: waypoint::Model) -> crate::models::Waypoint {
+    crate::models::Waypoint {
+        location: Location::parse(w.location),
+        waypoint_type: WaypointType::parse(w.r#type),
+        traits: crate::models::WaypointTrait::parse_vec(w.traits),
+        x: w.x,
+        y: w.y,
+    }
+}
+
+pub async fn save_construction(
+    db: &DatabaseConnection,
+    construction: &crate::models::Construction,
+) -> Result<()> {
+    let location = construction.location.waypoint_ident();
+    let model = construction::ActiveModel {
+        location: ActiveValue::Set(location.clone()),
+        system: ActiveValue::Set(construction.location.system_ident()),
+        is_complete: ActiveValue::Set(construction.is_complete),
+        updated_at: ActiveValue::Set(time::OffsetDateTime::now_utc()),
+    };
+
+    Construction::insert(model)
+        .on_conflict(
+            sea_query::OnConflict::column(construction::Column::Location)
+                .update_columns([
+                    construction::Column::IsComplete,
+                    construction::Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec(db)
+        .await
+        .wrap_err("Failed to save construction")?;
+
+    let materials = construction
+        .materials
+        .iter()
+        .map(|m| construction_material::ActiveModel {
+            location: ActiveValue::Set(location.clone()),
+            trade_symbol: ActiveValue::Set(m.trade_symbol.clone()),
+            required: ActiveValue::Set(m.required),
+            fulfilled: ActiveValue::Set(m.fulfilled),
+        })
+        .collect::<Vec<construction_material::ActiveModel>>();
+
+    if !materials.is_empty() {
+        ConstructionMaterial::insert_many(materials)
+            .on_conflict(
+                sea_query::OnConflict::columns([
+                    construction_material::Column::Location,
+                    construction_material::Column::TradeSymbol,
+                ])
+                .update_columns([
+                    construction_material::Column::Required,
+                    construction_material::Column::Fulfilled,
+                ])
+                .to_owned(),
+            )
+            .exec(db)
+            .await
+            .wrap_err("Failed to save construction materials")?;
+    }
+
+    Ok(())
+}
+
+/// The last recorded construction progress for the system, if any.
+pub async fn get_construction(
+    db: &DatabaseConnection,
+    system: &str,
+) -> Result<Option<crate::models::Construction>> {
+    let construction = Construction::find()
+        .filter(construction::Column::System.eq(system))
+        .one(db)
+        .await
+        .wrap_err("Failed to query construction")?;
+
+    let construction = match construction {
+        Some(construction) => construction,
+        None => return Ok(None),
+    };
+
+    let materials = ConstructionMaterial::find()
+        .filter(construction_material::Column::Location.eq(construction.location.clone()))
+        .all(db)
+        .await
+        .wrap_err("Failed to query construction materials")?
+        .into_iter()
+        .map(|m| crate::models::ConstructionMaterial {
+            trade_symbol: m.trade_symbol,
+            required: m.required,
+            fulfilled: m.fulfilled,
+        })
+        .collect::<Vec<crate::models::ConstructionMaterial>>();
+
+    Ok(Some(crate::models::Construction {
+        location: Location::parse(construction.location),
+        materials,
+        is_complete: construction.is_complete,
+    }))
+}
+
+/// Removes the agent's own row. Everything else in the database describes
+/// the universe and is shared between agents.
+pub async fn remove_agent(db: &DatabaseConnection, symbol: &str) -> Result<()> {
+    db.execute(Statement::from_sql_and_values(
+        db.get_database_backend(),
+        "DELETE FROM agents WHERE symbol = ?",
+        [symbol.into()],
+    ))
+    .await
+    .wrap_err("Failed to remove agent")?;
+
+    Ok(())
+}
+
+/// Removes everything that describes the current universe. Used after a
+/// server reset, when waypoints and markets are generated from scratch.
+pub async fn clear_universe(db: &DatabaseConnection) -> Result<()> {
+    Waypoint::delete_many().exec(db).await?;
+    WaypointVisit::delete_many().exec(db).await?;
+    MarketSnapshot::delete_many().exec(db).await?;
+    ShipyardSnapshot::delete_many().exec(db).await?;
+    ConstructionMaterial::delete_many().exec(db).await?;
+    Construction::delete_many().exec(db).await?;
+
+    Ok(())
+}
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS waypoint_visits;
+DROP TABLE IF EXISTS shipyard_snapshots;
+DROP TABLE IF EXISTS market_snapshots;
@@ -0,0 +1,29 @@
+CREATE TABLE IF NOT EXISTS market_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    location TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    trade_type TEXT NOT NULL,
+    supply TEXT NOT NULL,
+    trade_volume INTEGER NOT NULL,
+    purchase_price INTEGER NOT NULL,
+    sell_price INTEGER NOT NULL,
+    recorded_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS market_snapshots_location_idx ON market_snapshots (location, recorded_at);
+
+CREATE TABLE IF NOT EXISTS shipyard_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    location TEXT NOT NULL,
+    ship_type TEXT NOT NULL,
+    supply TEXT NOT NULL,
+    purchase_price INTEGER NOT NULL,
+    recorded_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS shipyard_snapshots_location_idx ON shipyard_snapshots (location, recorded_at);
+
+CREATE TABLE IF NOT EXISTS waypoint_visits (
+    location TEXT PRIMARY KEY NOT NULL,
+    visited_at DATETIME NOT NULL
+);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS construction_materials;
+DROP TABLE IF EXISTS constructions;
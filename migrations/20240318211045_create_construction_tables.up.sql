@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS constructions (
+    location TEXT PRIMARY KEY NOT NULL,
+    system TEXT NOT NULL,
+    is_complete BOOLEAN NOT NULL,
+    updated_at DATETIME NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS construction_materials (
+    location TEXT NOT NULL,
+    trade_symbol TEXT NOT NULL,
+    required INTEGER NOT NULL,
+    fulfilled INTEGER NOT NULL,
+    PRIMARY KEY (location, trade_symbol)
+);
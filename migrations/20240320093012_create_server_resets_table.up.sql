@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS server_resets (
+    reset_date TEXT PRIMARY KEY NOT NULL,
+    next_reset TEXT NOT NULL,
+    archive TEXT,
+    detected_at DATETIME NOT NULL
+);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS ship_checkpoints (
+    ship_symbol TEXT PRIMARY KEY NOT NULL,
+    behaviour TEXT NOT NULL,
+    params TEXT NOT NULL,
+    state TEXT NOT NULL,
+    finished BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at DATETIME NOT NULL
+);
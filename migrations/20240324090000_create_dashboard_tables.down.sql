@@ -0,0 +1,3 @@
+ALTER TABLE ship_checkpoints DROP COLUMN error;
+DROP TABLE IF EXISTS credit_history;
+DROP TABLE IF EXISTS market_transactions;
@@ -0,0 +1,24 @@
+CREATE TABLE IF NOT EXISTS market_transactions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    ship_symbol TEXT NOT NULL,
+    location TEXT NOT NULL,
+    trade_symbol TEXT NOT NULL,
+    transaction_type TEXT NOT NULL,
+    units INTEGER NOT NULL,
+    price_per_unit INTEGER NOT NULL,
+    total_price INTEGER NOT NULL,
+    recorded_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS market_transactions_recorded_at_idx ON market_transactions (recorded_at);
+
+CREATE TABLE IF NOT EXISTS credit_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    agent_symbol TEXT NOT NULL,
+    credits INTEGER NOT NULL,
+    recorded_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS credit_history_agent_idx ON credit_history (agent_symbol, recorded_at);
+
+ALTER TABLE ship_checkpoints ADD COLUMN error TEXT;
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS leaderboard_entries_agent_symbol;
+DROP TABLE IF EXISTS leaderboard_entries;
+DROP TABLE IF EXISTS status_snapshots;
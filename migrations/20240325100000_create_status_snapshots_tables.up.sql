@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS status_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    reset_date TEXT NOT NULL,
+    version TEXT NOT NULL,
+    agents INTEGER NOT NULL,
+    ships INTEGER NOT NULL,
+    systems INTEGER NOT NULL,
+    waypoints INTEGER NOT NULL,
+    recorded_at DATETIME NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS leaderboard_entries (
+    snapshot_id INTEGER NOT NULL REFERENCES status_snapshots (id) ON DELETE CASCADE,
+    board TEXT NOT NULL,
+    rank INTEGER NOT NULL,
+    agent_symbol TEXT NOT NULL,
+    value INTEGER NOT NULL,
+    PRIMARY KEY (snapshot_id, board, rank)
+);
+
+CREATE INDEX IF NOT EXISTS leaderboard_entries_agent_symbol ON leaderboard_entries (agent_symbol);
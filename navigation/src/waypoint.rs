@@ -1,4 +1,6 @@
-use openapi::apis::{self, systems_api::GetSystemWaypointsError};
+use openapi::apis;
+use openapi::apis::configuration::Configuration;
+use openapi::apis::systems_api::{self, GetSystemWaypointsError};
 use sqlx::SqlitePool;
 use thiserror::Error;
 
@@ -63,4 +65,33 @@ impl From<openapi::models::Waypoint> for Waypoint {
     }
 }
 
-pub async fn initialize_system_waypoints(system: String) {}
+/// Fetches every waypoint in the system and stores it.
+pub async fn initialize_system_waypoints(
+    config: &Configuration,
+    pool: &SqlitePool,
+    system: String,
+) -> Result<usize, WaypointError> {
+    let page = 1;
+    let limit = 20;
+
+    let res =
+        systems_api::get_system_waypoints(config, &system, Some(page), Some(limit), None, None)
+            .await?;
+
+    let mut waypoints = res.data;
+    let num_pages = (res.meta.total as f32 / limit as f32).ceil() as i32;
+
+    for n in (page + 1)..=num_pages {
+        let res =
+            systems_api::get_system_waypoints(config, &system, Some(n), Some(limit), None, None)
+                .await?;
+        waypoints.extend(res.data);
+    }
+
+    let count = waypoints.len();
+    for waypoint in waypoints {
+        Waypoint::from(waypoint).save(pool).await;
+    }
+
+    Ok(count)
+}
@@ -1,30 +1,66 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Duration;
 
-use common::crds::{Agent as K8sAgent, AgentSpec, AgentStatus, Manager};
+use common::crds::{
+    Agent as K8sAgent, AgentSpec, AgentStatus, Condition, Manager, Ship as K8sShip,
+};
 use common::models::FactionSymbol;
 use futures::StreamExt;
-use kube::api::{Patch, PatchParams};
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::chrono::Utc;
+use kube::api::{DeleteParams, ListParams, Patch, PatchParams};
 use kube::core::ObjectMeta;
 use kube::runtime::controller::Action;
+use kube::runtime::events::EventType;
+use kube::runtime::finalizer::{self, finalizer, Event as Finalizer};
 use kube::runtime::Controller;
 use kube::{Api, Client, Resource, ResourceExt};
+use openapi::apis;
+use openapi::apis::agents_api::{self, GetMyAgentError};
 use openapi::apis::configuration::Configuration;
-use snafu::{ResultExt, Snafu};
+use sea_orm::DatabaseConnection;
+use serde_json::json;
+use snafu::{ensure, ResultExt, Snafu};
 use tracing::{info, warn};
 
+use crate::backoff::Backoff;
+use crate::behaviour::BehaviourRegistry;
+use crate::contract::sync_contracts;
+use crate::metrics;
+use crate::registry::{owning_agent, ApiConfigRegistry};
 use crate::ship::patch_ship;
+use crate::status::{object_key, patch_conditions, publish_event, time_after};
+
+/// How often the fleet is listed again to pick up purchased or lost ships.
+const FLEET_SYNC_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Key of the bearer token inside the agent's token secret.
+const TOKEN_KEY: &str = "token";
+
+const AGENT_FINALIZER: &str = "spacetraders.io/agent-cleanup";
 
 pub struct AgentControllerData {
-    pub api_config: crate::ApiConfig,
+    pub api_config: ApiConfigRegistry,
     pub k8s_client: Client,
+    pub db: Arc<DatabaseConnection>,
+    pub behaviours: BehaviourRegistry,
+    pub backoff: Backoff,
 }
 
 impl AgentControllerData {
-    pub fn new(k8s_client: Client, api_config: crate::ApiConfig) -> Self {
+    pub fn new(
+        k8s_client: Client,
+        api_config: ApiConfigRegistry,
+        db: Arc<DatabaseConnection>,
+        behaviours: BehaviourRegistry,
+    ) -> Self {
         Self {
             api_config,
             k8s_client,
+            db,
+            behaviours,
+            backoff: Backoff::new(),
         }
     }
 }
@@ -48,13 +84,52 @@ pub enum AgentError {
 
     #[snafu(display("error registering agent {}", source))]
     RegisterAgentError { source: eyre::Report },
+
+    #[snafu(display("api config is not available yet"))]
+    ApiConfigNotAvailable,
+
+    #[snafu(display("error fetching ships {}", source))]
+    GetShipsError { source: eyre::Report },
+
+    #[snafu(display("error fetching agent {}", source))]
+    AgentApiError {
+        source: apis::Error<GetMyAgentError>,
+    },
+
+    #[snafu(display("error listing ships {}", source))]
+    ListShipError { source: kube::Error },
+
+    #[snafu(display("error deleting ship {}", source))]
+    DeleteShipError { source: kube::Error },
+
+    #[snafu(display("error accessing token secret {}", source))]
+    SecretError { source: kube::Error },
+
+    #[snafu(display("token secret is not valid utf-8 {}", source))]
+    TokenDecodeError { source: std::string::FromUtf8Error },
+
+    #[snafu(display("error syncing contracts {}", source))]
+    ContractSyncError { source: eyre::Report },
+
+    #[snafu(display("error removing agent from the database {}", source))]
+    DatabaseError { source: eyre::Report },
+
+    #[snafu(display("finalizer error {}", source))]
+    FinalizerError {
+        #[snafu(source(from(finalizer::Error<AgentError>, Box::new)))]
+        source: Box<finalizer::Error<AgentError>>,
+    },
 }
 
 pub(crate) async fn run_controller(data: Arc<AgentControllerData>) -> eyre::Result<()> {
     let agent = Api::<K8sAgent>::all(data.k8s_client.clone());
 
     Controller::new(agent.clone(), Default::default())
-        .run(reconcile, error_policy, data)
+        .run(
+            |obj, ctx| metrics::measure("agent", reconcile(obj, ctx)),
+            error_policy,
+            data,
+        )
         .for_each(|_| futures::future::ready(()))
         .await;
 
@@ -62,101 +137,410 @@ pub(crate) async fn run_controller(data: Arc<AgentControllerData>) -> eyre::Resu
 }
 
 pub(crate) fn error_policy(
-    _object: Arc<K8sAgent>,
+    object: Arc<K8sAgent>,
     err: &AgentError,
-    _ctx: Arc<AgentControllerData>,
+    ctx: Arc<AgentControllerData>,
 ) -> Action {
     warn!("{}", err);
-    Action::requeue(Duration::from_secs(5))
+    Action::requeue(ctx.backoff.next(object_key(object.as_ref()).as_str()))
 }
 
 pub(crate) async fn reconcile(
     agent: Arc<K8sAgent>,
     ctx: Arc<AgentControllerData>,
 ) -> Result<Action, AgentError> {
+    let ns = agent.namespace().unwrap_or("default".to_string());
+    let agent_api: Api<K8sAgent> = Api::namespaced(ctx.k8s_client.clone(), ns.as_str());
+
+    finalizer(&agent_api, AGENT_FINALIZER, agent, |event| async {
+        match event {
+            Finalizer::Apply(agent) => apply(agent, ctx.clone()).await,
+            Finalizer::Cleanup(agent) => cleanup(agent, ctx.clone()).await,
+        }
+    })
+    .await
+    .context(FinalizerSnafu)
+}
+
+async fn apply(agent: Arc<K8sAgent>, ctx: Arc<AgentControllerData>) -> Result<Action, AgentError> {
+    if let Some(wait) = pending_sync(agent.as_ref(), ctx.as_ref()).await {
+        return Ok(Action::requeue(wait));
+    }
+
     info!("reconciling agent");
+    let current = agent
+        .status
+        .as_ref()
+        .map(|s| s.conditions.clone())
+        .unwrap_or_default();
+
+    let registered = match reconcile_token(agent.clone(), ctx.clone()).await {
+        Ok(reason) => reason,
+        Err(err) => {
+            report_failure(&agent, &ctx, &current, Condition::REGISTERED, &err).await?;
+            return Err(err);
+        }
+    };
 
-    reconcile_token(agent.clone(), ctx.clone()).await?;
-    reconcile_starting_ships(agent.clone(), ctx.clone()).await?;
+    if let Err(err) = reconcile_ships(agent.clone(), ctx.clone()).await {
+        report_failure(&agent, &ctx, &current, Condition::SYNCED, &err).await?;
+        return Err(err);
+    }
+
+    ctx.backoff.reset(object_key(agent.as_ref()).as_str());
+
+    let conditions = vec![
+        Condition::new(
+            Condition::REGISTERED,
+            true,
+            registered,
+            format!("token for {} loaded", agent.spec.symbol),
+        ),
+        Condition::new(
+            Condition::SYNCED,
+            true,
+            "FleetSynced",
+            "ships, contracts and credits are up to date".to_string(),
+        ),
+        Condition::new(Condition::READY, true, "Reconciled", "".to_string()),
+        Condition::new(Condition::DEGRADED, false, "Reconciled", "".to_string()),
+    ];
+
+    patch_conditions(ctx.k8s_client.clone(), agent.as_ref(), &current, conditions)
+        .await
+        .context(AgentPatchSnafu)?;
 
     info!("agent reconciled");
+    Ok(Action::requeue(FLEET_SYNC_INTERVAL))
+}
+
+/// Stops the behaviours of the agent's ships and forgets everything the
+/// operator keeps about the agent. Every step is safe to repeat, so a
+/// cleanup that failed halfway is simply retried.
+async fn cleanup(
+    agent: Arc<K8sAgent>,
+    ctx: Arc<AgentControllerData>,
+) -> Result<Action, AgentError> {
+    info!("cleaning up agent {}", agent.name_any());
+
+    let ns = agent.namespace().unwrap_or("default".to_string());
+    let ship_api: Api<K8sShip> = Api::namespaced(ctx.k8s_client.clone(), ns.as_str());
+    let ships = ship_api
+        .list(&ListParams::default())
+        .await
+        .context(ListShipSnafu)?
+        .items;
+
+    for ship in ships
+        .iter()
+        .filter(|s| owning_agent(s.owner_references()) == Some(agent.name_any()))
+    {
+        ctx.behaviours.stop(object_key(ship).as_str()).await;
+    }
+
+    common::repository::remove_agent(ctx.db.as_ref(), agent.spec.symbol.as_str())
+        .await
+        .context(DatabaseSnafu)?;
+
+    ctx.api_config
+        .remove(ns.as_str(), agent.name_any().as_str())
+        .await;
+    ctx.backoff.reset(object_key(agent.as_ref()).as_str());
+
+    publish_event(
+        ctx.k8s_client.clone(),
+        agent.as_ref(),
+        EventType::Normal,
+        "CleanedUp",
+        format!("stopped {} and released its token", agent.spec.symbol),
+    )
+    .await;
+
+    info!("agent {} cleaned up", agent.name_any());
     Ok(Action::await_change())
 }
 
+/// Time left until the next fleet sync when nothing in the spec changed
+/// since the last one. Status writes wake the controller up as well, those
+/// reconciles are skipped this way.
+async fn pending_sync(agent: &K8sAgent, ctx: &AgentControllerData) -> Option<Duration> {
+    let status = agent.status.as_ref()?;
+    if status.observed_generation != agent.metadata.generation {
+        return None;
+    }
+
+    let ns = agent.namespace().unwrap_or("default".to_string());
+    ctx.api_config
+        .get(ns.as_str(), agent.name_any().as_str())
+        .await?;
+
+    (status.next_sync? - Utc::now()).to_std().ok()
+}
+
+async fn report_failure(
+    agent: &K8sAgent,
+    ctx: &AgentControllerData,
+    current: &[Condition],
+    condition_type: &str,
+    err: &AgentError,
+) -> Result<(), AgentError> {
+    let reason = match condition_type {
+        Condition::REGISTERED => "RegistrationFailed",
+        _ => "SyncFailed",
+    };
+
+    publish_event(
+        ctx.k8s_client.clone(),
+        agent,
+        EventType::Warning,
+        reason,
+        err.to_string(),
+    )
+    .await;
+
+    let conditions = vec![
+        Condition::new(condition_type, false, reason, err.to_string()),
+        Condition::new(Condition::READY, false, reason, err.to_string()),
+        Condition::new(Condition::DEGRADED, true, reason, err.to_string()),
+    ];
+
+    patch_conditions(ctx.k8s_client.clone(), agent, current, conditions)
+        .await
+        .context(AgentPatchSnafu)
+}
+
+/// Loads the agent's token from its secret, moving a plaintext spec token or
+/// a freshly registered one into the secret first when needed.
 async fn reconcile_token(
     agent: Arc<K8sAgent>,
     data: Arc<AgentControllerData>,
-) -> Result<(), AgentError> {
+) -> Result<&'static str, AgentError> {
     info!("reconciling token");
 
-    let authed_config = match agent.spec.token.clone() {
+    let ns = agent.namespace().unwrap_or("default".to_string());
+    let secret_name = agent
+        .spec
+        .token_secret
+        .clone()
+        .unwrap_or(format!("{}-token", agent.name_any()));
+
+    let mut reason = "TokenLoaded";
+    let token = match read_token(data.k8s_client.clone(), ns.as_str(), secret_name.as_str()).await?
+    {
+        Some(token) => token,
         None => {
-            let token = register_agent(agent.spec.symbol.clone(), agent.spec.faction.clone())
-                .await
-                .context(RegisterAgentSnafu)?;
-
-            let spec = AgentSpec {
-                token: Some(token.clone()),
-                ..agent.spec.clone()
+            let token = match agent.spec.token.clone() {
+                Some(token) => {
+                    info!("migrating spec token into secret {}", secret_name);
+                    reason = "TokenMigrated";
+                    token
+                }
+                None => {
+                    let token = register_agent(
+                        &data.api_config.unauthenticated(),
+                        agent.spec.symbol.clone(),
+                        agent.spec.faction.clone(),
+                    )
+                    .await
+                    .context(RegisterAgentSnafu)?;
+
+                    reason = "Registered";
+                    publish_event(
+                        data.k8s_client.clone(),
+                        agent.as_ref(),
+                        EventType::Normal,
+                        reason,
+                        format!(
+                            "registered {} with faction {}",
+                            agent.spec.symbol, agent.spec.faction
+                        ),
+                    )
+                    .await;
+
+                    token
+                }
             };
 
-            let new_agent = K8sAgent::new(agent.name_any().as_str(), spec);
-
-            let serverside = PatchParams::apply("operator");
-            let ns = agent.namespace().unwrap_or("default".to_string());
-            let agent_api: Api<K8sAgent> = Api::namespaced(data.k8s_client.clone(), ns.as_str());
-            agent_api
-                .patch(
-                    agent.name_any().as_str(),
-                    &serverside,
-                    &Patch::Apply(new_agent),
-                )
-                .await
-                .context(AgentPatchSnafu)?;
-
-            get_authenticated_config(token)
-        }
+            write_token(
+                data.k8s_client.clone(),
+                agent.clone(),
+                secret_name.as_str(),
+                token.clone(),
+            )
+            .await?;
 
-        Some(token) => get_authenticated_config(token),
+            token
+        }
     };
 
-    let mut cfg = data.api_config.write().await;
-    *cfg = Some(authed_config);
+    if agent.spec.token.is_some() || agent.spec.token_secret.as_ref() != Some(&secret_name) {
+        let patch = json!({
+            "spec": {
+                "token": null,
+                "token_secret": secret_name,
+            }
+        });
+
+        let agent_api: Api<K8sAgent> = Api::namespaced(data.k8s_client.clone(), ns.as_str());
+        agent_api
+            .patch(
+                agent.name_any().as_str(),
+                &PatchParams::default(),
+                &Patch::Merge(&patch),
+            )
+            .await
+            .context(AgentPatchSnafu)?;
+    }
+
+    data.api_config
+        .insert(ns.as_str(), agent.name_any().as_str(), token)
+        .await;
+
     info!("token reconciled");
-    Ok(())
+    Ok(reason)
 }
 
-fn get_authenticated_config(bearer_token: String) -> Configuration {
-    Configuration {
-        bearer_access_token: Some(bearer_token),
+async fn read_token(
+    client: Client,
+    namespace: &str,
+    secret_name: &str,
+) -> Result<Option<String>, AgentError> {
+    let secret_api: Api<Secret> = Api::namespaced(client, namespace);
+    let secret = secret_api.get_opt(secret_name).await.context(SecretSnafu)?;
+
+    let token = secret
+        .and_then(|s| s.data)
+        .and_then(|mut d| d.remove(TOKEN_KEY))
+        .map(|t| String::from_utf8(t.0))
+        .transpose()
+        .context(TokenDecodeSnafu)?;
+
+    Ok(token)
+}
+
+async fn write_token(
+    client: Client,
+    agent: Arc<K8sAgent>,
+    secret_name: &str,
+    token: String,
+) -> Result<(), AgentError> {
+    let ns = agent.namespace().unwrap_or("default".to_string());
+    let oref = agent.controller_owner_ref(&());
+
+    let secret = Secret {
+        metadata: ObjectMeta {
+            name: Some(secret_name.to_string()),
+            namespace: Some(ns.clone()),
+            owner_references: oref.map(|oref| vec![oref]),
+            ..Default::default()
+        },
+        string_data: Some(BTreeMap::from([(TOKEN_KEY.to_string(), token)])),
+        type_: Some("Opaque".to_string()),
         ..Default::default()
-    }
+    };
+
+    let secret_api: Api<Secret> = Api::namespaced(client, ns.as_str());
+    secret_api
+        .patch(
+            secret_name,
+            &PatchParams::apply("operator"),
+            &Patch::Apply(&secret),
+        )
+        .await
+        .context(SecretSnafu)?;
+
+    Ok(())
 }
 
-async fn reconcile_starting_ships(
+/// Makes sure every ship in the fleet has a `Ship` CR owned by the agent and
+/// removes CRs for ships that no longer exist. Contracts are mirrored the
+/// same way, but never removed since the server keeps them.
+async fn reconcile_ships(
     agent: Arc<K8sAgent>,
     data: Arc<AgentControllerData>,
 ) -> Result<(), AgentError> {
-    info!("reconciling starting ships");
-    let oref = agent.controller_owner_ref(&());
+    info!("reconciling ships");
 
-    let symbol = agent.spec.symbol.clone().to_uppercase();
-    let command_ship = format!("{}-1", symbol);
-    patch_ship(
-        data.k8s_client.clone(),
-        command_ship,
-        agent.namespace(),
-        oref.clone(),
-    )
-    .await
-    .context(ShipPatchSnafu)?;
+    let ns = agent.namespace().unwrap_or("default".to_string());
+    let cfg = data
+        .api_config
+        .get(ns.as_str(), agent.name_any().as_str())
+        .await;
+    ensure!(cfg.is_some(), ApiConfigNotAvailableSnafu);
+    let cfg = &cfg.unwrap();
 
-    let satellite = format!("{}-2", symbol);
-    patch_ship(data.k8s_client.clone(), satellite, agent.namespace(), oref)
+    let ships = common::machines::actions::get_all_ships(cfg)
+        .await
+        .context(GetShipsSnafu)?;
+
+    let oref = agent.controller_owner_ref(&());
+    for ship in ships.iter() {
+        patch_ship(
+            data.k8s_client.clone(),
+            ship.symbol.clone(),
+            agent.namespace(),
+            oref.clone(),
+        )
         .await
         .context(ShipPatchSnafu)?;
+    }
+
+    let ship_api: Api<K8sShip> = Api::namespaced(data.k8s_client.clone(), ns.as_str());
+    let existing = ship_api
+        .list(&ListParams::default())
+        .await
+        .context(ListShipSnafu)?
+        .items;
+
+    // Other agents in the namespace keep their ships.
+    for k8s_ship in existing
+        .into_iter()
+        .filter(|s| owning_agent(s.owner_references()) == Some(agent.name_any()))
+    {
+        if ships.iter().any(|s| s.symbol == k8s_ship.spec.symbol) {
+            continue;
+        }
 
-    info!("starting ships reconciled");
+        info!("removing stale ship {}", k8s_ship.name_any());
+        ship_api
+            .delete(k8s_ship.name_any().as_str(), &DeleteParams::default())
+            .await
+            .context(DeleteShipSnafu)?;
+    }
+
+    let contracts = sync_contracts(data.k8s_client.clone(), agent.as_ref(), cfg)
+        .await
+        .context(ContractSyncSnafu)?;
+    info!("{} contracts synced", contracts);
+
+    let res = agents_api::get_my_agent(cfg).await.context(AgentApiSnafu)?;
+
+    let now = Utc::now();
+    let next_sync = time_after(now, FLEET_SYNC_INTERVAL);
+    let status = json!({
+        "status": {
+            "checksum": agent.status.as_ref().map(|s| s.checksum.clone()).unwrap_or_default(),
+            "ships_initialized": true,
+            "last_updated": now,
+            "credits": res.data.credits,
+            "headquarters": res.data.headquarters,
+            "ship_count": ships.len(),
+            "observed_generation": agent.metadata.generation,
+            "next_sync": next_sync,
+        }
+    });
+
+    let agent_api: Api<K8sAgent> = Api::namespaced(data.k8s_client.clone(), ns.as_str());
+    agent_api
+        .patch_status(
+            agent.name_any().as_str(),
+            &PatchParams::apply("operator"),
+            &Patch::Merge(&status),
+        )
+        .await
+        .context(AgentPatchSnafu)?;
+
+    info!("{} ships reconciled", ships.len());
     Ok(())
 }
 
@@ -166,6 +550,7 @@ pub(crate) fn create_owned_agent(source: Arc<Manager>) -> K8sAgent {
         symbol: source.spec.symbol.clone(),
         faction: source.spec.faction.clone(),
         token: None,
+        token_secret: None,
         reset_date: None,
     };
 
@@ -181,14 +566,219 @@ pub(crate) fn create_owned_agent(source: Arc<Manager>) -> K8sAgent {
             checksum: "".to_string(),
             ships_initialized: false,
             last_updated: None,
+            credits: None,
+            headquarters: None,
+            ship_count: None,
+            observed_generation: None,
+            next_sync: None,
+            conditions: vec![],
         }),
     }
 }
 
-async fn register_agent(symbol: String, faction: FactionSymbol) -> eyre::Result<String> {
-    let conf = Configuration::new();
+async fn register_agent(
+    conf: &Configuration,
+    symbol: String,
+    faction: FactionSymbol,
+) -> eyre::Result<String> {
     let req = openapi::models::RegisterRequest::new(faction.into(), symbol);
-    let res = openapi::apis::default_api::register(&conf, Some(req)).await?;
+    let res = openapi::apis::default_api::register(conf, Some(req)).await?;
 
     Ok(res.data.token)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use hyper::http::{Method, StatusCode};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::mock;
+
+    const NAMESPACE: &str = "spacetraders-test";
+
+    fn agent() -> Arc<K8sAgent> {
+        let mut agent = K8sAgent::new(
+            "test",
+            AgentSpec {
+                symbol: "TEST".to_string(),
+                faction: FactionSymbol::Cosmic,
+                token: None,
+                token_secret: None,
+                reset_date: None,
+            },
+        );
+        agent.metadata.namespace = Some(NAMESPACE.to_string());
+        agent.metadata.uid = Some("agent-uid".to_string());
+        agent.metadata.finalizers = Some(vec![AGENT_FINALIZER.to_string()]);
+        Arc::new(agent)
+    }
+
+    /// A cluster holding only the agent, and its token secret when `token`
+    /// is set.
+    fn cluster(token: Option<&str>) -> (Client, mock::Requests) {
+        let agent = json!(agent().as_ref());
+        let secret = token.map(|t| {
+            json!({
+                "apiVersion": "v1",
+                "kind": "Secret",
+                "metadata": { "name": "test-token", "namespace": NAMESPACE },
+                "data": { TOKEN_KEY: k8s_openapi::ByteString(t.as_bytes().to_vec()) },
+            })
+        });
+        let contract = Mutex::new(Value::Null);
+
+        mock::kube_client(move |method, path, body| match (method, path) {
+            (&Method::GET, p) if p.contains("/secrets/test-token") => match &secret {
+                Some(secret) => (StatusCode::OK, secret.clone()),
+                None => mock::not_found(),
+            },
+            (&Method::PATCH, p) if p.contains("/secrets/") => (StatusCode::OK, body.clone()),
+            (&Method::PATCH, p) if p.contains("/agents/test") => (StatusCode::OK, agent.clone()),
+            (&Method::GET, p) if p.contains("/ships") => mock::list("Ship", vec![]),
+            (&Method::PATCH, p) if p.contains("/ships/") => (StatusCode::OK, body.clone()),
+            (&Method::POST, p) if p.contains("/contracts") => {
+                *contract.lock().unwrap() = body.clone();
+                (StatusCode::CREATED, body.clone())
+            }
+            (&Method::PATCH, p) if p.contains("/contracts/") => {
+                (StatusCode::OK, contract.lock().unwrap().clone())
+            }
+            _ => mock::not_found(),
+        })
+    }
+
+    /// A game with a single agent owning one ship and one contract. Fails
+    /// registrations when `register_status` isn't a success.
+    async fn game(register_status: StatusCode) -> (String, mock::Requests) {
+        mock::spacetraders(move |method, path, _| match (method, path) {
+            (&Method::POST, "/register") if register_status.is_success() => {
+                (register_status, mock::registration("TEST", "token-1"))
+            }
+            (&Method::POST, "/register") => (
+                register_status,
+                json!({ "error": { "message": "symbol taken", "code": 4111 } }),
+            ),
+            (&Method::GET, p) if p.starts_with("/my/ships") => {
+                mock::page(vec![mock::ship("TEST-1")])
+            }
+            (&Method::GET, p) if p.starts_with("/my/contracts") => {
+                mock::page(vec![mock::contract("contract-1")])
+            }
+            (&Method::GET, "/my/agent") => (
+                StatusCode::OK,
+                json!({ "data": mock::agent("TEST", 175000) }),
+            ),
+            _ => (StatusCode::NOT_FOUND, json!({})),
+        })
+        .await
+    }
+
+    fn context(client: Client, base_path: &str) -> Arc<AgentControllerData> {
+        Arc::new(AgentControllerData::new(
+            client,
+            ApiConfigRegistry::with_base_path(base_path),
+            Arc::new(DatabaseConnection::Disconnected),
+            BehaviourRegistry::new(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn registers_agent_and_stores_token() {
+        let (client, kube) = cluster(None);
+        let (base_path, game_requests) = game(StatusCode::CREATED).await;
+        let ctx = context(client, base_path.as_str());
+
+        reconcile(agent(), ctx.clone()).await.unwrap();
+
+        let registrations = game_requests.matching(Method::POST, "/register");
+        assert_eq!(registrations.len(), 1);
+        assert_eq!(registrations[0].body["symbol"], "TEST");
+        assert_eq!(registrations[0].body["faction"], "COSMIC");
+
+        let secrets = kube.matching(Method::PATCH, "/secrets/test-token");
+        assert_eq!(secrets[0].body["stringData"][TOKEN_KEY], "token-1");
+
+        let spec = kube
+            .matching(Method::PATCH, "/agents/test?")
+            .into_iter()
+            .find(|r| r.body["spec"].is_object())
+            .expect("agent spec was not patched");
+        assert_eq!(spec.body["spec"]["token_secret"], "test-token");
+
+        let config = ctx.api_config.get(NAMESPACE, "test").await.unwrap();
+        assert_eq!(config.bearer_access_token.as_deref(), Some("token-1"));
+    }
+
+    #[tokio::test]
+    async fn syncs_ships_and_contracts() {
+        let (client, kube) = cluster(Some("token-1"));
+        let (base_path, game_requests) = game(StatusCode::CREATED).await;
+        let ctx = context(client, base_path.as_str());
+
+        let action = reconcile(agent(), ctx).await.unwrap();
+        assert_eq!(action, Action::requeue(FLEET_SYNC_INTERVAL));
+        assert!(game_requests.matching(Method::POST, "/register").is_empty());
+
+        let ships = kube.matching(Method::PATCH, "/namespaces/spacetraders-test/ships/test-1");
+        assert_eq!(ships.len(), 1);
+        assert_eq!(ships[0].body["spec"]["symbol"], "TEST-1");
+        assert_eq!(
+            ships[0].body["metadata"]["ownerReferences"][0]["name"],
+            "test"
+        );
+
+        let contracts = kube.matching(Method::POST, "/contracts");
+        assert_eq!(contracts.len(), 1);
+        assert_eq!(contracts[0].body["spec"]["id"], "contract-1");
+        assert_eq!(contracts[0].body["spec"]["accept"], false);
+
+        let status = kube
+            .matching(Method::PATCH, "/agents/test/status")
+            .into_iter()
+            .find(|r| r.body["status"]["credits"].is_number())
+            .expect("agent status was not patched");
+        assert_eq!(status.body["status"]["credits"], 175000);
+        assert_eq!(status.body["status"]["ship_count"], 1);
+        assert_eq!(status.body["status"]["ships_initialized"], true);
+    }
+
+    #[tokio::test]
+    async fn failed_registration_requeues_with_backoff() {
+        let (client, kube) = cluster(None);
+        let (base_path, _) = game(StatusCode::CONFLICT).await;
+        let ctx = context(client, base_path.as_str());
+        let agent = agent();
+
+        let err = reconcile(agent.clone(), ctx.clone()).await.unwrap_err();
+        match &err {
+            AgentError::FinalizerError { source } => assert!(matches!(
+                **source,
+                finalizer::Error::ApplyFailed(AgentError::RegisterAgentError { .. })
+            )),
+            err => panic!("unexpected error {}", err),
+        }
+
+        assert!(kube.matching(Method::PATCH, "/secrets/").is_empty());
+        let conditions = kube
+            .matching(Method::PATCH, "/agents/test/status")
+            .pop()
+            .expect("conditions were not patched");
+        let registered = conditions.body["status"]["conditions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|c| c["type"] == Condition::REGISTERED)
+            .cloned()
+            .unwrap();
+        assert_eq!(registered["status"], "False");
+        assert_eq!(registered["reason"], "RegistrationFailed");
+
+        let first = error_policy(agent.clone(), &err, ctx.clone());
+        let second = error_policy(agent, &err, ctx);
+        assert_eq!(first, Action::requeue(Duration::from_secs(5)));
+        assert_eq!(second, Action::requeue(Duration::from_secs(10)));
+    }
+}
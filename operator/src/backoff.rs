@@ -0,0 +1,35 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const BASE_DELAY: Duration = Duration::from_secs(5);
+const MAX_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// Exponential requeue delays for objects that keep failing to reconcile,
+/// tracked per namespace/name.
+#[derive(Default)]
+pub struct Backoff {
+    failures: Mutex<HashMap<String, u32>>,
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records another failure and returns how long to wait before retrying.
+    pub fn next(&self, key: &str) -> Duration {
+        let mut failures = self.failures.lock().unwrap();
+        let count = failures.entry(key.to_string()).or_insert(0);
+        *count = count.saturating_add(1);
+
+        BASE_DELAY
+            .saturating_mul(2u32.saturating_pow(*count - 1))
+            .min(MAX_DELAY)
+    }
+
+    pub fn reset(&self, key: &str) {
+        let mut failures = self.failures.lock().unwrap();
+        failures.remove(key);
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use common::crds::ShipBehaviour;
+use common::machines::BehaviourMachine;
+use eyre::{Context, Result};
+use openapi::apis::configuration::Configuration;
+use sea_orm::DatabaseConnection;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+struct RunningBehaviour {
+    behaviour: ShipBehaviour,
+    handle: JoinHandle<Result<()>>,
+}
+
+/// Behaviours currently driving ships, keyed by the namespace/name of the
+/// `Ship` CR they were started for.
+#[derive(Clone, Default)]
+pub struct BehaviourRegistry {
+    running: Arc<Mutex<HashMap<String, RunningBehaviour>>>,
+}
+
+impl BehaviourRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts the behaviour unless it is already running, replacing a
+    /// different behaviour for the same ship. A behaviour that has stopped is
+    /// removed and its error returned, so the next call starts it again.
+    pub async fn ensure(
+        &self,
+        key: &str,
+        behaviour: &ShipBehaviour,
+        config: Configuration,
+        db: Arc<DatabaseConnection>,
+        ship_symbol: &str,
+    ) -> Result<()> {
+        let mut running = self.running.lock().await;
+
+        if let Some(current) = running.get(key) {
+            if &current.behaviour == behaviour && !current.handle.is_finished() {
+                return Ok(());
+            }
+        }
+
+        if let Some(current) = running.remove(key) {
+            if current.handle.is_finished() && &current.behaviour == behaviour {
+                return match current.handle.await {
+                    Ok(result) => result,
+                    Err(e) => Err(e).wrap_err("behaviour task panicked"),
+                };
+            }
+
+            current.handle.abort();
+        }
+
+        info!("starting {} behaviour for {}", behaviour.name, ship_symbol);
+        let handle = spawn(behaviour.clone(), config, db, ship_symbol.to_string());
+        running.insert(
+            key.to_string(),
+            RunningBehaviour {
+                behaviour: behaviour.clone(),
+                handle,
+            },
+        );
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub async fn is_running(&self, key: &str) -> bool {
+        self.running.lock().await.contains_key(key)
+    }
+
+    /// Aborts the behaviour and waits for its task to wind down, so nothing
+    /// it was writing is still in flight once this returns.
+    pub async fn stop(&self, key: &str) {
+        let mut running = self.running.lock().await;
+        if let Some(current) = running.remove(key) {
+            info!("stopping {} behaviour for {}", current.behaviour.name, key);
+            current.handle.abort();
+            let _ = current.handle.await;
+        }
+    }
+}
+
+fn spawn(
+    behaviour: ShipBehaviour,
+    config: Configuration,
+    db: Arc<DatabaseConnection>,
+    ship_symbol: String,
+) -> JoinHandle<Result<()>> {
+    tokio::spawn(async move {
+        BehaviourMachine::new(&behaviour, config, db.as_ref(), ship_symbol.as_str())
+            .await?
+            .run()
+            .await
+    })
+}
@@ -0,0 +1,510 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::crds::{
+    set_condition, Agent as K8sAgent, Condition, Contract as K8sContract, ContractDeliverySpec,
+    ContractDeliveryStatus, ContractSpec, ContractTermsSpec, Ship as K8sShip, ShipBehaviour,
+};
+use common::models::Contract;
+use futures::StreamExt;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::api::{ListParams, Patch, PatchParams, PostParams};
+use kube::core::ObjectMeta;
+use kube::runtime::controller::Action;
+use kube::runtime::events::EventType;
+use kube::runtime::Controller;
+use kube::{Api, Client, Resource, ResourceExt};
+use openapi::apis;
+use openapi::apis::configuration::Configuration;
+use openapi::apis::contracts_api::{self, AcceptContractError, GetContractError};
+use serde_json::json;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::backoff::Backoff;
+use crate::metrics;
+use crate::registry::{resolve_agent, ApiConfigRegistry};
+use crate::status::{object_key, publish_event};
+
+/// How often delivery progress is fetched while a ship works on the contract.
+const DELIVERY_CHECK_INTERVAL: Duration = Duration::from_secs(120);
+
+/// How often contracts that nobody works on are looked at again.
+const CONTRACT_CHECK_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Name of the ship behaviour that delivers contracts.
+const CONTRACT_BEHAVIOUR: &str = "contract";
+
+pub struct ContractControllerData {
+    pub api_config: ApiConfigRegistry,
+    pub k8s_client: Client,
+    pub backoff: Backoff,
+}
+
+impl ContractControllerData {
+    pub fn new(k8s_client: Client, api_config: ApiConfigRegistry) -> Self {
+        Self {
+            api_config,
+            k8s_client,
+            backoff: Backoff::new(),
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum ContractError {
+    #[snafu(display("api config is not available yet"))]
+    ApiConfigNotAvailable,
+
+    #[snafu(display("no agent found for contract {}", contract))]
+    AgentNotFound { contract: String },
+
+    #[snafu(display("error listing agents {}", source))]
+    ListAgentError { source: kube::Error },
+
+    #[snafu(display("error fetching contract {}", source))]
+    GetContractApiError {
+        source: apis::Error<GetContractError>,
+    },
+
+    #[snafu(display("error accepting contract {}", source))]
+    AcceptContractApiError {
+        source: apis::Error<AcceptContractError>,
+    },
+
+    #[snafu(display("error listing ships {}", source))]
+    ListShipError { source: kube::Error },
+
+    #[snafu(display("error patching ship {}", source))]
+    ShipPatchError { source: kube::Error },
+
+    #[snafu(display("error patching contract {}", source))]
+    ContractPatchError { source: kube::Error },
+}
+
+pub(crate) async fn run_controller(data: Arc<ContractControllerData>) -> eyre::Result<()> {
+    let contract = Api::<K8sContract>::all(data.k8s_client.clone());
+
+    Controller::new(contract, Default::default())
+        .run(
+            |obj, ctx| metrics::measure("contract", reconcile(obj, ctx)),
+            error_policy,
+            data,
+        )
+        .for_each(|_| futures::future::ready(()))
+        .await;
+
+    Ok(())
+}
+
+pub(crate) fn error_policy(
+    object: Arc<K8sContract>,
+    err: &ContractError,
+    ctx: Arc<ContractControllerData>,
+) -> Action {
+    warn!("error reconciling contract: {}", err);
+    Action::requeue(ctx.backoff.next(object_key(object.as_ref()).as_str()))
+}
+
+pub(crate) async fn reconcile(
+    k8s_contract: Arc<K8sContract>,
+    ctx: Arc<ContractControllerData>,
+) -> Result<Action, ContractError> {
+    info!("Reconciling contract {}", k8s_contract.name_any());
+
+    let ns = k8s_contract.namespace().unwrap_or("default".to_string());
+    let agent = resolve_agent(
+        ctx.k8s_client.clone(),
+        ns.as_str(),
+        k8s_contract.owner_references(),
+    )
+    .await
+    .context(ListAgentSnafu)?
+    .context(AgentNotFoundSnafu {
+        contract: k8s_contract.name_any(),
+    })?;
+
+    let cfg = ctx.api_config.get(ns.as_str(), agent.as_str()).await;
+    ensure!(cfg.is_some(), ApiConfigNotAvailableSnafu);
+    let cfg = &cfg.unwrap();
+
+    let id = k8s_contract.spec.id.as_str();
+    let res = contracts_api::get_contract(cfg, id)
+        .await
+        .context(GetContractApiSnafu)?;
+    let mut contract = Contract::from(res.data);
+
+    if k8s_contract.spec.accept && !contract.accepted {
+        contract = accept(ctx.as_ref(), cfg, k8s_contract.as_ref()).await?;
+    }
+
+    let was_fulfilled = k8s_contract.status.as_ref().is_some_and(|s| s.fulfilled);
+    let working = contract.accepted && !contract.fulfilled;
+    let ship = match working {
+        true => k8s_contract.spec.ship.as_deref(),
+        false => None,
+    };
+
+    release_ships(ctx.as_ref(), k8s_contract.as_ref(), ship).await?;
+
+    let condition = match ship {
+        Some(ship) => match assign_ship(ctx.as_ref(), k8s_contract.as_ref(), ship).await? {
+            true => Condition::new(
+                Condition::READY,
+                true,
+                "Delivering",
+                format!("{} is delivering the goods", ship),
+            ),
+            false => Condition::new(
+                Condition::READY,
+                false,
+                "ShipNotFound",
+                format!("no ship {} in the namespace", ship),
+            ),
+        },
+        None if contract.fulfilled => {
+            Condition::new(Condition::READY, true, "Fulfilled", "".to_string())
+        }
+        None if contract.accepted => Condition::new(
+            Condition::READY,
+            false,
+            "WaitingForShip",
+            "set a ship to deliver the goods".to_string(),
+        ),
+        None => Condition::new(
+            Condition::READY,
+            true,
+            "Offered",
+            "set accept to take the contract".to_string(),
+        ),
+    };
+
+    if contract.fulfilled && !was_fulfilled {
+        publish_event(
+            ctx.k8s_client.clone(),
+            k8s_contract.as_ref(),
+            EventType::Normal,
+            "Fulfilled",
+            format!(
+                "contract {} fulfilled for {} credits",
+                contract.id, contract.terms.payment.on_fulfilled
+            ),
+        )
+        .await;
+    }
+
+    patch_status(
+        ctx.k8s_client.clone(),
+        k8s_contract.as_ref(),
+        &contract,
+        Some(condition),
+    )
+    .await
+    .context(ContractPatchSnafu)?;
+
+    ctx.backoff
+        .reset(object_key(k8s_contract.as_ref()).as_str());
+
+    match working && ship.is_some() {
+        true => Ok(Action::requeue(DELIVERY_CHECK_INTERVAL)),
+        false => Ok(Action::requeue(CONTRACT_CHECK_INTERVAL)),
+    }
+}
+
+async fn accept(
+    ctx: &ContractControllerData,
+    cfg: &Configuration,
+    k8s_contract: &K8sContract,
+) -> Result<Contract, ContractError> {
+    let res = contracts_api::accept_contract(cfg, k8s_contract.spec.id.as_str())
+        .await
+        .context(AcceptContractApiSnafu)?;
+    let contract = Contract::from(res.data.contract);
+
+    info!("accepted contract {}", contract.id);
+    publish_event(
+        ctx.k8s_client.clone(),
+        k8s_contract,
+        EventType::Normal,
+        "Accepted",
+        format!(
+            "contract accepted, {} credits paid up front",
+            contract.terms.payment.on_accepted
+        ),
+    )
+    .await;
+
+    Ok(contract)
+}
+
+fn contract_behaviour(id: &str) -> ShipBehaviour {
+    ShipBehaviour {
+        name: CONTRACT_BEHAVIOUR.to_string(),
+        params: BTreeMap::from([("contract".to_string(), id.to_string())]),
+    }
+}
+
+/// Hands the ship over to the contract behaviour. Returns false when there
+/// is no `Ship` CR for the symbol.
+async fn assign_ship(
+    ctx: &ContractControllerData,
+    k8s_contract: &K8sContract,
+    symbol: &str,
+) -> Result<bool, ContractError> {
+    let ns = k8s_contract.namespace().unwrap_or("default".to_string());
+    let ship_api: Api<K8sShip> = Api::namespaced(ctx.k8s_client.clone(), ns.as_str());
+
+    let ship = ship_api
+        .get_opt(symbol.to_lowercase().as_str())
+        .await
+        .context(ListShipSnafu)?;
+    let ship = match ship {
+        Some(ship) => ship,
+        None => return Ok(false),
+    };
+
+    let behaviour = contract_behaviour(k8s_contract.spec.id.as_str());
+    if ship.spec.behaviour.as_ref() == Some(&behaviour) {
+        return Ok(true);
+    }
+
+    let spec = json!({
+        "spec": { "behaviour": behaviour }
+    });
+    ship_api
+        .patch(
+            ship.name_any().as_str(),
+            &PatchParams::default(),
+            &Patch::Merge(&spec),
+        )
+        .await
+        .context(ShipPatchSnafu)?;
+
+    info!("assigned {} to contract {}", symbol, k8s_contract.spec.id);
+    publish_event(
+        ctx.k8s_client.clone(),
+        k8s_contract,
+        EventType::Normal,
+        "ShipAssigned",
+        format!("{} is delivering the goods", symbol),
+    )
+    .await;
+
+    Ok(true)
+}
+
+/// Clears the behaviour of every ship still working on this contract other
+/// than `keep`, so ships are freed once the contract is fulfilled or another
+/// ship takes over.
+async fn release_ships(
+    ctx: &ContractControllerData,
+    k8s_contract: &K8sContract,
+    keep: Option<&str>,
+) -> Result<(), ContractError> {
+    let ns = k8s_contract.namespace().unwrap_or("default".to_string());
+    let ship_api: Api<K8sShip> = Api::namespaced(ctx.k8s_client.clone(), ns.as_str());
+    let behaviour = contract_behaviour(k8s_contract.spec.id.as_str());
+
+    let ships = ship_api
+        .list(&ListParams::default())
+        .await
+        .context(ListShipSnafu)?
+        .items;
+
+    for ship in ships {
+        if ship.spec.behaviour.as_ref() != Some(&behaviour)
+            || keep.is_some_and(|k| k.eq_ignore_ascii_case(ship.spec.symbol.as_str()))
+        {
+            continue;
+        }
+
+        let spec = json!({
+            "spec": { "behaviour": null }
+        });
+        ship_api
+            .patch(
+                ship.name_any().as_str(),
+                &PatchParams::default(),
+                &Patch::Merge(&spec),
+            )
+            .await
+            .context(ShipPatchSnafu)?;
+
+        info!(
+            "released {} from contract {}",
+            ship.spec.symbol, k8s_contract.spec.id
+        );
+        publish_event(
+            ctx.k8s_client.clone(),
+            k8s_contract,
+            EventType::Normal,
+            "ShipReleased",
+            format!("{} no longer works on the contract", ship.spec.symbol),
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// Makes sure every contract of the agent has a `Contract` CR owned by it.
+/// Only the fields mirrored from the server are written, `accept` and `ship`
+/// belong to the user.
+pub(crate) async fn sync_contracts(
+    client: Client,
+    agent: &K8sAgent,
+    cfg: &Configuration,
+) -> eyre::Result<usize> {
+    let ns = agent.namespace().unwrap_or("default".to_string());
+    let contract_api: Api<K8sContract> = Api::namespaced(client.clone(), ns.as_str());
+
+    let contracts = common::machines::actions::get_all_contracts(cfg).await?;
+
+    for contract in contracts.iter() {
+        let name = contract.id.to_lowercase();
+        let spec = contract_spec(contract);
+
+        let existing = match contract_api.get_opt(name.as_str()).await? {
+            Some(existing) => existing,
+            None => {
+                let k8s_contract = K8sContract {
+                    metadata: ObjectMeta {
+                        name: Some(name.clone()),
+                        namespace: Some(ns.clone()),
+                        owner_references: agent.controller_owner_ref(&()).map(|o| vec![o]),
+                        ..Default::default()
+                    },
+                    spec: spec.clone(),
+                    status: None,
+                };
+
+                info!("adding contract {}", contract.id);
+                contract_api
+                    .create(&PostParams::default(), &k8s_contract)
+                    .await?
+            }
+        };
+
+        if existing.spec.terms != spec.terms
+            || existing.spec.deadline_to_accept != spec.deadline_to_accept
+            || existing.spec.expiration != spec.expiration
+        {
+            let patch = json!({
+                "spec": {
+                    "terms": spec.terms,
+                    "expiration": spec.expiration,
+                    "deadline_to_accept": spec.deadline_to_accept,
+                }
+            });
+            contract_api
+                .patch(
+                    name.as_str(),
+                    &PatchParams::default(),
+                    &Patch::Merge(&patch),
+                )
+                .await?;
+        }
+
+        patch_status(client.clone(), &existing, contract, None).await?;
+    }
+
+    Ok(contracts.len())
+}
+
+fn contract_spec(contract: &Contract) -> ContractSpec {
+    ContractSpec {
+        id: contract.id.clone(),
+        faction_symbol: contract.faction_symbol.clone(),
+        contract_type: contract.contract_type.clone(),
+        terms: ContractTermsSpec {
+            deadline: to_utc(contract.terms.deadline),
+            payment: contract.terms.payment.clone(),
+            deliver: contract
+                .terms
+                .deliver
+                .iter()
+                .map(|d| ContractDeliverySpec {
+                    trade_symbol: d.trade_symbol.clone(),
+                    destination: d.destination.clone(),
+                    units_required: d.units_required,
+                })
+                .collect(),
+        },
+        expiration: to_utc(contract.expiration),
+        deadline_to_accept: contract.deadline_to_accept.map(to_utc),
+        accept: false,
+        ship: None,
+    }
+}
+
+fn to_utc(date: OffsetDateTime) -> DateTime<Utc> {
+    DateTime::from_timestamp(date.unix_timestamp(), date.nanosecond()).unwrap_or_default()
+}
+
+/// Mirrors the delivery progress into the status, merging in the condition
+/// if one is given. Nothing is written when the status is already up to
+/// date.
+async fn patch_status(
+    client: Client,
+    k8s_contract: &K8sContract,
+    contract: &Contract,
+    condition: Option<Condition>,
+) -> Result<(), kube::Error> {
+    let current = k8s_contract.status.clone().unwrap_or_default();
+    let mut conditions = current.conditions.clone();
+    let changed = condition.is_some_and(|c| set_condition(&mut conditions, c));
+
+    let deliveries = contract
+        .terms
+        .deliver
+        .iter()
+        .map(|d| ContractDeliveryStatus {
+            trade_symbol: d.trade_symbol.clone(),
+            units_fulfilled: d.units_fulfilled,
+            units_required: d.units_required,
+        })
+        .collect::<Vec<ContractDeliveryStatus>>();
+
+    let required: i32 = deliveries.iter().map(|d| d.units_required).sum();
+    let fulfilled: i32 = deliveries
+        .iter()
+        .map(|d| d.units_fulfilled.min(d.units_required))
+        .sum();
+    let progress = match required {
+        0 if contract.fulfilled => 100,
+        0 => 0,
+        _ => (fulfilled * 100 / required) as u8,
+    };
+
+    if !changed
+        && current.accepted == contract.accepted
+        && current.fulfilled == contract.fulfilled
+        && current.deliveries == deliveries
+        && current.progress == progress
+    {
+        return Ok(());
+    }
+
+    let ns = k8s_contract.namespace().unwrap_or("default".to_string());
+    let api: Api<K8sContract> = Api::namespaced(client, ns.as_str());
+    let status = json!({
+        "status": {
+            "accepted": contract.accepted,
+            "fulfilled": contract.fulfilled,
+            "deliveries": deliveries,
+            "progress": progress,
+            "conditions": conditions,
+        }
+    });
+
+    api.patch_status(
+        k8s_contract.name_any().as_str(),
+        &PatchParams::default(),
+        &Patch::Merge(&status),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,494 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::crds::{
+    set_condition, Agent as K8sAgent, Condition, Fleet, FleetTarget, FleetTargetStatus,
+    PurchaseOrder, PurchaseOrderSpec, Ship as K8sShip,
+};
+use common::models::Location;
+use futures::StreamExt;
+use kube::api::{ListParams, Patch, PatchParams, PostParams};
+use kube::core::ObjectMeta;
+use kube::runtime::controller::Action;
+use kube::runtime::events::EventType;
+use kube::runtime::Controller;
+use kube::{Api, Client, Resource, ResourceExt};
+use openapi::apis;
+use openapi::apis::agents_api::{self, GetMyAgentError};
+use sea_orm::DatabaseConnection;
+use serde_json::json;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use tracing::{info, warn};
+
+use crate::backoff::Backoff;
+use crate::metrics;
+use crate::registry::{owning_agent, resolve_agent, ApiConfigRegistry};
+use crate::status::{object_key, publish_event};
+
+/// How often credits are checked again while ships are missing.
+const FLEET_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+/// How often an open purchase order is checked on. Completed orders wake the
+/// controller up on their own.
+const ORDER_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct FleetControllerData {
+    pub api_config: ApiConfigRegistry,
+    pub k8s_client: Client,
+    pub db: Arc<DatabaseConnection>,
+    pub backoff: Backoff,
+}
+
+impl FleetControllerData {
+    pub fn new(
+        k8s_client: Client,
+        api_config: ApiConfigRegistry,
+        db: Arc<DatabaseConnection>,
+    ) -> Self {
+        Self {
+            api_config,
+            k8s_client,
+            db,
+            backoff: Backoff::new(),
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum FleetError {
+    #[snafu(display("api config is not available yet"))]
+    ApiConfigNotAvailable,
+
+    #[snafu(display("no agent found for fleet {}", fleet))]
+    AgentNotFound { fleet: String },
+
+    #[snafu(display("error listing agents {}", source))]
+    ListAgentError { source: kube::Error },
+
+    #[snafu(display("error listing ships {}", source))]
+    ListShipError { source: kube::Error },
+
+    #[snafu(display("error listing purchase orders {}", source))]
+    ListOrderError { source: kube::Error },
+
+    #[snafu(display("error creating purchase order {}", source))]
+    CreateOrderError { source: kube::Error },
+
+    #[snafu(display("error fetching agent {}", source))]
+    GetAgentError {
+        source: apis::Error<GetMyAgentError>,
+    },
+
+    #[snafu(display("error loading shipyard prices {}", source))]
+    ShipyardPricesError { source: eyre::Report },
+
+    #[snafu(display("error patching fleet {}", source))]
+    FleetPatchError { source: kube::Error },
+}
+
+/// What the fleet is waiting for, reported on its Ready condition.
+enum Progress {
+    Complete,
+    Purchasing(String),
+    Blocked(&'static str, String),
+}
+
+pub(crate) async fn run_controller(data: Arc<FleetControllerData>) -> eyre::Result<()> {
+    let fleet = Api::<Fleet>::all(data.k8s_client.clone());
+    let purchase_order = Api::<PurchaseOrder>::all(data.k8s_client.clone());
+
+    Controller::new(fleet, Default::default())
+        .owns(purchase_order, Default::default())
+        .run(
+            |obj, ctx| metrics::measure("fleet", reconcile(obj, ctx)),
+            error_policy,
+            data,
+        )
+        .for_each(|_| futures::future::ready(()))
+        .await;
+
+    Ok(())
+}
+
+pub(crate) fn error_policy(
+    object: Arc<Fleet>,
+    err: &FleetError,
+    ctx: Arc<FleetControllerData>,
+) -> Action {
+    warn!("error reconciling fleet: {}", err);
+    Action::requeue(ctx.backoff.next(object_key(object.as_ref()).as_str()))
+}
+
+pub(crate) async fn reconcile(
+    fleet: Arc<Fleet>,
+    ctx: Arc<FleetControllerData>,
+) -> Result<Action, FleetError> {
+    info!("Reconciling fleet {}", fleet.name_any());
+
+    let ns = fleet.namespace().unwrap_or("default".to_string());
+    let agent = resolve_agent(
+        ctx.k8s_client.clone(),
+        ns.as_str(),
+        fleet.owner_references(),
+    )
+    .await
+    .context(ListAgentSnafu)?
+    .context(AgentNotFoundSnafu {
+        fleet: fleet.name_any(),
+    })?;
+
+    let ship_api: Api<K8sShip> = Api::namespaced(ctx.k8s_client.clone(), ns.as_str());
+    let ships = ship_api
+        .list(&ListParams::default())
+        .await
+        .context(ListShipSnafu)?
+        .items
+        .into_iter()
+        .filter(|s| owning_agent(s.owner_references()).as_ref() == Some(&agent))
+        .collect::<Vec<K8sShip>>();
+
+    let order_api: Api<PurchaseOrder> = Api::namespaced(ctx.k8s_client.clone(), ns.as_str());
+    let orders = order_api
+        .list(&ListParams::default())
+        .await
+        .context(ListOrderSnafu)?
+        .items
+        .into_iter()
+        .filter(|o| {
+            o.owner_references()
+                .iter()
+                .any(|r| Some(&r.uid) == fleet.metadata.uid.as_ref())
+        })
+        .collect::<Vec<PurchaseOrder>>();
+
+    let spent = orders
+        .iter()
+        .filter_map(|o| o.status.as_ref().and_then(|s| s.price))
+        .sum::<i64>();
+
+    let targets = fleet
+        .spec
+        .targets
+        .iter()
+        .map(|t| count_target(t, &ships, &orders))
+        .collect::<Vec<FleetTargetStatus>>();
+
+    let progress = expand(&fleet, &ctx, agent.as_str(), &ships, &orders, spent).await?;
+    let requeue = match progress {
+        Progress::Complete => Action::await_change(),
+        Progress::Purchasing(_) => Action::requeue(ORDER_CHECK_INTERVAL),
+        Progress::Blocked(_, _) => Action::requeue(FLEET_CHECK_INTERVAL),
+    };
+
+    let condition = match progress {
+        Progress::Complete => Condition::new(
+            Condition::READY,
+            true,
+            "FleetComplete",
+            "every target is met".to_string(),
+        ),
+        Progress::Purchasing(message) => {
+            Condition::new(Condition::READY, false, "Purchasing", message)
+        }
+        Progress::Blocked(reason, message) => {
+            Condition::new(Condition::READY, false, reason, message)
+        }
+    };
+
+    patch_status(&ctx, &fleet, targets, spent, condition).await?;
+
+    ctx.backoff.reset(object_key(fleet.as_ref()).as_str());
+    info!("fleet {} reconciled", fleet.name_any());
+    Ok(requeue)
+}
+
+fn count_target(
+    target: &FleetTarget,
+    ships: &[K8sShip],
+    orders: &[PurchaseOrder],
+) -> FleetTargetStatus {
+    let bought = |o: &&PurchaseOrder| o.spec.ship_type == target.ship_type;
+
+    let actual = match &target.role {
+        Some(role) => ships
+            .iter()
+            .filter(|s| s.spec.role.as_ref() == Some(role))
+            .count(),
+        None => ships
+            .iter()
+            .filter(|s| {
+                orders.iter().filter(bought).any(|o| {
+                    o.status.as_ref().and_then(|s| s.ship_symbol.as_ref()) == Some(&s.spec.symbol)
+                })
+            })
+            .count(),
+    };
+
+    let pending = orders
+        .iter()
+        .filter(bought)
+        .filter(|o| !o.status.as_ref().is_some_and(|s| s.complete))
+        .count();
+
+    FleetTargetStatus {
+        ship_type: target.ship_type.clone(),
+        role: target.role.clone(),
+        desired: target.count,
+        actual: actual as u32,
+        pending: pending as u32,
+    }
+}
+
+/// A ship the fleet bought for a role target whose role isn't the target's.
+fn mismatched_ship<'a>(
+    target: &FleetTarget,
+    ships: &'a [K8sShip],
+    orders: &[PurchaseOrder],
+) -> Option<&'a K8sShip> {
+    let role = target.role.as_ref()?;
+
+    orders
+        .iter()
+        .filter(|o| o.spec.ship_type == target.ship_type)
+        .filter_map(|o| o.status.as_ref().and_then(|s| s.ship_symbol.as_ref()))
+        .filter_map(|symbol| ships.iter().find(|s| &s.spec.symbol == symbol))
+        .find(|s| s.spec.role.as_ref().is_some_and(|r| r != role))
+}
+
+/// Orders the first missing ship when the credits and the budget allow it.
+/// Only one purchase order is open at a time, so the credits checked here
+/// aren't already promised to another order.
+async fn expand(
+    fleet: &Fleet,
+    ctx: &FleetControllerData,
+    agent: &str,
+    ships: &[K8sShip],
+    orders: &[PurchaseOrder],
+    spent: i64,
+) -> Result<Progress, FleetError> {
+    if let Some(order) = orders
+        .iter()
+        .find(|o| !o.status.as_ref().is_some_and(|s| s.complete))
+    {
+        return Ok(Progress::Purchasing(format!(
+            "waiting for purchase order {}",
+            order.name_any()
+        )));
+    }
+
+    let target = fleet.spec.targets.iter().find(|t| {
+        let status = count_target(t, ships, orders);
+        status.actual < status.desired
+    });
+
+    let target = match target {
+        Some(target) => target,
+        None => return Ok(Progress::Complete),
+    };
+
+    // A role target only counts ships registering with its role, another
+    // ship of a type that registers differently would never count either.
+    if let Some(ship) = mismatched_ship(target, ships, orders) {
+        return Ok(Progress::Blocked(
+            "RoleMismatch",
+            format!(
+                "{} bought as {} has role {}, not {}",
+                ship.spec.symbol,
+                target.ship_type,
+                ship.spec
+                    .role
+                    .as_ref()
+                    .map(|r| r.to_string())
+                    .unwrap_or_default(),
+                target
+                    .role
+                    .as_ref()
+                    .map(|r| r.to_string())
+                    .unwrap_or_default()
+            ),
+        ));
+    }
+
+    // Orders are fulfilled by routing a ship to the shipyard, so only
+    // systems the fleet already has a ship in are considered.
+    let systems = ships
+        .iter()
+        .filter_map(|s| s.status.as_ref().and_then(|s| s.location.as_ref()))
+        .map(|l| l.system_ident())
+        .collect::<Vec<String>>();
+
+    let cheapest = common::repository::get_shipyard_prices(
+        ctx.db.as_ref(),
+        target.ship_type.to_string().as_str(),
+    )
+    .await
+    .context(ShipyardPricesSnafu)?
+    .into_iter()
+    .filter(|(location, _)| systems.contains(&location.system_ident()))
+    .min_by_key(|(_, price)| *price);
+
+    let (shipyard, price) = match cheapest {
+        Some((shipyard, price)) => (shipyard, price as i64),
+        None => {
+            return Ok(Progress::Blocked(
+                "NoShipyardKnown",
+                format!("no known shipyard sells {}", target.ship_type),
+            ))
+        }
+    };
+
+    if let Some(budget) = fleet.spec.budget {
+        if spent + price > budget {
+            return Ok(Progress::Blocked(
+                "BudgetExhausted",
+                format!(
+                    "{} costs {} but only {} of the budget is left",
+                    target.ship_type,
+                    price,
+                    budget - spent
+                ),
+            ));
+        }
+    }
+
+    let ns = fleet.namespace().unwrap_or("default".to_string());
+    let cfg = ctx.api_config.get(ns.as_str(), agent).await;
+    ensure!(cfg.is_some(), ApiConfigNotAvailableSnafu);
+    let cfg = &cfg.unwrap();
+
+    let credits = agents_api::get_my_agent(cfg)
+        .await
+        .context(GetAgentSnafu)?
+        .data
+        .credits;
+
+    let available = credits - fleet.spec.credit_reserve;
+    if available < price {
+        return Ok(Progress::Blocked(
+            "WaitingForCredits",
+            format!(
+                "{} costs {} but only {} credits are available",
+                target.ship_type, price, available
+            ),
+        ));
+    }
+
+    let order = create_order(ctx, fleet, agent, target, shipyard).await?;
+    Ok(Progress::Purchasing(format!(
+        "waiting for purchase order {}",
+        order.name_any()
+    )))
+}
+
+async fn create_order(
+    ctx: &FleetControllerData,
+    fleet: &Fleet,
+    agent: &str,
+    target: &FleetTarget,
+    shipyard: Location,
+) -> Result<PurchaseOrder, FleetError> {
+    let ns = fleet.namespace().unwrap_or("default".to_string());
+
+    let agent_api: Api<K8sAgent> = Api::namespaced(ctx.k8s_client.clone(), ns.as_str());
+    let agent_ref = agent_api
+        .get_opt(agent)
+        .await
+        .context(ListAgentSnafu)?
+        .and_then(|a| a.controller_owner_ref(&()))
+        .map(|mut oref| {
+            // The fleet is the order's controller, the agent only owns it.
+            oref.controller = None;
+            oref
+        });
+
+    let owner_references = fleet
+        .controller_owner_ref(&())
+        .into_iter()
+        .chain(agent_ref)
+        .collect();
+
+    let order = PurchaseOrder {
+        metadata: ObjectMeta {
+            generate_name: Some(format!("{}-", fleet.name_any())),
+            namespace: Some(ns.clone()),
+            owner_references: Some(owner_references),
+            ..Default::default()
+        },
+        spec: PurchaseOrderSpec {
+            location: shipyard.clone(),
+            ship_type: target.ship_type.clone(),
+        },
+        status: None,
+    };
+
+    let order_api: Api<PurchaseOrder> = Api::namespaced(ctx.k8s_client.clone(), ns.as_str());
+    let order = order_api
+        .create(&PostParams::default(), &order)
+        .await
+        .context(CreateOrderSnafu)?;
+
+    info!(
+        "ordered {} at {} for fleet {}",
+        target.ship_type,
+        shipyard,
+        fleet.name_any()
+    );
+    publish_event(
+        ctx.k8s_client.clone(),
+        fleet,
+        EventType::Normal,
+        "PurchaseOrderCreated",
+        format!(
+            "ordered {} at {} as {}",
+            target.ship_type,
+            shipyard,
+            order.name_any()
+        ),
+    )
+    .await;
+
+    Ok(order)
+}
+
+/// Patches the status only when something changed, the controller watches
+/// its own fleets and would otherwise wake itself up.
+async fn patch_status(
+    ctx: &FleetControllerData,
+    fleet: &Fleet,
+    targets: Vec<FleetTargetStatus>,
+    spent: i64,
+    condition: Condition,
+) -> Result<(), FleetError> {
+    let current = fleet.status.clone().unwrap_or_default();
+    let mut conditions = current.conditions.clone();
+    let changed = set_condition(&mut conditions, condition);
+
+    if !changed
+        && current.targets == targets
+        && current.spent == spent
+        && current.observed_generation == fleet.metadata.generation
+    {
+        return Ok(());
+    }
+
+    let ns = fleet.namespace().unwrap_or("default".to_string());
+    let api: Api<Fleet> = Api::namespaced(ctx.k8s_client.clone(), ns.as_str());
+    let status = json!({
+        "status": {
+            "targets": targets,
+            "spent": spent,
+            "observed_generation": fleet.metadata.generation,
+            "conditions": conditions,
+        }
+    });
+
+    api.patch_status(
+        fleet.name_any().as_str(),
+        &PatchParams::default(),
+        &Patch::Merge(&status),
+    )
+    .await
+    .context(FleetPatchSnafu)?;
+
+    Ok(())
+}
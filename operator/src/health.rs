@@ -0,0 +1,65 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+
+use crate::metrics::METRICS;
+
+/// Address the probe and metrics endpoints are served on.
+const HTTP_ADDR: ([u8; 4], u16) = ([0, 0, 0, 0], 8080);
+
+/// Whether the operator finished starting up. Replicas waiting for the lease
+/// are ready as well, they just don't run any controllers.
+#[derive(Default)]
+pub(crate) struct Health {
+    ready: AtomicBool,
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_ready(&self) {
+        self.ready.store(true, Ordering::Relaxed);
+    }
+
+    fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+}
+
+/// Serves `/healthz`, `/readyz` and `/metrics`.
+pub(crate) async fn serve(health: Arc<Health>) -> eyre::Result<()> {
+    let addr = SocketAddr::from(HTTP_ADDR);
+    let make_service = make_service_fn(move |_| {
+        let health = health.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, health.clone()))) }
+    });
+
+    tracing::info!("serving probes and metrics on {}", addr);
+    Server::try_bind(&addr)?.serve(make_service).await?;
+
+    Ok(())
+}
+
+async fn handle(req: Request<Body>, health: Arc<Health>) -> Result<Response<Body>, Infallible> {
+    let response = match req.uri().path() {
+        "/healthz" => text(StatusCode::OK, "ok".to_string()),
+        "/readyz" if health.is_ready() => text(StatusCode::OK, "ok".to_string()),
+        "/readyz" => text(StatusCode::SERVICE_UNAVAILABLE, "starting".to_string()),
+        "/metrics" => text(StatusCode::OK, METRICS.render()),
+        _ => text(StatusCode::NOT_FOUND, "not found".to_string()),
+    };
+
+    Ok(response)
+}
+
+fn text(status: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
@@ -0,0 +1,158 @@
+use std::time::Duration;
+
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+use k8s_openapi::chrono::Utc;
+use kube::api::PostParams;
+use kube::core::ObjectMeta;
+use kube::{Api, Client};
+use snafu::{ResultExt, Snafu};
+use tracing::{info, warn};
+
+use crate::metrics::METRICS;
+use crate::status::time_after;
+
+const LEASE_NAME: &str = "spacetraders-operator";
+
+/// How long the lease stays valid without being renewed.
+const LEASE_DURATION: Duration = Duration::from_secs(15);
+
+const RENEW_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Snafu)]
+pub enum LeaderError {
+    #[snafu(display("error accessing lease {}", source))]
+    LeaseError { source: kube::Error },
+
+    #[snafu(display("{} lost the lease", identity))]
+    LeaseLost { identity: String },
+}
+
+/// Makes sure only one operator replica runs the controllers at a time, so
+/// commands aren't sent to the API twice.
+pub(crate) struct LeaderElector {
+    api: Api<Lease>,
+    identity: String,
+}
+
+impl LeaderElector {
+    pub fn new(client: Client, namespace: &str) -> Self {
+        let identity = std::env::var("POD_NAME")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_else(|_| format!("operator-{}", std::process::id()));
+
+        Self {
+            api: Api::namespaced(client, namespace),
+            identity,
+        }
+    }
+
+    /// Waits until this replica holds the lease.
+    pub async fn acquire(&self) -> Result<(), LeaderError> {
+        info!("{} waiting for the lease", self.identity);
+
+        while !self.try_acquire_or_renew().await? {
+            tokio::time::sleep(RENEW_INTERVAL).await;
+        }
+
+        info!("{} acquired the lease", self.identity);
+        METRICS.leader.set(1);
+        Ok(())
+    }
+
+    /// Keeps renewing the lease and returns once it is lost. A renewal that
+    /// fails is retried until the lease would have expired.
+    pub async fn hold(&self) -> eyre::Result<()> {
+        let mut last_renewal = Utc::now();
+
+        loop {
+            tokio::time::sleep(RENEW_INTERVAL).await;
+
+            match self.try_acquire_or_renew().await {
+                Ok(true) => last_renewal = Utc::now(),
+                Ok(false) => break,
+                Err(e) if time_after(last_renewal, LEASE_DURATION) > Utc::now() => {
+                    warn!("error renewing the lease: {}", e)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        METRICS.leader.set(0);
+        Err(LeaseLostSnafu {
+            identity: self.identity.clone(),
+        }
+        .build()
+        .into())
+    }
+
+    async fn try_acquire_or_renew(&self) -> Result<bool, LeaderError> {
+        let now = Utc::now();
+        let lease = self.api.get_opt(LEASE_NAME).await.context(LeaseSnafu)?;
+
+        let mut lease = match lease {
+            Some(lease) => lease,
+            None => {
+                let lease = Lease {
+                    metadata: ObjectMeta {
+                        name: Some(LEASE_NAME.to_string()),
+                        ..Default::default()
+                    },
+                    spec: Some(LeaseSpec {
+                        holder_identity: Some(self.identity.clone()),
+                        acquire_time: Some(MicroTime(now)),
+                        renew_time: Some(MicroTime(now)),
+                        lease_duration_seconds: Some(LEASE_DURATION.as_secs() as i32),
+                        lease_transitions: Some(0),
+                    }),
+                };
+
+                return match self.api.create(&PostParams::default(), &lease).await {
+                    Ok(_) => Ok(true),
+                    Err(kube::Error::Api(e)) if e.code == 409 => Ok(false),
+                    Err(e) => Err(e).context(LeaseSnafu),
+                };
+            }
+        };
+
+        let mut spec = lease.spec.clone().unwrap_or_default();
+        let held = spec.holder_identity.as_deref() == Some(self.identity.as_str());
+        let duration = spec
+            .lease_duration_seconds
+            .map(|s| Duration::from_secs(s.max(0) as u64))
+            .unwrap_or(LEASE_DURATION);
+        let expired = spec
+            .renew_time
+            .as_ref()
+            .is_none_or(|t| time_after(t.0, duration) < now);
+
+        if !held && !expired {
+            return Ok(false);
+        }
+
+        if !held {
+            info!(
+                "taking over expired lease from {}",
+                spec.holder_identity.clone().unwrap_or_default()
+            );
+            spec.holder_identity = Some(self.identity.clone());
+            spec.acquire_time = Some(MicroTime(now));
+            spec.lease_transitions = Some(spec.lease_transitions.unwrap_or(0) + 1);
+        }
+        spec.renew_time = Some(MicroTime(now));
+        spec.lease_duration_seconds = Some(LEASE_DURATION.as_secs() as i32);
+        lease.spec = Some(spec);
+
+        // The resource version is kept, so a replica that raced us to the
+        // lease makes this replace fail with a conflict.
+        match self
+            .api
+            .replace(LEASE_NAME, &PostParams::default(), &lease)
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(e)) if e.code == 409 => Ok(false),
+            Err(e) => Err(e).context(LeaseSnafu),
+        }
+    }
+}
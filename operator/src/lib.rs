@@ -1,27 +1,47 @@
 use std::sync::Arc;
 
-use common::crds::Manager;
+use common::crds::{Manager, ManagerSpec};
+use common::models::FactionSymbol;
 use eyre::{Context, Ok, Result};
 use k8s_openapi::api::core::v1::Namespace;
 use kube::api::PostParams;
 use kube::core::ObjectMeta;
 use kube::{Api, Client, Config, CustomResourceExt};
-use openapi::apis::configuration::Configuration;
-use tokio::sync::RwLock;
+use serde::Serialize;
 use tokio::task::JoinSet;
 use tracing::info;
 
 mod agent;
+mod backoff;
+mod behaviour;
+mod contract;
+mod fleet;
+mod health;
+mod leader;
 mod manager;
+mod metrics;
+#[cfg(test)]
+mod mock;
+mod purchase_order;
+mod rbac;
+mod registry;
+mod reset;
 mod ship;
+mod status;
 
 pub use manager::init_manager;
 
 use crate::agent::AgentControllerData;
+use crate::behaviour::BehaviourRegistry;
+use crate::contract::ContractControllerData;
+use crate::fleet::FleetControllerData;
+use crate::health::Health;
+use crate::leader::LeaderElector;
+use crate::manager::ManagerControllerData;
+use crate::purchase_order::PurchaseOrderControllerData;
+use crate::registry::ApiConfigRegistry;
 use crate::ship::ShipControllerData;
 
-type ApiConfig = Arc<RwLock<Option<Configuration>>>;
-
 async fn get_client() -> Result<Client> {
     let config = Config::infer().await?;
     Client::try_from(config).wrap_err("error creating k8s client")
@@ -29,45 +49,116 @@ async fn get_client() -> Result<Client> {
 
 pub async fn run() -> Result<()> {
     info!("Running operator");
-    let client = get_client().await?;
-    let api_config = Arc::new(RwLock::new(None));
-    let agent_data = AgentControllerData::new(client.clone(), api_config.clone());
-    let ship_data = ShipControllerData::new(client.clone(), api_config.clone());
-
+    let health = Arc::new(Health::new());
     let mut set = JoinSet::new();
+    set.spawn(health::serve(health.clone()));
 
-    set.spawn(manager::run_controller(client.clone()));
+    let client = get_client().await?;
+    let api_config = ApiConfigRegistry::new();
+    let behaviours = BehaviourRegistry::new();
+    let db = Arc::new(common::repository::connect().await?);
+    let agent_data = AgentControllerData::new(
+        client.clone(),
+        api_config.clone(),
+        db.clone(),
+        behaviours.clone(),
+    );
+    let ship_data =
+        ShipControllerData::new(client.clone(), api_config.clone(), db.clone(), behaviours);
+    let purchase_order_data = PurchaseOrderControllerData::new(client.clone(), api_config.clone());
+    let fleet_data = FleetControllerData::new(client.clone(), api_config.clone(), db.clone());
+    let contract_data = ContractControllerData::new(client.clone(), api_config.clone());
+
+    // Replicas waiting for the lease count as ready so rollouts aren't
+    // blocked on the current leader stepping down.
+    health.set_ready();
+    let namespace = std::env::var("POD_NAMESPACE").unwrap_or("default".to_string());
+    let elector = LeaderElector::new(client.clone(), namespace.as_str());
+    elector.acquire().await?;
+
+    set.spawn(async move { elector.hold().await });
+    set.spawn(manager::run_controller(Arc::new(
+        ManagerControllerData::new(client.clone()),
+    )));
     set.spawn(agent::run_controller(Arc::new(agent_data)));
     set.spawn(ship::run_controller(Arc::new(ship_data)));
-
+    set.spawn(purchase_order::run_controller(Arc::new(
+        purchase_order_data,
+    )));
+    set.spawn(fleet::run_controller(Arc::new(fleet_data)));
+    set.spawn(contract::run_controller(Arc::new(contract_data)));
+    set.spawn(reset::run_watcher(client.clone(), db));
+
+    // Losing the lease ends the operator, the restarted pod then waits for
+    // it again without running any controllers.
     while let Some(result) = set.join_next().await {
-        let _ = result?;
+        result??;
     }
 
     Ok(())
 }
 
-pub fn crdgen() -> Result<()> {
-    let manager_crd = Manager::crd();
-    let manager_yaml = serde_yaml::to_string(&manager_crd)?;
-    print!("{}", manager_yaml);
+fn print_yaml<T: Serialize>(resource: &T) -> Result<()> {
+    print!("{}", serde_yaml::to_string(resource)?);
     println!("---");
+    Ok(())
+}
 
-    let agent_crd = common::crds::Agent::crd();
-    let agent_yaml = serde_yaml::to_string(&agent_crd)?;
+pub fn crdgen() -> Result<()> {
+    print_yaml(&Manager::crd())?;
+    print_yaml(&common::crds::Agent::crd())?;
+    print_yaml(&common::crds::Ship::crd())?;
+    print_yaml(&common::crds::PurchaseOrder::crd())?;
+    print_yaml(&common::crds::Fleet::crd())?;
+    print_yaml(&common::crds::Contract::crd())?;
 
-    println!("{}", agent_yaml);
-    println!("---");
+    Ok(())
+}
 
-    let ship_crd = common::crds::Ship::crd();
-    let ship_yaml = serde_yaml::to_string(&ship_crd)?;
-    print!("{}", ship_yaml);
-    println!("---");
+/// Prints the service account the operator runs as, bound to a cluster role
+/// that only grants what the controllers use and a role for the leader
+/// election lease.
+pub fn rbacgen(namespace: &str) -> Result<()> {
+    print_yaml(&rbac::service_account(namespace))?;
+    print_yaml(&rbac::cluster_role())?;
+    print_yaml(&rbac::cluster_role_binding(namespace))?;
+    print_yaml(&rbac::role(namespace))?;
+    print_yaml(&rbac::role_binding(namespace))?;
+
+    Ok(())
+}
+
+/// Prints a namespace and a manager in it, the same objects `init_manager`
+/// creates, for setups managed with plain manifests.
+pub fn sample_manager() -> Result<()> {
+    let namespace = "spacetraders-example";
+    let ns = Namespace {
+        metadata: ObjectMeta {
+            name: Some(namespace.into()),
+            ..Default::default()
+        },
+        spec: None,
+        status: None,
+    };
+
+    let mut manager = Manager::new(
+        "example",
+        ManagerSpec {
+            symbol: "EXAMPLE".to_string(),
+            faction: FactionSymbol::Cosmic,
+            namespace: namespace.to_string(),
+            delete_namespace: true,
+        },
+    );
+    manager.metadata.namespace = Some(namespace.to_string());
+
+    print_yaml(&ns)?;
+    print_yaml(&manager)?;
 
     Ok(())
 }
 
-pub(crate) async fn create_namespace(name: &str) -> Result<()> {
+pub(crate) async fn create_namespace(client: Client, name: &str) -> Result<()> {
     let ns = Namespace {
         metadata: ObjectMeta {
             namespace: Some(name.into()),
@@ -78,7 +169,6 @@ pub(crate) async fn create_namespace(name: &str) -> Result<()> {
         status: None,
     };
 
-    let client = crate::get_client().await?;
     let ns_api: Api<Namespace> = Api::all(client);
     ns_api.create(&PostParams::default(), &ns).await?;
 
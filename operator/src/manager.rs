@@ -1,17 +1,26 @@
 use common::{
-    crds::{Agent as K8sAgent, Manager, ManagerSpec},
+    crds::{Agent as K8sAgent, Condition, Manager, ManagerSpec},
     models::FactionSymbol,
 };
 use futures::StreamExt;
+use k8s_openapi::api::core::v1::Namespace;
 use kube::{
-    api::{ListParams, Patch, PatchParams, PostParams},
-    runtime::{controller::Action, Controller},
+    api::{DeleteParams, ListParams, Patch, PatchParams, PostParams},
+    runtime::{
+        controller::Action,
+        events::EventType,
+        finalizer::{self, finalizer, Event as Finalizer},
+        Controller,
+    },
     Api, Client, ResourceExt,
 };
 use snafu::{ResultExt, Snafu};
-use std::{sync::Arc, time::Duration};
-use tracing::info;
+use std::sync::Arc;
+use tracing::{info, warn};
 
+use crate::backoff::Backoff;
+use crate::metrics;
+use crate::status::{object_key, patch_conditions, publish_event};
 use crate::{agent::create_owned_agent, create_namespace};
 
 #[derive(Debug, Snafu)]
@@ -27,37 +36,208 @@ pub enum ManagerError {
 
     #[snafu(display("error patching agent status {}", source))]
     AgentStatusPatchError { source: kube::Error },
+
+    #[snafu(display("error patching manager status {}", source))]
+    ManagerStatusPatchError { source: kube::Error },
+
+    #[snafu(display("error deleting agent {}", source))]
+    DeleteAgentError { source: kube::Error },
+
+    #[snafu(display("error deleting namespace {}", source))]
+    DeleteNamespaceError { source: kube::Error },
+
+    #[snafu(display("finalizer error {}", source))]
+    FinalizerError {
+        #[snafu(source(from(finalizer::Error<ManagerError>, Box::new)))]
+        source: Box<finalizer::Error<ManagerError>>,
+    },
 }
 
-pub(crate) async fn run_controller(client: Client) -> eyre::Result<()> {
-    let manager = Api::<Manager>::all(client);
+const MANAGER_FINALIZER: &str = "spacetraders.io/manager-cleanup";
+
+pub struct ManagerControllerData {
+    pub k8s_client: Client,
+    pub backoff: Backoff,
+}
+
+impl ManagerControllerData {
+    pub fn new(k8s_client: Client) -> Self {
+        Self {
+            k8s_client,
+            backoff: Backoff::new(),
+        }
+    }
+}
+
+pub(crate) async fn run_controller(data: Arc<ManagerControllerData>) -> eyre::Result<()> {
+    let manager = Api::<Manager>::all(data.k8s_client.clone());
 
     Controller::new(manager.clone(), Default::default())
-        .run(reconcile, error_policy, Arc::new(()))
+        .run(
+            |obj, ctx| metrics::measure("manager", reconcile(obj, ctx)),
+            error_policy,
+            data,
+        )
         .for_each(|_| futures::future::ready(()))
         .await;
 
     Ok(())
 }
 
-pub(crate) fn error_policy(_object: Arc<Manager>, _err: &ManagerError, _ctx: Arc<()>) -> Action {
-    Action::requeue(Duration::from_secs(5))
+pub(crate) fn error_policy(
+    object: Arc<Manager>,
+    err: &ManagerError,
+    ctx: Arc<ManagerControllerData>,
+) -> Action {
+    warn!("error reconciling manager: {}", err);
+    Action::requeue(ctx.backoff.next(object_key(object.as_ref()).as_str()))
 }
 
 pub(crate) async fn reconcile(
     manager: Arc<Manager>,
-    _ctx: Arc<()>,
+    ctx: Arc<ManagerControllerData>,
+) -> Result<Action, ManagerError> {
+    let ns = manager.namespace().unwrap_or("default".to_string());
+    let manager_api: Api<Manager> = Api::namespaced(ctx.k8s_client.clone(), ns.as_str());
+
+    finalizer(&manager_api, MANAGER_FINALIZER, manager, |event| async {
+        match event {
+            Finalizer::Apply(manager) => apply(manager, ctx.clone()).await,
+            Finalizer::Cleanup(manager) => cleanup(manager, ctx.clone()).await,
+        }
+    })
+    .await
+    .context(FinalizerSnafu)
+}
+
+async fn apply(
+    manager: Arc<Manager>,
+    ctx: Arc<ManagerControllerData>,
 ) -> Result<Action, ManagerError> {
     info!("Reconciling manager");
+    let current = manager
+        .status
+        .as_ref()
+        .map(|s| s.conditions.clone())
+        .unwrap_or_default();
+
+    let conditions = match reconcile_agent(manager.clone(), ctx.clone()).await {
+        Ok(reason) => {
+            ctx.backoff.reset(object_key(manager.as_ref()).as_str());
+            vec![
+                Condition::new(
+                    Condition::READY,
+                    true,
+                    reason,
+                    format!("agent {} is managed", manager.spec.symbol),
+                ),
+                Condition::new(Condition::DEGRADED, false, "Reconciled", "".to_string()),
+            ]
+        }
+        Err(err) => {
+            publish_event(
+                ctx.k8s_client.clone(),
+                manager.as_ref(),
+                EventType::Warning,
+                "ReconcileFailed",
+                err.to_string(),
+            )
+            .await;
+
+            let conditions = vec![
+                Condition::new(Condition::READY, false, "ReconcileFailed", err.to_string()),
+                Condition::new(
+                    Condition::DEGRADED,
+                    true,
+                    "ReconcileFailed",
+                    err.to_string(),
+                ),
+            ];
+            patch_conditions(
+                ctx.k8s_client.clone(),
+                manager.as_ref(),
+                &current,
+                conditions,
+            )
+            .await
+            .context(ManagerStatusPatchSnafu)?;
+
+            return Err(err);
+        }
+    };
+
+    patch_conditions(
+        ctx.k8s_client.clone(),
+        manager.as_ref(),
+        &current,
+        conditions,
+    )
+    .await
+    .context(ManagerStatusPatchSnafu)?;
+
+    Ok(Action::await_change())
+}
+
+/// Deletes the manager's agent, whose own finalizer stops its ships and
+/// releases its token, and the namespace when the spec asks for it. Objects
+/// that are already gone are skipped so the cleanup can be retried.
+async fn cleanup(
+    manager: Arc<Manager>,
+    ctx: Arc<ManagerControllerData>,
+) -> Result<Action, ManagerError> {
+    info!("cleaning up manager {}", manager.name_any());
+
+    let agent_api: Api<K8sAgent> =
+        Api::namespaced(ctx.k8s_client.clone(), manager.spec.namespace.as_str());
+    let agents = agent_api
+        .list(&ListParams::default())
+        .await
+        .context(ListAgentSnafu)?
+        .items;
+
+    for agent in agents {
+        match agent_api
+            .delete(agent.name_any().as_str(), &DeleteParams::default())
+            .await
+        {
+            Ok(_) | Err(kube::Error::Api(kube::error::ErrorResponse { code: 404, .. })) => {}
+            Err(e) => return Err(e).context(DeleteAgentSnafu),
+        }
+    }
+
+    if manager.spec.delete_namespace {
+        let ns_api: Api<Namespace> = Api::all(ctx.k8s_client.clone());
+        match ns_api
+            .delete(manager.spec.namespace.as_str(), &DeleteParams::default())
+            .await
+        {
+            Ok(_) | Err(kube::Error::Api(kube::error::ErrorResponse { code: 404, .. })) => {}
+            Err(e) => return Err(e).context(DeleteNamespaceSnafu),
+        }
+    }
+
+    ctx.backoff.reset(object_key(manager.as_ref()).as_str());
+    info!("manager {} cleaned up", manager.name_any());
+    Ok(Action::await_change())
+}
+
+/// Creates the manager's agent unless it exists already. Returns the reason
+/// reported on the Ready condition.
+async fn reconcile_agent(
+    manager: Arc<Manager>,
+    ctx: Arc<ManagerControllerData>,
+) -> Result<&'static str, ManagerError> {
     let serverside = PatchParams::apply("operator");
-    let client = crate::get_client().await.unwrap();
-    let agent_api: Api<K8sAgent> = Api::namespaced(client, manager.spec.namespace.clone().as_str());
+    let agent_api: Api<K8sAgent> = Api::namespaced(
+        ctx.k8s_client.clone(),
+        manager.spec.namespace.clone().as_str(),
+    );
 
     let lp = ListParams::default();
     let agents = agent_api.list(&lp).await.context(ListAgentSnafu)?.items;
     let new_agent = create_owned_agent(manager.clone());
     let agent = match &agents[..] {
-        [_] => return Ok(Action::await_change()),
+        [_] => return Ok("AgentExists"),
         [] => &new_agent,
         [_, ..] => {
             return TooManyAgentsSnafu {
@@ -85,22 +265,41 @@ pub(crate) async fn reconcile(
         .await
         .context(AgentStatusPatchSnafu)?;
 
+    publish_event(
+        ctx.k8s_client.clone(),
+        manager.as_ref(),
+        EventType::Normal,
+        "AgentCreated",
+        format!("created agent {}", manager.spec.symbol),
+    )
+    .await;
+
     info!("manager {} reconciled", agent.name_any());
-    Ok(Action::await_change())
+    Ok("AgentCreated")
 }
 
 pub async fn init_manager(symbol: String, faction: FactionSymbol) -> eyre::Result<()> {
     let client = crate::get_client().await?;
+    create_manager(client, symbol, faction).await
+}
 
+/// Creates a namespace for the agent and a manager in it.
+pub(crate) async fn create_manager(
+    client: Client,
+    symbol: String,
+    faction: FactionSymbol,
+) -> eyre::Result<()> {
     let name = symbol.clone().to_lowercase();
     let namespace = format!("spacetraders-{}", name);
-    create_namespace(namespace.as_str()).await?;
+    create_namespace(client.clone(), namespace.as_str()).await?;
 
     let manager_api: Api<Manager> = Api::namespaced(client, namespace.as_str());
+    // The namespace only exists for this manager, so it goes with it.
     let manager_spec = ManagerSpec {
         symbol: symbol.clone(),
         namespace,
         faction,
+        delete_namespace: true,
     };
 
     let manager = Manager::new(name.as_str(), manager_spec);
@@ -109,3 +308,109 @@ pub async fn init_manager(symbol: String, faction: FactionSymbol) -> eyre::Resul
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hyper::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    const NAMESPACE: &str = "spacetraders-test";
+
+    fn manager() -> Arc<Manager> {
+        let mut manager = Manager::new(
+            "test",
+            ManagerSpec {
+                symbol: "TEST".to_string(),
+                faction: FactionSymbol::Cosmic,
+                namespace: NAMESPACE.to_string(),
+                delete_namespace: true,
+            },
+        );
+        manager.metadata.namespace = Some(NAMESPACE.to_string());
+        manager.metadata.uid = Some("manager-uid".to_string());
+        manager.metadata.finalizers = Some(vec![MANAGER_FINALIZER.to_string()]);
+        Arc::new(manager)
+    }
+
+    fn context(agents: usize) -> (Arc<ManagerControllerData>, mock::Requests) {
+        let manager = manager();
+        let agent = json!(create_owned_agent(manager.clone()));
+
+        let (client, requests) =
+            mock::kube_client(move |method, path, body| match (method, path) {
+                (&Method::GET, p) if p.contains("/agents") => {
+                    mock::list("Agent", vec![agent.clone(); agents])
+                }
+                (&Method::PATCH, p) if p.contains("/agents/") => (StatusCode::OK, body.clone()),
+                (&Method::PATCH, p) if p.contains("/managers/") => {
+                    (StatusCode::OK, json!(manager.as_ref()))
+                }
+                _ => mock::not_found(),
+            });
+
+        (Arc::new(ManagerControllerData::new(client)), requests)
+    }
+
+    fn ready_reason(requests: &mock::Requests) -> Option<String> {
+        requests
+            .matching(Method::PATCH, "/managers/test/status")
+            .last()?
+            .body["status"]["conditions"]
+            .as_array()?
+            .iter()
+            .find(|c| c["type"] == Condition::READY)
+            .map(|c| c["reason"].as_str().unwrap_or_default().to_string())
+    }
+
+    #[tokio::test]
+    async fn creates_agent_for_manager() {
+        let (ctx, requests) = context(0);
+
+        let action = reconcile(manager(), ctx).await.unwrap();
+        assert_eq!(action, Action::await_change());
+
+        let patches = requests.matching(Method::PATCH, "/namespaces/spacetraders-test/agents/test");
+        let agent = &patches.first().expect("agent was not created").body;
+        assert_eq!(agent["spec"]["symbol"], "TEST");
+        assert_eq!(agent["spec"]["faction"], "Cosmic");
+        assert_eq!(agent["metadata"]["ownerReferences"][0]["kind"], "Manager");
+
+        assert_eq!(ready_reason(&requests).as_deref(), Some("AgentCreated"));
+    }
+
+    #[tokio::test]
+    async fn keeps_existing_agent() {
+        let (ctx, requests) = context(1);
+
+        reconcile(manager(), ctx).await.unwrap();
+
+        assert!(requests.matching(Method::PATCH, "/agents/").is_empty());
+        assert_eq!(ready_reason(&requests).as_deref(), Some("AgentExists"));
+    }
+
+    #[tokio::test]
+    async fn too_many_agents_requeue_with_backoff() {
+        let (ctx, requests) = context(2);
+        let manager = manager();
+
+        let err = reconcile(manager.clone(), ctx.clone()).await.unwrap_err();
+        match &err {
+            ManagerError::FinalizerError { source } => assert!(matches!(
+                **source,
+                finalizer::Error::ApplyFailed(ManagerError::TooManyAgentsError { num_agents: 2 })
+            )),
+            err => panic!("unexpected error {}", err),
+        }
+        assert_eq!(ready_reason(&requests).as_deref(), Some("ReconcileFailed"));
+
+        let first = error_policy(manager.clone(), &err, ctx.clone());
+        let second = error_policy(manager, &err, ctx);
+        assert_eq!(first, Action::requeue(Duration::from_secs(5)));
+        assert_eq!(second, Action::requeue(Duration::from_secs(10)));
+    }
+}
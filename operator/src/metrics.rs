@@ -0,0 +1,189 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use prometheus::{
+    histogram_opts, opts, Encoder, HistogramVec, IntCounterVec, IntGauge, Registry, TextEncoder,
+};
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use task_local_extensions::Extensions;
+
+/// Everything the operator exports on `/metrics`.
+pub(crate) struct Metrics {
+    registry: Registry,
+    reconciliations: IntCounterVec,
+    failures: IntCounterVec,
+    duration: HistogramVec,
+    api_requests: IntCounterVec,
+    pub leader: IntGauge,
+}
+
+pub(crate) static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("spacetraders".to_string()), None).unwrap();
+
+        let reconciliations = IntCounterVec::new(
+            opts!("reconciliations_total", "Reconciliations by controller"),
+            &["controller"],
+        )
+        .unwrap();
+        let failures = IntCounterVec::new(
+            opts!(
+                "reconcile_errors_total",
+                "Failed reconciliations by controller"
+            ),
+            &["controller"],
+        )
+        .unwrap();
+        let duration = HistogramVec::new(
+            histogram_opts!(
+                "reconcile_duration_seconds",
+                "Time spent reconciling by controller",
+                vec![0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 15.0, 60.0]
+            ),
+            &["controller"],
+        )
+        .unwrap();
+        let api_requests = IntCounterVec::new(
+            opts!("api_requests_total", "SpaceTraders API requests"),
+            &["method", "endpoint", "status"],
+        )
+        .unwrap();
+        let leader = IntGauge::new("leader", "Whether this replica holds the lease").unwrap();
+
+        registry
+            .register(Box::new(reconciliations.clone()))
+            .unwrap();
+        registry.register(Box::new(failures.clone())).unwrap();
+        registry.register(Box::new(duration.clone())).unwrap();
+        registry.register(Box::new(api_requests.clone())).unwrap();
+        registry.register(Box::new(leader.clone())).unwrap();
+
+        Self {
+            registry,
+            reconciliations,
+            failures,
+            duration,
+            api_requests,
+            leader,
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buffer = vec![];
+        let encoder = TextEncoder::new();
+        if let Err(e) = encoder.encode(&self.registry.gather(), &mut buffer) {
+            tracing::warn!("error encoding metrics: {}", e);
+        }
+
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// Runs a reconcile and records its outcome and duration.
+pub(crate) async fn measure<T, E, F>(controller: &str, reconcile: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Display,
+{
+    let start = Instant::now();
+    let result = reconcile.await;
+
+    METRICS
+        .duration
+        .with_label_values(&[controller])
+        .observe(start.elapsed().as_secs_f64());
+    METRICS
+        .reconciliations
+        .with_label_values(&[controller])
+        .inc();
+    if result.is_err() {
+        METRICS.failures.with_label_values(&[controller]).inc();
+    }
+
+    result
+}
+
+/// Counts the API requests of every configuration the operator hands out,
+/// by method, endpoint and response status.
+pub(crate) struct ApiRequestCounter {
+    /// Path of the configuration's base path, cut off the request paths.
+    pub base_path: String,
+}
+
+#[async_trait::async_trait]
+impl Middleware for ApiRequestCounter {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let method = req.method().to_string();
+        let path = req.url().path();
+        let endpoint = endpoint(path.strip_prefix(&self.base_path).unwrap_or(path));
+
+        let res = next.run(req, extensions).await;
+        let status = match &res {
+            Ok(res) => res.status().as_u16().to_string(),
+            Err(_) => "error".to_string(),
+        };
+        METRICS
+            .api_requests
+            .with_label_values(&[method.as_str(), endpoint.as_str(), status.as_str()])
+            .inc();
+
+        res
+    }
+}
+
+/// The path template of a request path, e.g. `/my/ships/{shipSymbol}/dock`
+/// for `/my/ships/AGENT-1/dock`, so symbols don't end up in label values.
+fn endpoint(path: &str) -> String {
+    let mut segments = path.split('/').collect::<Vec<&str>>();
+    for i in 1..segments.len() {
+        let parameter = match segments[i - 1] {
+            "agents" => "{agentSymbol}",
+            "contracts" => "{contractId}",
+            "factions" => "{factionSymbol}",
+            "ships" => "{shipSymbol}",
+            "systems" => "{systemSymbol}",
+            "waypoints" => "{waypointSymbol}",
+            _ => continue,
+        };
+        segments[i] = parameter;
+    }
+
+    match segments.join("/") {
+        endpoint if endpoint.is_empty() => "/".to_string(),
+        endpoint => endpoint,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::endpoint;
+
+    #[test]
+    fn endpoint_replaces_symbols_with_parameters() {
+        assert_eq!(endpoint(""), "/");
+        assert_eq!(endpoint("/my/ships"), "/my/ships");
+        assert_eq!(
+            endpoint("/my/ships/AGENT-1/scan/ships"),
+            "/my/ships/{shipSymbol}/scan/ships"
+        );
+        assert_eq!(
+            endpoint("/systems/X1-DF55/waypoints/X1-DF55-20250Z/market"),
+            "/systems/{systemSymbol}/waypoints/{waypointSymbol}/market"
+        );
+        assert_eq!(
+            endpoint("/my/contracts/clm0n4k8q/deliver"),
+            "/my/contracts/{contractId}/deliver"
+        );
+    }
+}
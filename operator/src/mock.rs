@@ -0,0 +1,274 @@
+//! Stand-ins for the Kubernetes API and the SpaceTraders API so controllers
+//! can be reconciled in tests without a cluster or network access.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use hyper::http::{Method, Request, Response, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Server};
+use kube::Client;
+use serde_json::{json, Value};
+
+/// A request seen by one of the mocks.
+#[derive(Clone, Debug)]
+pub(crate) struct Recorded {
+    pub method: Method,
+    pub path: String,
+    pub body: Value,
+}
+
+type Handler = dyn Fn(&Method, &str, &Value) -> (StatusCode, Value) + Send + Sync;
+
+/// Every request the mock received, in order.
+#[derive(Clone, Default)]
+pub(crate) struct Requests(Arc<Mutex<Vec<Recorded>>>);
+
+impl Requests {
+    fn push(&self, method: &Method, path: &str, body: Value) {
+        self.0.lock().unwrap().push(Recorded {
+            method: method.clone(),
+            path: path.to_string(),
+            body,
+        });
+    }
+
+    pub fn all(&self) -> Vec<Recorded> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Requests with the method whose path contains `path`.
+    pub fn matching(&self, method: Method, path: &str) -> Vec<Recorded> {
+        self.all()
+            .into_iter()
+            .filter(|r| r.method == method && r.path.contains(path))
+            .collect()
+    }
+}
+
+async fn read_body(body: Body) -> Value {
+    let bytes = hyper::body::to_bytes(body).await.unwrap_or_default();
+    serde_json::from_slice(&bytes).unwrap_or(Value::Null)
+}
+
+fn respond(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// A kube client answering from `handler`. Events are accepted without
+/// reaching the handler since every controller publishes them.
+pub(crate) fn kube_client<F>(handler: F) -> (Client, Requests)
+where
+    F: Fn(&Method, &str, &Value) -> (StatusCode, Value) + Send + Sync + 'static,
+{
+    let (service, mut handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+    let requests = Requests::default();
+    let handler: Arc<Handler> = Arc::new(handler);
+
+    let recorder = requests.clone();
+    tokio::spawn(async move {
+        while let Some((request, send)) = handle.next_request().await {
+            let method = request.method().clone();
+            let path = request.uri().to_string();
+            let body = read_body(request.into_body()).await;
+            recorder.push(&method, path.as_str(), body.clone());
+
+            let (status, body) = match path.contains("/events") {
+                true => (StatusCode::CREATED, body),
+                false => handler(&method, path.as_str(), &body),
+            };
+            send.send_response(respond(status, body));
+        }
+    });
+
+    (Client::new(service, "default"), requests)
+}
+
+/// Serves `handler` on a local port and returns the base path to point an
+/// API configuration at.
+pub(crate) async fn spacetraders<F>(handler: F) -> (String, Requests)
+where
+    F: Fn(&Method, &str, &Value) -> (StatusCode, Value) + Send + Sync + 'static,
+{
+    let requests = Requests::default();
+    let handler: Arc<Handler> = Arc::new(handler);
+
+    let recorder = requests.clone();
+    let make_service = make_service_fn(move |_| {
+        let handler = handler.clone();
+        let recorder = recorder.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let handler = handler.clone();
+                let recorder = recorder.clone();
+                async move {
+                    let method = request.method().clone();
+                    let path = request.uri().to_string();
+                    let body = read_body(request.into_body()).await;
+                    recorder.push(&method, path.as_str(), body.clone());
+
+                    let (status, body) = handler(&method, path.as_str(), &body);
+                    Ok::<_, Infallible>(respond(status, body))
+                }
+            }))
+        }
+    });
+
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+    let base_path = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    (base_path, requests)
+}
+
+pub(crate) fn not_found() -> (StatusCode, Value) {
+    (
+        StatusCode::NOT_FOUND,
+        json!({
+            "kind": "Status",
+            "apiVersion": "v1",
+            "metadata": {},
+            "status": "Failure",
+            "message": "not found",
+            "reason": "NotFound",
+            "code": 404,
+        }),
+    )
+}
+
+pub(crate) fn list(kind: &str, items: Vec<Value>) -> (StatusCode, Value) {
+    (
+        StatusCode::OK,
+        json!({
+            "apiVersion": "spacetraders.io/v1",
+            "kind": format!("{}List", kind),
+            "metadata": {},
+            "items": items,
+        }),
+    )
+}
+
+/// A page of results the way the SpaceTraders API returns lists.
+pub(crate) fn page(items: Vec<Value>) -> (StatusCode, Value) {
+    (
+        StatusCode::OK,
+        json!({
+            "data": items,
+            "meta": { "total": items.len(), "page": 1, "limit": 20 },
+        }),
+    )
+}
+
+pub(crate) fn agent(symbol: &str, credits: i64) -> Value {
+    json!({
+        "accountId": "account",
+        "symbol": symbol,
+        "headquarters": "X1-TEST-A1",
+        "credits": credits,
+        "startingFaction": "COSMIC",
+        "shipCount": 1,
+    })
+}
+
+fn requirements() -> Value {
+    json!({ "power": 1, "crew": 0, "slots": 0 })
+}
+
+fn route_waypoint(symbol: &str) -> Value {
+    json!({
+        "symbol": symbol,
+        "type": "PLANET",
+        "systemSymbol": "X1-TEST",
+        "x": 0,
+        "y": 0,
+    })
+}
+
+/// A probe docked at the headquarters with nothing in its hold.
+pub(crate) fn ship(symbol: &str) -> Value {
+    json!({
+        "symbol": symbol,
+        "registration": { "name": symbol, "factionSymbol": "COSMIC", "role": "SATELLITE" },
+        "nav": {
+            "systemSymbol": "X1-TEST",
+            "waypointSymbol": "X1-TEST-A1",
+            "route": {
+                "destination": route_waypoint("X1-TEST-A1"),
+                "origin": route_waypoint("X1-TEST-A1"),
+                "departureTime": "2024-01-01T00:00:00.000Z",
+                "arrival": "2024-01-01T00:00:00.000Z",
+            },
+            "status": "DOCKED",
+            "flightMode": "CRUISE",
+        },
+        "crew": {
+            "current": 0, "required": 0, "capacity": 0,
+            "rotation": "STRICT", "morale": 100, "wages": 0,
+        },
+        "frame": {
+            "symbol": "FRAME_PROBE", "name": "Probe", "description": "",
+            "moduleSlots": 0, "mountingPoints": 0, "fuelCapacity": 0,
+            "requirements": requirements(),
+        },
+        "reactor": {
+            "symbol": "REACTOR_SOLAR_I", "name": "Solar", "description": "",
+            "powerOutput": 3, "requirements": requirements(),
+        },
+        "engine": {
+            "symbol": "ENGINE_IMPULSE_DRIVE_I", "name": "Impulse", "description": "",
+            "speed": 3, "requirements": requirements(),
+        },
+        "cooldown": { "shipSymbol": symbol, "totalSeconds": 0, "remainingSeconds": 0 },
+        "modules": [],
+        "mounts": [],
+        "cargo": { "capacity": 0, "units": 0, "inventory": [] },
+        "fuel": { "current": 0, "capacity": 0 },
+    })
+}
+
+pub(crate) fn contract(id: &str) -> Value {
+    json!({
+        "id": id,
+        "factionSymbol": "COSMIC",
+        "type": "PROCUREMENT",
+        "terms": {
+            "deadline": "2024-01-08T00:00:00.000Z",
+            "payment": { "onAccepted": 1000, "onFulfilled": 5000 },
+            "deliver": [{
+                "tradeSymbol": "IRON_ORE",
+                "destinationSymbol": "X1-TEST-A1",
+                "unitsRequired": 50,
+                "unitsFulfilled": 0,
+            }],
+        },
+        "accepted": false,
+        "fulfilled": false,
+        "expiration": "2024-01-02T00:00:00.000Z",
+        "deadlineToAccept": "2024-01-02T00:00:00.000Z",
+    })
+}
+
+/// The response to registering a new agent.
+pub(crate) fn registration(symbol: &str, token: &str) -> Value {
+    json!({
+        "data": {
+            "agent": agent(symbol, 175000),
+            "contract": contract("contract-1"),
+            "faction": {
+                "symbol": "COSMIC",
+                "name": "Cosmic Engineers",
+                "description": "",
+                "headquarters": "X1-TEST",
+                "traits": [],
+                "isRecruiting": true,
+            },
+            "ship": ship(format!("{}-1", symbol).as_str()),
+            "token": token,
+        }
+    })
+}
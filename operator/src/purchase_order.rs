@@ -0,0 +1,617 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::crds::{
+    set_condition, Agent as K8sAgent, Condition, PurchaseIntent, PurchaseOrder, Ship as K8sShip,
+};
+use common::models::{Ship, ShipNavStatus, ShipRole};
+use futures::StreamExt;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::api::{ListParams, Patch, PatchParams};
+use kube::runtime::controller::Action;
+use kube::runtime::events::EventType;
+use kube::runtime::Controller;
+use kube::{Api, Client, Resource, ResourceExt};
+use openapi::apis;
+use openapi::apis::agents_api::{self, GetMyAgentError};
+use openapi::apis::configuration::Configuration;
+use openapi::apis::fleet_api::{self, NavigateShipError, OrbitShipError, PurchaseShipError};
+use openapi::apis::systems_api::{self, GetShipyardError};
+use openapi::models::{NavigateShipRequest, PurchaseShipRequest};
+use serde_json::json;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use tracing::{info, warn};
+
+use crate::backoff::Backoff;
+use crate::metrics;
+use crate::registry::{resolve_agent, ApiConfigRegistry};
+use crate::ship::patch_ship;
+use crate::status::{object_key, publish_event};
+
+pub struct PurchaseOrderControllerData {
+    pub api_config: ApiConfigRegistry,
+    pub k8s_client: Client,
+    pub backoff: Backoff,
+}
+
+impl PurchaseOrderControllerData {
+    pub fn new(k8s_client: Client, api_config: ApiConfigRegistry) -> Self {
+        Self {
+            api_config,
+            k8s_client,
+            backoff: Backoff::new(),
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum PurchaseOrderError {
+    #[snafu(display("api config is not available yet"))]
+    ApiConfigNotAvailable,
+
+    #[snafu(display("no agent found for purchase order {}", order))]
+    AgentNotFound { order: String },
+
+    #[snafu(display("error fetching ships {}", source))]
+    GetShipsError { source: eyre::Report },
+
+    #[snafu(display("error fetching agent {}", source))]
+    GetAgentError {
+        source: apis::Error<GetMyAgentError>,
+    },
+
+    #[snafu(display("error fetching shipyard {}", source))]
+    GetShipyardError {
+        source: apis::Error<GetShipyardError>,
+    },
+
+    #[snafu(display("error orbiting ship {}", source))]
+    OrbitShipError { source: apis::Error<OrbitShipError> },
+
+    #[snafu(display("error navigating ship {}", source))]
+    NavigateShipError {
+        source: apis::Error<NavigateShipError>,
+    },
+
+    #[snafu(display("error purchasing ship {}", source))]
+    PurchaseShipError {
+        source: apis::Error<PurchaseShipError>,
+    },
+
+    #[snafu(display("error listing agents {}", source))]
+    ListAgentError { source: kube::Error },
+
+    #[snafu(display("error listing purchase orders {}", source))]
+    ListOrderError { source: kube::Error },
+
+    #[snafu(display("error listing ships {}", source))]
+    ListShipError { source: kube::Error },
+
+    #[snafu(display("error patching purchase order {}", source))]
+    PatchOrderError { source: kube::Error },
+
+    #[snafu(display("error patching ship {}", source))]
+    ShipPatchError { source: eyre::Report },
+}
+
+pub(crate) async fn run_controller(data: Arc<PurchaseOrderControllerData>) -> eyre::Result<()> {
+    let purchase_order = Api::<PurchaseOrder>::all(data.k8s_client.clone());
+
+    Controller::new(purchase_order.clone(), Default::default())
+        .run(
+            |obj, ctx| metrics::measure("purchase_order", reconcile(obj, ctx)),
+            error_policy,
+            data,
+        )
+        .for_each(|_| futures::future::ready(()))
+        .await;
+
+    Ok(())
+}
+
+pub(crate) fn error_policy(
+    object: Arc<PurchaseOrder>,
+    err: &PurchaseOrderError,
+    ctx: Arc<PurchaseOrderControllerData>,
+) -> Action {
+    warn!("error reconciling purchase order: {}", err);
+    Action::requeue(ctx.backoff.next(object_key(object.as_ref()).as_str()))
+}
+
+pub(crate) async fn reconcile(
+    order: Arc<PurchaseOrder>,
+    ctx: Arc<PurchaseOrderControllerData>,
+) -> Result<Action, PurchaseOrderError> {
+    info!("Reconciling purchase order {}", order.name_any());
+
+    match reconcile_order(order.clone(), ctx.clone()).await {
+        Ok(action) => {
+            ctx.backoff.reset(object_key(order.as_ref()).as_str());
+            Ok(action)
+        }
+        Err(err) => {
+            publish_event(
+                ctx.k8s_client.clone(),
+                order.as_ref(),
+                EventType::Warning,
+                "ReconcileFailed",
+                err.to_string(),
+            )
+            .await;
+
+            let conditions = vec![
+                Condition::new(Condition::READY, false, "ReconcileFailed", err.to_string()),
+                Condition::new(
+                    Condition::DEGRADED,
+                    true,
+                    "ReconcileFailed",
+                    err.to_string(),
+                ),
+            ];
+            let status = order.status.clone().unwrap_or_default();
+            patch_status(
+                ctx.k8s_client.clone(),
+                &order,
+                status.complete,
+                status.ship_symbol,
+                status.price,
+                conditions,
+            )
+            .await?;
+
+            Err(err)
+        }
+    }
+}
+
+async fn reconcile_order(
+    order: Arc<PurchaseOrder>,
+    ctx: Arc<PurchaseOrderControllerData>,
+) -> Result<Action, PurchaseOrderError> {
+    let ns = order.namespace().unwrap_or("default".to_string());
+    let status = order.status.clone().unwrap_or_default();
+
+    let agent = resolve_agent(
+        ctx.k8s_client.clone(),
+        ns.as_str(),
+        order.owner_references(),
+    )
+    .await
+    .context(ListAgentSnafu)?
+    .context(AgentNotFoundSnafu {
+        order: order.name_any(),
+    })?;
+
+    // The ship CR is applied again on every pass so a failure after the
+    // purchase never leaves the new ship unmanaged.
+    if let (true, Some(symbol)) = (status.complete, status.ship_symbol.clone()) {
+        let oref = agent_owner_ref(ctx.k8s_client.clone(), ns.as_str(), agent.as_str()).await?;
+        patch_ship(ctx.k8s_client.clone(), symbol, Some(ns), oref)
+            .await
+            .context(ShipPatchSnafu)?;
+
+        return Ok(Action::await_change());
+    }
+
+    let cfg = ctx.api_config.get(ns.as_str(), agent.as_str()).await;
+    ensure!(cfg.is_some(), ApiConfigNotAvailableSnafu);
+    let cfg = &cfg.unwrap();
+
+    let shipyard = &order.spec.location;
+    let ships = common::machines::actions::get_all_ships(cfg)
+        .await
+        .context(GetShipsSnafu)?;
+
+    // A purchase that was started may have gone through without being
+    // recorded, the ship it bought is picked up instead of buying another.
+    if let Some(intent) = &status.purchasing {
+        if let Some(ship) = bought_ship(ctx.k8s_client.clone(), &order, intent, &ships).await? {
+            info!("{} was already purchased for {}", ship, order.name_any());
+            let message = format!("purchased {} at {}", ship, intent.location);
+            return complete(&ctx, &order, ns, &agent, ship, intent.price, message).await;
+        }
+    }
+
+    let at_shipyard = ships
+        .iter()
+        .any(|s| &s.nav.location == shipyard && s.nav.status != ShipNavStatus::InTransit);
+
+    if !at_shipyard {
+        return route_ship(order.clone(), ctx.clone(), cfg, &ships).await;
+    }
+
+    let res = systems_api::get_shipyard(
+        cfg,
+        shipyard.system_ident().as_str(),
+        shipyard.waypoint_ident().as_str(),
+    )
+    .await
+    .context(GetShipyardSnafu)?;
+
+    let ship_type: openapi::models::ShipType = order.spec.ship_type.clone().into();
+    let listing = res
+        .data
+        .ships
+        .unwrap_or_default()
+        .into_iter()
+        .find(|s| s.r#type == ship_type);
+
+    let (price, frame) = match listing {
+        Some(listing) => (
+            listing.purchase_price as i64,
+            serde_json::to_value(listing.frame.symbol)
+                .ok()
+                .and_then(|v| v.as_str().map(String::from)),
+        ),
+        None => {
+            let message = format!("{} is not sold at {}", order.spec.ship_type, shipyard);
+            waiting(&ctx, &order, "ShipTypeUnavailable", message).await?;
+            return Ok(Action::requeue(Duration::from_secs(300)));
+        }
+    };
+
+    let res = agents_api::get_my_agent(cfg).await.context(GetAgentSnafu)?;
+
+    let credits = res.data.credits;
+    if credits < price {
+        let message = format!(
+            "{} costs {} but only {} available",
+            order.spec.ship_type, price, credits
+        );
+        waiting(&ctx, &order, "InsufficientCredits", message).await?;
+        return Ok(Action::requeue(Duration::from_secs(60)));
+    }
+
+    let intent = PurchaseIntent {
+        location: shipyard.clone(),
+        ship_type: order.spec.ship_type.clone(),
+        frame,
+        price,
+        known_ships: ships.iter().map(|s| s.symbol.clone()).collect(),
+    };
+    patch_purchasing(ctx.k8s_client.clone(), &order, Some(&intent)).await?;
+
+    let req = PurchaseShipRequest::new(ship_type, shipyard.waypoint_ident());
+    let res = match fleet_api::purchase_ship(cfg, Some(req)).await {
+        Ok(res) => res,
+        Err(err) => {
+            // A rejected purchase bought nothing. Without an answer the
+            // purchase may have gone through, the intent stays to find out.
+            if let apis::Error::ResponseError(_) = err {
+                patch_purchasing(ctx.k8s_client.clone(), &order, None).await?;
+            }
+            return Err(err).context(PurchaseShipSnafu);
+        }
+    };
+
+    let ship = Ship::from(res.data.ship);
+    info!(
+        "purchased {} for {} credits",
+        ship.symbol, res.data.transaction.price
+    );
+
+    let message = format!(
+        "purchased {} at {} for {} credits",
+        ship.symbol, shipyard, res.data.transaction.price
+    );
+    complete(
+        &ctx,
+        &order,
+        ns,
+        &agent,
+        ship.symbol,
+        res.data.transaction.price as i64,
+        message,
+    )
+    .await
+}
+
+/// Marks the order complete and hands the ship over to a Ship CR.
+async fn complete(
+    ctx: &PurchaseOrderControllerData,
+    order: &PurchaseOrder,
+    ns: String,
+    agent: &str,
+    ship_symbol: String,
+    price: i64,
+    message: String,
+) -> Result<Action, PurchaseOrderError> {
+    publish_event(
+        ctx.k8s_client.clone(),
+        order,
+        EventType::Normal,
+        "Purchased",
+        message.clone(),
+    )
+    .await;
+
+    let conditions = vec![
+        Condition::new(Condition::READY, true, "Purchased", message),
+        Condition::new(Condition::DEGRADED, false, "Purchased", "".to_string()),
+    ];
+    patch_status(
+        ctx.k8s_client.clone(),
+        order,
+        true,
+        Some(ship_symbol.clone()),
+        Some(price),
+        conditions,
+    )
+    .await?;
+
+    let oref = agent_owner_ref(ctx.k8s_client.clone(), ns.as_str(), agent).await?;
+    patch_ship(ctx.k8s_client.clone(), ship_symbol, Some(ns), oref)
+        .await
+        .context(ShipPatchSnafu)?;
+
+    info!("purchase order {} reconciled", order.name_any());
+    Ok(Action::await_change())
+}
+
+/// The ship a started purchase bought: one at the shipyard that the agent
+/// didn't have before and that no other order claims.
+async fn bought_ship(
+    client: Client,
+    order: &PurchaseOrder,
+    intent: &PurchaseIntent,
+    ships: &[Ship],
+) -> Result<Option<String>, PurchaseOrderError> {
+    let ns = order.namespace().unwrap_or("default".to_string());
+    let api: Api<PurchaseOrder> = Api::namespaced(client, ns.as_str());
+    let claimed = api
+        .list(&ListParams::default())
+        .await
+        .context(ListOrderSnafu)?
+        .items
+        .into_iter()
+        .filter(|o| o.name_any() != order.name_any())
+        .filter_map(|o| o.status.and_then(|s| s.ship_symbol))
+        .collect::<Vec<String>>();
+
+    Ok(ships
+        .iter()
+        .find(|s| {
+            s.nav.location == intent.location
+                && intent.frame.as_ref().is_none_or(|f| &s.frame == f)
+                && !intent.known_ships.contains(&s.symbol)
+                && !claimed.contains(&s.symbol)
+        })
+        .map(|s| s.symbol.clone()))
+}
+
+/// Shipyard prices are only visible with a ship present, so send the most
+/// expendable idle ship in the system there and check back once it arrives.
+async fn route_ship(
+    order: Arc<PurchaseOrder>,
+    ctx: Arc<PurchaseOrderControllerData>,
+    cfg: &Configuration,
+    ships: &[Ship],
+) -> Result<Action, PurchaseOrderError> {
+    let shipyard = &order.spec.location;
+
+    let en_route = ships.iter().find(|s| {
+        s.nav.status == ShipNavStatus::InTransit && &s.nav.route.destination.location == shipyard
+    });
+
+    if let Some(ship) = en_route {
+        return Ok(requeue_at_arrival(ship));
+    }
+
+    let ns = order.namespace().unwrap_or("default".to_string());
+    let busy = ships_with_behaviour(ctx.k8s_client.clone(), ns.as_str()).await?;
+
+    let mut available = ships
+        .iter()
+        .filter(|s| {
+            s.nav.status != ShipNavStatus::InTransit
+                && s.nav.location.system_ident() == shipyard.system_ident()
+                && !busy.contains(&s.symbol)
+        })
+        .collect::<Vec<&Ship>>();
+    available.sort_by_key(|s| s.registration.role != ShipRole::Satellite);
+
+    let ship = match available.first() {
+        Some(ship) => ship,
+        None => {
+            let message = format!("no idle ship in {}", shipyard.system_ident());
+            waiting(&ctx, &order, "NoShipAvailable", message).await?;
+            return Ok(Action::requeue(Duration::from_secs(60)));
+        }
+    };
+
+    if ship.nav.status == ShipNavStatus::Docked {
+        fleet_api::orbit_ship(cfg, ship.symbol.as_str())
+            .await
+            .context(OrbitShipSnafu)?;
+    }
+
+    let req = NavigateShipRequest::new(shipyard.waypoint_ident());
+    let res = fleet_api::navigate_ship(cfg, ship.symbol.as_str(), Some(req))
+        .await
+        .context(NavigateShipSnafu)?;
+
+    let mut ship = (*ship).clone();
+    ship.update_nav(res.data.nav.into());
+
+    info!("routing {} to {}", ship.symbol, shipyard);
+    let message = format!("{} is travelling to {}", ship.symbol, shipyard);
+    publish_event(
+        ctx.k8s_client.clone(),
+        order.as_ref(),
+        EventType::Normal,
+        "ShipEnRoute",
+        message.clone(),
+    )
+    .await;
+
+    let conditions = vec![
+        Condition::new(Condition::READY, false, "ShipEnRoute", message),
+        Condition::new(Condition::DEGRADED, false, "ShipEnRoute", "".to_string()),
+    ];
+    patch_status(
+        ctx.k8s_client.clone(),
+        &order,
+        false,
+        None,
+        None,
+        conditions,
+    )
+    .await?;
+
+    Ok(requeue_at_arrival(&ship))
+}
+
+/// Symbols of the ships a behaviour controls, which can't be sent away.
+async fn ships_with_behaviour(
+    client: Client,
+    namespace: &str,
+) -> Result<Vec<String>, PurchaseOrderError> {
+    let api: Api<K8sShip> = Api::namespaced(client, namespace);
+
+    Ok(api
+        .list(&ListParams::default())
+        .await
+        .context(ListShipSnafu)?
+        .items
+        .into_iter()
+        .filter(|s| s.spec.behaviour.is_some())
+        .map(|s| s.spec.symbol)
+        .collect())
+}
+
+fn requeue_at_arrival(ship: &Ship) -> Action {
+    let seconds = ship
+        .nav
+        .route
+        .time_to_arrival
+        .map(|d| d.whole_seconds().max(0) as u64)
+        .unwrap_or(0);
+
+    Action::requeue(Duration::from_secs(seconds + 1))
+}
+
+/// Owner reference to the agent that bought the ship, so only that agent
+/// manages and collects its Ship CR.
+async fn agent_owner_ref(
+    client: Client,
+    namespace: &str,
+    agent: &str,
+) -> Result<Option<OwnerReference>, PurchaseOrderError> {
+    let agent_api: Api<K8sAgent> = Api::namespaced(client, namespace);
+    let agent = agent_api.get_opt(agent).await.context(ListAgentSnafu)?;
+
+    Ok(agent.and_then(|a| a.controller_owner_ref(&())))
+}
+
+/// Records why the order can't be fulfilled yet. The event is only published
+/// when the reason changes so a long wait doesn't flood the event stream.
+async fn waiting(
+    ctx: &PurchaseOrderControllerData,
+    order: &PurchaseOrder,
+    reason: &str,
+    message: String,
+) -> Result<(), PurchaseOrderError> {
+    let unchanged = order
+        .status
+        .as_ref()
+        .and_then(|s| {
+            s.conditions
+                .iter()
+                .find(|c| c.condition_type == Condition::READY)
+        })
+        .is_some_and(|c| c.reason == reason);
+
+    if !unchanged {
+        publish_event(
+            ctx.k8s_client.clone(),
+            order,
+            EventType::Warning,
+            reason,
+            message.clone(),
+        )
+        .await;
+    }
+
+    let conditions = vec![
+        Condition::new(Condition::READY, false, reason, message.clone()),
+        Condition::new(Condition::DEGRADED, true, reason, message),
+    ];
+    patch_status(ctx.k8s_client.clone(), order, false, None, None, conditions).await
+}
+
+async fn patch_status(
+    client: Client,
+    order: &PurchaseOrder,
+    complete: bool,
+    ship_symbol: Option<String>,
+    price: Option<i64>,
+    updates: Vec<Condition>,
+) -> Result<(), PurchaseOrderError> {
+    let ns = order.namespace().unwrap_or("default".to_string());
+    let api: Api<PurchaseOrder> = Api::namespaced(client, ns.as_str());
+
+    let mut conditions = order
+        .status
+        .as_ref()
+        .map(|s| s.conditions.clone())
+        .unwrap_or_default();
+    let mut changed = false;
+    for condition in updates {
+        changed |= set_condition(&mut conditions, condition);
+    }
+
+    let current = order.status.clone().unwrap_or_default();
+    if !changed
+        && current.complete == complete
+        && current.ship_symbol == ship_symbol
+        && current.price == price
+    {
+        return Ok(());
+    }
+
+    let mut status = json!({
+        "status": {
+            "complete": complete,
+            "ship_symbol": ship_symbol,
+            "price": price,
+            "conditions": conditions,
+        }
+    });
+    // Left untouched otherwise, the order passed in may predate it.
+    if complete {
+        status["status"]["purchasing"] = serde_json::Value::Null;
+    }
+
+    api.patch_status(
+        order.name_any().as_str(),
+        &PatchParams::apply("operator"),
+        &Patch::Merge(&status),
+    )
+    .await
+    .context(PatchOrderSnafu)?;
+
+    Ok(())
+}
+
+/// Records that the ship is about to be bought, or clears that with
+/// `None`. Nothing is bought unless recording it succeeded.
+async fn patch_purchasing(
+    client: Client,
+    order: &PurchaseOrder,
+    intent: Option<&PurchaseIntent>,
+) -> Result<(), PurchaseOrderError> {
+    let ns = order.namespace().unwrap_or("default".to_string());
+    let api: Api<PurchaseOrder> = Api::namespaced(client, ns.as_str());
+
+    let status = json!({ "status": { "purchasing": intent } });
+
+    api.patch_status(
+        order.name_any().as_str(),
+        &PatchParams::apply("operator"),
+        &Patch::Merge(&status),
+    )
+    .await
+    .context(PatchOrderSnafu)?;
+
+    Ok(())
+}
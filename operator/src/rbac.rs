@@ -0,0 +1,147 @@
+use k8s_openapi::api::core::v1::ServiceAccount;
+use k8s_openapi::api::rbac::v1::{
+    ClusterRole, ClusterRoleBinding, PolicyRule, Role, RoleBinding, RoleRef, Subject,
+};
+use kube::core::ObjectMeta;
+
+const NAME: &str = "spacetraders-operator";
+
+fn rule(api_group: &str, resources: &[&str], verbs: &[&str]) -> PolicyRule {
+    PolicyRule {
+        api_groups: Some(vec![api_group.to_string()]),
+        resources: Some(resources.iter().map(|r| r.to_string()).collect()),
+        verbs: verbs.iter().map(|v| v.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+/// Everything the controllers and the reset watcher read or write. Keep this
+/// in step with the APIs used by the operator.
+fn rules() -> Vec<PolicyRule> {
+    vec![
+        rule(
+            "spacetraders.io",
+            &["managers"],
+            &["get", "list", "watch", "patch", "update"],
+        ),
+        rule(
+            "spacetraders.io",
+            &["agents", "ships"],
+            &[
+                "get", "list", "watch", "create", "patch", "update", "delete",
+            ],
+        ),
+        rule(
+            "spacetraders.io",
+            &["purchaseorders", "contracts"],
+            &["get", "list", "watch", "create", "patch"],
+        ),
+        rule("spacetraders.io", &["fleets"], &["get", "list", "watch"]),
+        rule(
+            "spacetraders.io",
+            &[
+                "managers/status",
+                "agents/status",
+                "ships/status",
+                "purchaseorders/status",
+                "fleets/status",
+                "contracts/status",
+            ],
+            &["get", "patch", "update"],
+        ),
+        // Owner references with blockOwnerDeletion need update on the
+        // owner's finalizers.
+        rule(
+            "spacetraders.io",
+            &[
+                "managers/finalizers",
+                "agents/finalizers",
+                "fleets/finalizers",
+            ],
+            &["update"],
+        ),
+        rule("", &["secrets"], &["get", "create", "patch", "delete"]),
+        rule("", &["configmaps"], &["create"]),
+        rule("", &["namespaces"], &["delete"]),
+        rule("events.k8s.io", &["events"], &["create"]),
+    ]
+}
+
+pub(crate) fn service_account(namespace: &str) -> ServiceAccount {
+    ServiceAccount {
+        metadata: ObjectMeta {
+            name: Some(NAME.to_string()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+pub(crate) fn cluster_role() -> ClusterRole {
+    ClusterRole {
+        metadata: ObjectMeta {
+            name: Some(NAME.to_string()),
+            ..Default::default()
+        },
+        rules: Some(rules()),
+        ..Default::default()
+    }
+}
+
+/// The leader election lease lives next to the operator, so access to it is
+/// only granted in that namespace.
+pub(crate) fn role(namespace: &str) -> Role {
+    Role {
+        metadata: ObjectMeta {
+            name: Some(NAME.to_string()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        rules: Some(vec![rule(
+            "coordination.k8s.io",
+            &["leases"],
+            &["get", "create", "update"],
+        )]),
+    }
+}
+
+pub(crate) fn role_binding(namespace: &str) -> RoleBinding {
+    RoleBinding {
+        metadata: ObjectMeta {
+            name: Some(NAME.to_string()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        role_ref: RoleRef {
+            api_group: "rbac.authorization.k8s.io".to_string(),
+            kind: "Role".to_string(),
+            name: NAME.to_string(),
+        },
+        subjects: Some(vec![subject(namespace)]),
+    }
+}
+
+fn subject(namespace: &str) -> Subject {
+    Subject {
+        kind: "ServiceAccount".to_string(),
+        name: NAME.to_string(),
+        namespace: Some(namespace.to_string()),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn cluster_role_binding(namespace: &str) -> ClusterRoleBinding {
+    ClusterRoleBinding {
+        metadata: ObjectMeta {
+            name: Some(NAME.to_string()),
+            ..Default::default()
+        },
+        role_ref: RoleRef {
+            api_group: "rbac.authorization.k8s.io".to_string(),
+            kind: "ClusterRole".to_string(),
+            name: NAME.to_string(),
+        },
+        subjects: Some(vec![subject(namespace)]),
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use common::crds::Agent as K8sAgent;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::api::ListParams;
+use kube::{Api, Client, Resource};
+use openapi::apis::configuration::Configuration;
+use tokio::sync::RwLock;
+
+use crate::metrics::ApiRequestCounter;
+
+/// Authenticated API configurations keyed by the namespace/name of the agent
+/// that owns them. Every configuration carries its own rate limiter, so agents
+/// never eat into each other's request budget.
+#[derive(Clone, Default)]
+pub struct ApiConfigRegistry {
+    configs: Arc<RwLock<HashMap<String, Configuration>>>,
+    base_path: Option<String>,
+}
+
+impl ApiConfigRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends every request to `base_path` instead of the public API server.
+    #[cfg(test)]
+    pub fn with_base_path(base_path: &str) -> Self {
+        Self {
+            base_path: Some(base_path.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn configuration(&self, token: Option<String>) -> Configuration {
+        let mut config = Configuration {
+            bearer_access_token: token,
+            ..Default::default()
+        };
+
+        if let Some(base_path) = &self.base_path {
+            config.base_path = base_path.clone();
+        }
+
+        let counter = ApiRequestCounter {
+            base_path: reqwest::Url::parse(&config.base_path)
+                .map(|url| url.path().trim_end_matches('/').to_string())
+                .unwrap_or_default(),
+        };
+        config.client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(counter)
+            .build();
+
+        config
+    }
+
+    /// A configuration without a token, for registering new agents.
+    pub fn unauthenticated(&self) -> Configuration {
+        self.configuration(None)
+    }
+
+    fn key(namespace: &str, name: &str) -> String {
+        format!("{}/{}", namespace, name)
+    }
+
+    pub async fn get(&self, namespace: &str, name: &str) -> Option<Configuration> {
+        let configs = self.configs.read().await;
+        configs.get(&Self::key(namespace, name)).cloned()
+    }
+
+    /// Registers the agent's token. An existing configuration with the same
+    /// token is kept as is so its rate limiter state survives reconciles.
+    pub async fn insert(&self, namespace: &str, name: &str, token: String) -> Configuration {
+        let key = Self::key(namespace, name);
+        let mut configs = self.configs.write().await;
+
+        if let Some(config) = configs.get(&key) {
+            if config.bearer_access_token.as_ref() == Some(&token) {
+                return config.clone();
+            }
+        }
+
+        let config = self.configuration(Some(token));
+
+        configs.insert(key, config.clone());
+        config
+    }
+
+    pub async fn remove(&self, namespace: &str, name: &str) {
+        let mut configs = self.configs.write().await;
+        configs.remove(&Self::key(namespace, name));
+    }
+}
+
+/// The agent a namespaced resource belongs to, taken from its `Agent` owner
+/// reference.
+pub(crate) fn owning_agent(owner_references: &[OwnerReference]) -> Option<String> {
+    owner_references
+        .iter()
+        .find(|o| o.kind == K8sAgent::kind(&()) && o.api_version == K8sAgent::api_version(&()))
+        .map(|o| o.name.clone())
+}
+
+/// Falls back to the only agent in the namespace for resources created by
+/// hand without an owner reference.
+pub(crate) async fn resolve_agent(
+    client: Client,
+    namespace: &str,
+    owner_references: &[OwnerReference],
+) -> Result<Option<String>, kube::Error> {
+    if let Some(name) = owning_agent(owner_references) {
+        return Ok(Some(name));
+    }
+
+    let agent_api: Api<K8sAgent> = Api::namespaced(client, namespace);
+    let agents = agent_api.list(&ListParams::default()).await?.items;
+
+    match &agents[..] {
+        [agent] => Ok(agent.meta().name.clone()),
+        _ => Ok(None),
+    }
+}
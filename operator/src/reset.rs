@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::crds::{Agent as K8sAgent, Ship as K8sShip};
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use k8s_openapi::chrono::{DateTime, NaiveDate, Utc};
+use kube::api::{DeleteParams, ListParams, Patch, PatchParams, PostParams};
+use kube::core::ObjectMeta;
+use kube::{Api, Client, ResourceExt};
+use openapi::apis;
+use openapi::apis::configuration::Configuration;
+use openapi::apis::default_api::{self, GetStatusError};
+use sea_orm::DatabaseConnection;
+use serde_json::json;
+use snafu::{ResultExt, Snafu};
+use tracing::{info, warn};
+
+use crate::registry::owning_agent;
+
+/// How often the server is asked whether a reset happened.
+const RESET_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Snafu)]
+pub enum ResetError {
+    #[snafu(display("error fetching server status {}", source))]
+    GetStatusError { source: apis::Error<GetStatusError> },
+
+    #[snafu(display("invalid reset date {}: {}", date, source))]
+    InvalidResetDate {
+        date: String,
+        source: k8s_openapi::chrono::ParseError,
+    },
+
+    #[snafu(display("error listing agents {}", source))]
+    ListAgentError { source: kube::Error },
+
+    #[snafu(display("error archiving agent {}", source))]
+    ArchiveError { source: kube::Error },
+
+    #[snafu(display("error serializing agent state {}", source))]
+    SerializeError { source: serde_yaml::Error },
+
+    #[snafu(display("error removing ships {}", source))]
+    DeleteShipError { source: kube::Error },
+
+    #[snafu(display("error removing token secret {}", source))]
+    DeleteSecretError { source: kube::Error },
+
+    #[snafu(display("error patching agent {}", source))]
+    AgentPatchError { source: kube::Error },
+
+    #[snafu(display("error clearing the database {}", source))]
+    ClearDatabaseError { source: eyre::Report },
+}
+
+/// Periodically compares every agent's reset date with the one reported by
+/// the server and starts the agent over after a reset.
+pub(crate) async fn run_watcher(client: Client, db: Arc<DatabaseConnection>) -> eyre::Result<()> {
+    let mut interval = tokio::time::interval(RESET_CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        if let Err(err) = check_for_reset(client.clone(), db.clone()).await {
+            warn!("error checking for a server reset: {}", err);
+        }
+    }
+}
+
+async fn check_for_reset(client: Client, db: Arc<DatabaseConnection>) -> Result<(), ResetError> {
+    let res = default_api::get_status(&Configuration::new())
+        .await
+        .context(GetStatusSnafu)?;
+
+    let reset_date = parse_reset_date(res.reset_date.as_str())?;
+
+    let agent_api: Api<K8sAgent> = Api::all(client.clone());
+    let agents = agent_api
+        .list(&ListParams::default())
+        .await
+        .context(ListAgentSnafu)?
+        .items;
+
+    let mut universe_cleared = false;
+    for agent in agents {
+        match agent.spec.reset_date {
+            Some(date) if date == reset_date => continue,
+            None => set_reset_date(client.clone(), &agent, reset_date).await?,
+            Some(date) => {
+                if !universe_cleared {
+                    common::repository::clear_universe(db.as_ref())
+                        .await
+                        .context(ClearDatabaseSnafu)?;
+                    universe_cleared = true;
+                }
+
+                start_over(client.clone(), &agent, date, reset_date).await?
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Archives the agent's CR state, removes the ships it owns and its token and clears
+/// the status so the agent controller registers it again with the same
+/// symbol and faction and rebuilds the fleet.
+async fn start_over(
+    client: Client,
+    agent: &K8sAgent,
+    last_reset: DateTime<Utc>,
+    reset_date: DateTime<Utc>,
+) -> Result<(), ResetError> {
+    info!(
+        "server reset on {} detected for agent {}",
+        reset_date.date_naive(),
+        agent.name_any()
+    );
+
+    let ns = agent.namespace().unwrap_or("default".to_string());
+    let ship_api: Api<K8sShip> = Api::namespaced(client.clone(), ns.as_str());
+    let ships = ship_api
+        .list(&ListParams::default())
+        .await
+        .context(DeleteShipSnafu)?
+        .items
+        .into_iter()
+        .filter(|s| owning_agent(s.owner_references()) == Some(agent.name_any()))
+        .collect::<Vec<K8sShip>>();
+
+    archive(client.clone(), agent, &ships, last_reset).await?;
+
+    for ship in ships {
+        ship_api
+            .delete(ship.name_any().as_str(), &DeleteParams::default())
+            .await
+            .context(DeleteShipSnafu)?;
+    }
+
+    let secret_name = agent
+        .spec
+        .token_secret
+        .clone()
+        .unwrap_or(format!("{}-token", agent.name_any()));
+    let secret_api: Api<Secret> = Api::namespaced(client.clone(), ns.as_str());
+    if secret_api
+        .get_opt(secret_name.as_str())
+        .await
+        .context(DeleteSecretSnafu)?
+        .is_some()
+    {
+        secret_api
+            .delete(secret_name.as_str(), &DeleteParams::default())
+            .await
+            .context(DeleteSecretSnafu)?;
+    }
+
+    let agent_api: Api<K8sAgent> = Api::namespaced(client, ns.as_str());
+    let status = json!({
+        "status": {
+            "ships_initialized": false,
+            "credits": null,
+            "headquarters": null,
+            "ship_count": null,
+        }
+    });
+    agent_api
+        .patch_status(
+            agent.name_any().as_str(),
+            &PatchParams::default(),
+            &Patch::Merge(&status),
+        )
+        .await
+        .context(AgentPatchSnafu)?;
+
+    let spec = json!({
+        "spec": {
+            "token": null,
+            "reset_date": reset_date,
+        }
+    });
+    agent_api
+        .patch(
+            agent.name_any().as_str(),
+            &PatchParams::default(),
+            &Patch::Merge(&spec),
+        )
+        .await
+        .context(AgentPatchSnafu)?;
+
+    Ok(())
+}
+
+/// Stores the agent and its ships in a config map named after the reset
+/// they belong to. The config map is not owned by the agent so it outlives
+/// it.
+async fn archive(
+    client: Client,
+    agent: &K8sAgent,
+    ships: &[K8sShip],
+    last_reset: DateTime<Utc>,
+) -> Result<(), ResetError> {
+    let ns = agent.namespace().unwrap_or("default".to_string());
+    let name = format!("{}-{}", agent.name_any(), last_reset.format("%Y-%m-%d"));
+
+    let mut archived = agent.clone();
+    archived.spec.token = None;
+    archived.metadata = ObjectMeta {
+        name: agent.metadata.name.clone(),
+        namespace: agent.metadata.namespace.clone(),
+        ..Default::default()
+    };
+
+    let ships = ships
+        .iter()
+        .map(|s| {
+            let mut ship = s.clone();
+            ship.metadata = ObjectMeta {
+                name: s.metadata.name.clone(),
+                namespace: s.metadata.namespace.clone(),
+                ..Default::default()
+            };
+            ship
+        })
+        .collect::<Vec<K8sShip>>();
+
+    let data = BTreeMap::from([
+        (
+            "agent.yaml".to_string(),
+            serde_yaml::to_string(&archived).context(SerializeSnafu)?,
+        ),
+        (
+            "ships.yaml".to_string(),
+            serde_yaml::to_string(&ships).context(SerializeSnafu)?,
+        ),
+    ]);
+
+    let config_map = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(name.clone()),
+            namespace: Some(ns.clone()),
+            labels: Some(BTreeMap::from([(
+                "spacetraders.io/archive".to_string(),
+                agent.name_any(),
+            )])),
+            ..Default::default()
+        },
+        data: Some(data),
+        ..Default::default()
+    };
+
+    let config_map_api: Api<ConfigMap> = Api::namespaced(client, ns.as_str());
+    match config_map_api
+        .create(&PostParams::default(), &config_map)
+        .await
+    {
+        Ok(_) => {}
+        Err(kube::Error::Api(e)) if e.code == 409 => {}
+        Err(e) => return Err(e).context(ArchiveSnafu),
+    }
+
+    info!("agent {} archived to {}", agent.name_any(), name);
+    Ok(())
+}
+
+async fn set_reset_date(
+    client: Client,
+    agent: &K8sAgent,
+    reset_date: DateTime<Utc>,
+) -> Result<(), ResetError> {
+    let ns = agent.namespace().unwrap_or("default".to_string());
+    let agent_api: Api<K8sAgent> = Api::namespaced(client, ns.as_str());
+
+    let spec = json!({
+        "spec": { "reset_date": reset_date }
+    });
+
+    agent_api
+        .patch(
+            agent.name_any().as_str(),
+            &PatchParams::default(),
+            &Patch::Merge(&spec),
+        )
+        .await
+        .context(AgentPatchSnafu)?;
+
+    Ok(())
+}
+
+fn parse_reset_date(date: &str) -> Result<DateTime<Utc>, ResetError> {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").context(InvalidResetDateSnafu {
+        date: date.to_string(),
+    })?;
+
+    Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+}
@@ -9,26 +9,69 @@ use kube::api::{Patch, PatchParams};
 use kube::core::object::HasSpec;
 use kube::core::ObjectMeta;
 use kube::runtime::controller::Action;
+use kube::runtime::controller::Config;
+use kube::runtime::events::EventType;
+use kube::runtime::finalizer::{self, finalizer, Event as Finalizer};
 use kube::runtime::Controller;
 use kube::{Api, Client, ResourceExt};
 
-use common::crds::{Ship as K8sShip, ShipSpec, ShipStatus};
+use common::crds::{
+    set_condition, Condition, Ship as K8sShip, ShipCargoStatus, ShipFuelStatus, ShipPhase,
+    ShipSpec, ShipStatus,
+};
+use common::machines::{actions, TravelMachineWrapper};
+use common::models::ShipNavStatus;
+use k8s_openapi::chrono::{DateTime, Utc};
 use openapi::apis;
+use openapi::apis::configuration::Configuration;
 use openapi::apis::fleet_api::{self, GetMyShipError};
+use sea_orm::DatabaseConnection;
 use serde_json::json;
-use snafu::{ensure, ResultExt, Snafu};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
 use tracing::{info, warn};
 
+use crate::backoff::Backoff;
+use crate::behaviour::BehaviourRegistry;
+use crate::metrics;
+use crate::registry::{owning_agent, ApiConfigRegistry};
+use crate::status::{object_key, patch_conditions, publish_event, time_after};
+
+/// How often a ship driven by a behaviour is checked for a stopped task.
+const BEHAVIOUR_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+const FAILURE_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+const SHIP_FINALIZER: &str = "spacetraders.io/ship-cleanup";
+
+/// Upper bound between two syncs of an otherwise idle ship.
+const RESYNC_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Reconciles that may run at once. Every reconcile calls the API, so this
+/// keeps a full resync from queueing up far more requests than the agent's
+/// rate limiter lets through.
+const MAX_CONCURRENT_RECONCILES: u16 = 2;
+
 pub struct ShipControllerData {
-    pub api_config: crate::ApiConfig,
+    pub api_config: ApiConfigRegistry,
     pub k8s_client: Client,
+    pub db: Arc<DatabaseConnection>,
+    pub behaviours: BehaviourRegistry,
+    pub backoff: Backoff,
 }
 
 impl ShipControllerData {
-    pub fn new(k8s_client: Client, api_config: crate::ApiConfig) -> Self {
+    pub fn new(
+        k8s_client: Client,
+        api_config: ApiConfigRegistry,
+        db: Arc<DatabaseConnection>,
+        behaviours: BehaviourRegistry,
+    ) -> Self {
         Self {
             api_config,
             k8s_client,
+            db,
+            behaviours,
+            backoff: Backoff::new(),
         }
     }
 }
@@ -38,18 +81,32 @@ pub enum ShipError {
     #[snafu(display("api config is not available yet"))]
     ApiConfigNotAvailable,
 
+    #[snafu(display("ship {} is not owned by an agent", ship))]
+    AgentNotFound { ship: String },
+
     #[snafu(display("error received from fleet api {}", source))]
     GetShipError { source: apis::Error<GetMyShipError> },
 
     #[snafu(display("error patching ship {}", source))]
     PatchShipError { source: kube::Error },
+
+    #[snafu(display("finalizer error {}", source))]
+    FinalizerError {
+        #[snafu(source(from(finalizer::Error<ShipError>, Box::new)))]
+        source: Box<finalizer::Error<ShipError>>,
+    },
 }
 
 pub(crate) async fn run_controller(data: Arc<ShipControllerData>) -> eyre::Result<()> {
     let ship = Api::<K8sShip>::all(data.k8s_client.clone());
 
     Controller::new(ship.clone(), Default::default())
-        .run(reconcile, error_policy, data)
+        .with_config(Config::default().concurrency(MAX_CONCURRENT_RECONCILES))
+        .run(
+            |obj, ctx| metrics::measure("ship", reconcile(obj, ctx)),
+            error_policy,
+            data,
+        )
         .for_each(|_| futures::future::ready(()))
         .await;
 
@@ -57,36 +114,79 @@ pub(crate) async fn run_controller(data: Arc<ShipControllerData>) -> eyre::Resul
 }
 
 pub(crate) fn error_policy(
-    _object: Arc<K8sShip>,
+    object: Arc<K8sShip>,
     err: &ShipError,
-    _ctx: Arc<ShipControllerData>,
+    ctx: Arc<ShipControllerData>,
 ) -> Action {
     warn!("error reconcilling ships: {}", err);
-    Action::requeue(Duration::from_secs(5))
+    Action::requeue(ctx.backoff.next(object_key(object.as_ref()).as_str()))
 }
 
 pub(crate) async fn reconcile(
     k8s_ship: Arc<K8sShip>,
     ctx: Arc<ShipControllerData>,
 ) -> Result<Action, ShipError> {
+    let ns = k8s_ship.namespace().unwrap_or("default".to_string());
+    let ship_api: Api<K8sShip> = Api::namespaced(ctx.k8s_client.clone(), ns.as_str());
+
+    finalizer(&ship_api, SHIP_FINALIZER, k8s_ship, |event| async {
+        match event {
+            Finalizer::Apply(ship) => apply(ship, ctx.clone()).await,
+            Finalizer::Cleanup(ship) => cleanup(ship, ctx.clone()).await,
+        }
+    })
+    .await
+    .context(FinalizerSnafu)
+}
+
+/// Stops the ship's behaviour, which would otherwise keep flying the ship
+/// with the agent's token after the CR is gone.
+async fn cleanup(
+    k8s_ship: Arc<K8sShip>,
+    ctx: Arc<ShipControllerData>,
+) -> Result<Action, ShipError> {
+    ctx.behaviours
+        .stop(object_key(k8s_ship.as_ref()).as_str())
+        .await;
+    ctx.backoff.reset(object_key(k8s_ship.as_ref()).as_str());
+
+    info!("ship {} cleaned up", k8s_ship.name_any());
+    Ok(Action::await_change())
+}
+
+async fn apply(k8s_ship: Arc<K8sShip>, ctx: Arc<ShipControllerData>) -> Result<Action, ShipError> {
+    if let Some(wait) = pending_sync(k8s_ship.as_ref()) {
+        return Ok(Action::requeue(wait));
+    }
+
     info!("Reconciling ship {}", k8s_ship.name_any());
 
-    let cfg = ctx.api_config.read().await;
+    let ns = k8s_ship.namespace().unwrap_or("default".to_string());
+    let agent = owning_agent(k8s_ship.owner_references()).context(AgentNotFoundSnafu {
+        ship: k8s_ship.name_any(),
+    })?;
+
+    let cfg = ctx.api_config.get(ns.as_str(), agent.as_str()).await;
     ensure!(cfg.is_some(), ApiConfigNotAvailableSnafu);
-    let cfg = cfg.as_ref().unwrap();
+    let cfg = &cfg.unwrap();
 
-    let res = fleet_api::get_my_ship(cfg, k8s_ship.spec.symbol.as_str())
-        .await
-        .context(GetShipSnafu)?;
+    let previous = k8s_ship.status.clone().unwrap_or_default();
+    let res = match fleet_api::get_my_ship(cfg, k8s_ship.spec.symbol.as_str()).await {
+        Ok(res) => res,
+        Err(e) => {
+            let err = ShipError::GetShipError { source: e };
+            report_api_error(&k8s_ship, &ctx, &previous.conditions, &err).await?;
+            return Err(err);
+        }
+    };
 
-    let ship = Ship::from(res.data);
-    let ns = k8s_ship.namespace().unwrap_or("default".to_string());
+    let mut ship = Ship::from(res.data);
     let serverside = PatchParams::apply("operator");
     let ship_api: Api<K8sShip> = Api::namespaced(ctx.k8s_client.clone(), ns.as_str());
 
-    if let None = k8s_ship.spec().role {
+    if k8s_ship.spec().role.is_none() {
         let spec = json!({
-            "spec": ShipSpec {role:Some(ship.registration.role), symbol: ship.symbol }
+            "spec": { "role": ship.registration.role }
         });
 
         ship_api
@@ -99,11 +199,86 @@ pub(crate) async fn reconcile(
             .context(PatchShipSnafu)?;
     }
 
+    let key = object_key(k8s_ship.as_ref());
+    let (phase, last_error) = match converge(&k8s_ship, &ctx, cfg, key.as_str(), &mut ship).await {
+        Ok(phase) => (phase, None),
+        Err(e) => {
+            warn!("error converging ship {}: {}", k8s_ship.name_any(), e);
+            publish_event(
+                ctx.k8s_client.clone(),
+                k8s_ship.as_ref(),
+                EventType::Warning,
+                "ConvergeFailed",
+                e.to_string(),
+            )
+            .await;
+            (ShipPhase::Failed, Some(e.to_string()))
+        }
+    };
+
+    publish_transitions(&k8s_ship, &ctx, &previous, &ship).await;
+
+    let eta = match ship.nav.status {
+        ShipNavStatus::InTransit => {
+            DateTime::<Utc>::from_timestamp(ship.nav.route.arrival.unix_timestamp(), 0)
+        }
+        _ => None,
+    };
+
+    let cooldown = match ship.cooldown.remaining().is_zero() {
+        true => None,
+        false => ship
+            .cooldown
+            .expiration
+            .and_then(|e| DateTime::<Utc>::from_timestamp(e.unix_timestamp(), 0)),
+    };
+
+    let cargo = ShipCargoStatus {
+        units: ship.cargo.current,
+        capacity: ship.cargo.capacity,
+        inventory: ship
+            .cargo
+            .inventory
+            .iter()
+            .map(|i| (i.symbol.clone(), i.units))
+            .collect(),
+    };
+
+    let fuel = ShipFuelStatus {
+        current: ship.fuel.current,
+        capacity: ship.fuel.capacity,
+    };
+
+    let mut conditions = previous.conditions.clone();
+    for condition in ship_conditions(&phase, last_error.as_deref()) {
+        set_condition(&mut conditions, condition);
+    }
+
+    let due = match phase {
+        ShipPhase::Failed => ctx.backoff.next(key.as_str()),
+        _ => {
+            ctx.backoff.reset(key.as_str());
+            next_reconcile(&phase, &ship)
+        }
+    };
+
+    let now = Utc::now();
     let status = json!({
-        "status": ShipStatus {
-            location: Some(ship.nav.location),
-            status: Some(ship.nav.status),
-            flight_mode: Some(ship.nav.flight_mode) }
+        "status": {
+            "location": ship.nav.location,
+            "status": ship.nav.status,
+            "flight_mode": ship.nav.flight_mode,
+            "phase": phase,
+            "eta": eta,
+            "last_error": last_error,
+            "fuel": fuel,
+            "cargo": cargo,
+            "cooldown": cooldown,
+            "last_synced": now,
+            "observed_generation": k8s_ship.metadata.generation,
+            "next_sync": time_after(now, due),
+            "conditions": conditions,
+        }
     });
 
     ship_api
@@ -116,7 +291,199 @@ pub(crate) async fn reconcile(
         .context(PatchShipSnafu)?;
 
     info!("ship {} reconciled", k8s_ship.name_any());
-    Ok(Action::await_change())
+    Ok(Action::requeue(due))
+}
+
+/// Time left until the next sync when the spec hasn't changed since the last
+/// one, so the controller's own status writes don't trigger a full sync.
+fn pending_sync(k8s_ship: &K8sShip) -> Option<Duration> {
+    let status = k8s_ship.status.as_ref()?;
+    if status.observed_generation != k8s_ship.metadata.generation {
+        return None;
+    }
+
+    (status.next_sync? - Utc::now()).to_std().ok()
+}
+
+fn ship_conditions(phase: &ShipPhase, last_error: Option<&str>) -> Vec<Condition> {
+    let synced = Condition::new(
+        Condition::SYNCED,
+        true,
+        "ShipFetched",
+        "status reflects the ship in the game".to_string(),
+    );
+
+    match last_error {
+        Some(err) => vec![
+            synced,
+            Condition::new(Condition::READY, false, "ConvergeFailed", err.to_string()),
+            Condition::new(Condition::DEGRADED, true, "ConvergeFailed", err.to_string()),
+        ],
+        None => {
+            let reason = format!("{:?}", phase);
+            vec![
+                synced,
+                Condition::new(Condition::READY, true, reason.as_str(), "".to_string()),
+                Condition::new(Condition::DEGRADED, false, reason.as_str(), "".to_string()),
+            ]
+        }
+    }
+}
+
+/// Emits events for departures and arrivals by comparing the navigation
+/// status from the last sync with the current one.
+async fn publish_transitions(
+    k8s_ship: &K8sShip,
+    ctx: &ShipControllerData,
+    previous: &ShipStatus,
+    ship: &Ship,
+) {
+    let was_in_transit = match &previous.status {
+        Some(status) => status == &ShipNavStatus::InTransit,
+        None => return,
+    };
+    let in_transit = ship.nav.status == ShipNavStatus::InTransit;
+
+    let (reason, note) = match (was_in_transit, in_transit) {
+        (true, false) => ("Arrived", format!("arrived at {}", ship.nav.location)),
+        (false, true) => (
+            "Departed",
+            format!(
+                "travelling to {}, arriving {}",
+                ship.nav.route.destination.location, ship.nav.route.arrival
+            ),
+        ),
+        _ => return,
+    };
+
+    publish_event(
+        ctx.k8s_client.clone(),
+        k8s_ship,
+        EventType::Normal,
+        reason,
+        note,
+    )
+    .await;
+}
+
+async fn report_api_error(
+    k8s_ship: &K8sShip,
+    ctx: &ShipControllerData,
+    current: &[Condition],
+    err: &ShipError,
+) -> Result<(), ShipError> {
+    publish_event(
+        ctx.k8s_client.clone(),
+        k8s_ship,
+        EventType::Warning,
+        "ApiError",
+        err.to_string(),
+    )
+    .await;
+
+    let conditions = vec![
+        Condition::new(Condition::SYNCED, false, "ApiError", err.to_string()),
+        Condition::new(Condition::DEGRADED, true, "ApiError", err.to_string()),
+    ];
+
+    patch_conditions(ctx.k8s_client.clone(), k8s_ship, current, conditions)
+        .await
+        .context(PatchShipSnafu)
+}
+
+/// Wakes the controller when something about the ship is due to change, and
+/// otherwise falls back to a slow resync so the status never goes stale.
+fn next_reconcile(phase: &ShipPhase, ship: &Ship) -> Duration {
+    let due = match phase {
+        ShipPhase::InTransit => ship
+            .nav
+            .route
+            .time_to_arrival
+            .map(|d| Duration::from_secs(d.whole_seconds().max(0) as u64 + 1))
+            .unwrap_or(FAILURE_RETRY_INTERVAL),
+        ShipPhase::Running => BEHAVIOUR_CHECK_INTERVAL,
+        ShipPhase::Failed => FAILURE_RETRY_INTERVAL,
+        ShipPhase::Idle => match ship.cooldown.remaining() {
+            remaining if remaining.is_zero() => RESYNC_INTERVAL,
+            remaining => remaining + Duration::from_secs(1),
+        },
+    };
+
+    due.min(RESYNC_INTERVAL)
+}
+
+/// Moves the real ship one step closer to the desired state in its spec. A
+/// behaviour takes precedence over the destination and docking state.
+async fn converge(
+    k8s_ship: &K8sShip,
+    ctx: &ShipControllerData,
+    cfg: &Configuration,
+    key: &str,
+    ship: &mut Ship,
+) -> eyre::Result<ShipPhase> {
+    let spec = k8s_ship.spec();
+
+    if let Some(behaviour) = &spec.behaviour {
+        ctx.behaviours
+            .ensure(
+                key,
+                behaviour,
+                cfg.clone(),
+                ctx.db.clone(),
+                ship.symbol.as_str(),
+            )
+            .await?;
+
+        return Ok(ShipPhase::Running);
+    }
+
+    ctx.behaviours.stop(key).await;
+
+    if ship.nav.status == ShipNavStatus::InTransit {
+        return Ok(ShipPhase::InTransit);
+    }
+
+    if let Some(destination) = &spec.destination {
+        if &ship.nav.location != destination {
+            let mut machine = TravelMachineWrapper::new(
+                cfg.clone(),
+                &ctx.db,
+                destination.clone(),
+                ship.symbol.as_str(),
+            )
+            .await?;
+
+            // Only the steps up to departure are taken here, arrival is
+            // handled by requeueing once the ship is due.
+            loop {
+                match machine {
+                    TravelMachineWrapper::InTransit(_) | TravelMachineWrapper::TravelComplete => {
+                        break
+                    }
+                    _ => machine = machine.step().await?,
+                }
+            }
+
+            let res = fleet_api::get_my_ship(cfg, ship.symbol.as_str()).await?;
+            *ship = Ship::from(res.data);
+
+            if ship.nav.status == ShipNavStatus::InTransit {
+                return Ok(ShipPhase::InTransit);
+            }
+        }
+    }
+
+    if let Some(flight_mode) = &spec.flight_mode {
+        actions::set_flight_mode(cfg, ship, flight_mode.clone()).await?;
+    }
+
+    match spec.docked {
+        Some(true) => actions::dock(cfg, ship).await?,
+        Some(false) => actions::orbit(cfg, ship).await?,
+        None => {}
+    }
+
+    Ok(ShipPhase::Idle)
 }
 
 pub(crate) async fn patch_ship(
@@ -140,7 +507,14 @@ pub(crate) async fn patch_ship(
 
 fn create_owned_ship(symbol: String, namespace: String, oref: Option<OwnerReference>) -> K8sShip {
     let name = symbol.to_lowercase();
-    let spec = ShipSpec { symbol, role: None };
+    let spec = ShipSpec {
+        symbol,
+        role: None,
+        destination: None,
+        flight_mode: None,
+        docked: None,
+        behaviour: None,
+    };
     let owner_references = oref.map_or(None, |oref| Some(vec![oref]));
 
     K8sShip {
@@ -151,10 +525,166 @@ fn create_owned_ship(symbol: String, namespace: String, oref: Option<OwnerRefere
             ..Default::default()
         },
         spec,
-        status: Some(ShipStatus {
-            location: None,
-            status: None,
-            flight_mode: None,
-        }),
+        status: Some(ShipStatus::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::models::{Location, ShipNavStatus, ShipRole};
+    use hyper::http::{Method, StatusCode};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+    use kube::Resource;
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    const NAMESPACE: &str = "spacetraders-test";
+
+    fn ship() -> Arc<K8sShip> {
+        let oref = OwnerReference {
+            api_version: common::crds::Agent::api_version(&()).to_string(),
+            kind: common::crds::Agent::kind(&()).to_string(),
+            name: "test".to_string(),
+            uid: "agent-uid".to_string(),
+            ..Default::default()
+        };
+
+        Arc::new(create_owned_ship(
+            "TEST-1".to_string(),
+            NAMESPACE.to_string(),
+            Some(oref),
+        ))
+    }
+
+    async fn context(game_status: StatusCode) -> (Arc<ShipControllerData>, mock::Requests) {
+        let ship = json!(ship().as_ref());
+        let (client, kube) = mock::kube_client(move |method, path, _| match (method, path) {
+            (&Method::PATCH, p) if p.contains("/ships/test-1") => (StatusCode::OK, ship.clone()),
+            _ => mock::not_found(),
+        });
+
+        let (base_path, _) = mock::spacetraders(move |method, path, _| match (method, path) {
+            (&Method::GET, "/my/ships/TEST-1") if game_status.is_success() => {
+                (game_status, json!({ "data": mock::ship("TEST-1") }))
+            }
+            _ => (
+                game_status,
+                json!({ "error": { "message": "server error", "code": 500 } }),
+            ),
+        })
+        .await;
+
+        let ctx = Arc::new(ShipControllerData::new(
+            client,
+            ApiConfigRegistry::with_base_path(base_path.as_str()),
+            Arc::new(DatabaseConnection::Disconnected),
+            BehaviourRegistry::new(),
+        ));
+
+        (ctx, kube)
+    }
+
+    #[tokio::test]
+    async fn patches_ship_from_game() {
+        let (ctx, kube) = context(StatusCode::OK).await;
+        ctx.api_config
+            .insert(NAMESPACE, "test", "token-1".to_string())
+            .await;
+
+        let action = apply(ship(), ctx).await.unwrap();
+        assert_eq!(action, Action::requeue(RESYNC_INTERVAL));
+
+        let patches = kube.matching(Method::PATCH, "/ships/test-1");
+        let spec = patches
+            .iter()
+            .find(|r| r.body["spec"].is_object())
+            .expect("role was not patched");
+        let role: ShipRole = serde_json::from_value(spec.body["spec"]["role"].clone()).unwrap();
+        assert_eq!(role, ShipRole::Satellite);
+
+        let status = patches
+            .iter()
+            .find(|r| r.path.contains("/ships/test-1/status"))
+            .expect("status was not patched");
+        let status: ShipStatus = serde_json::from_value(status.body["status"].clone()).unwrap();
+        assert_eq!(
+            status.location,
+            Some(Location::parse("X1-TEST-A1".to_string()))
+        );
+        assert_eq!(status.status, Some(ShipNavStatus::Docked));
+        assert_eq!(status.phase, Some(ShipPhase::Idle));
+        assert!(status
+            .conditions
+            .iter()
+            .any(|c| c.condition_type == Condition::READY && c.is_true()));
+    }
+
+    #[tokio::test]
+    async fn api_error_requeues_with_backoff() {
+        let (ctx, kube) = context(StatusCode::INTERNAL_SERVER_ERROR).await;
+        ctx.api_config
+            .insert(NAMESPACE, "test", "token-1".to_string())
+            .await;
+        let ship = ship();
+
+        let err = apply(ship.clone(), ctx.clone()).await.unwrap_err();
+        assert!(matches!(err, ShipError::GetShipError { .. }));
+
+        let status = kube
+            .matching(Method::PATCH, "/ships/test-1/status")
+            .pop()
+            .expect("conditions were not patched");
+        let synced = status.body["status"]["conditions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|c| c["type"] == Condition::SYNCED)
+            .cloned()
+            .unwrap();
+        assert_eq!(synced["status"], "False");
+        assert_eq!(synced["reason"], "ApiError");
+
+        let first = error_policy(ship.clone(), &err, ctx.clone());
+        let second = error_policy(ship, &err, ctx);
+        assert_eq!(first, Action::requeue(Duration::from_secs(5)));
+        assert_eq!(second, Action::requeue(Duration::from_secs(10)));
+    }
+
+    #[tokio::test]
+    async fn waits_for_api_config() {
+        let (ctx, kube) = context(StatusCode::OK).await;
+
+        let err = apply(ship(), ctx).await.unwrap_err();
+        assert!(matches!(err, ShipError::ApiConfigNotAvailable));
+        assert!(kube.all().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cleanup_stops_behaviour() {
+        let (ctx, _) = context(StatusCode::OK).await;
+        let ship = ship();
+        let key = object_key(ship.as_ref());
+
+        let behaviour = common::crds::ShipBehaviour {
+            name: "probe".to_string(),
+            params: Default::default(),
+        };
+        ctx.behaviours
+            .ensure(
+                key.as_str(),
+                &behaviour,
+                Configuration::new(),
+                ctx.db.clone(),
+                "TEST-1",
+            )
+            .await
+            .unwrap();
+        assert!(ctx.behaviours.is_running(key.as_str()).await);
+
+        let action = cleanup(ship, ctx.clone()).await.unwrap();
+        assert_eq!(action, Action::await_change());
+        assert!(!ctx.behaviours.is_running(key.as_str()).await);
     }
 }
@@ -0,0 +1,154 @@
+use std::fmt::Debug;
+use std::time::Duration;
+
+use common::crds::{set_condition, Condition};
+use k8s_openapi::chrono::{DateTime, TimeDelta, Utc};
+use k8s_openapi::NamespaceResourceScope;
+use kube::api::{Patch, PatchParams};
+use kube::runtime::events::{Event, EventType, Recorder, Reporter};
+use kube::{Api, Client, Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use tracing::warn;
+
+const REPORTER: &str = "spacetraders-operator";
+
+pub(crate) fn object_key<K: ResourceExt>(obj: &K) -> String {
+    format!(
+        "{}/{}",
+        obj.namespace().unwrap_or("default".to_string()),
+        obj.name_any()
+    )
+}
+
+pub(crate) fn time_after(from: DateTime<Utc>, duration: Duration) -> DateTime<Utc> {
+    from + TimeDelta::from_std(duration).unwrap_or(TimeDelta::zero())
+}
+
+/// Publishes an event on the object. Events are best effort, a failure is
+/// only logged.
+pub(crate) async fn publish_event<K>(
+    client: Client,
+    obj: &K,
+    type_: EventType,
+    reason: &str,
+    note: String,
+) where
+    K: Resource<DynamicType = ()>,
+{
+    let reporter = Reporter {
+        controller: REPORTER.to_string(),
+        instance: std::env::var("POD_NAME").ok(),
+    };
+
+    let recorder = Recorder::new(client, reporter, obj.object_ref(&()));
+    let event = Event {
+        type_,
+        reason: reason.to_string(),
+        note: Some(note),
+        action: reason.to_string(),
+        secondary: None,
+    };
+
+    if let Err(e) = recorder.publish(event).await {
+        warn!("error publishing event {}: {}", reason, e);
+    }
+}
+
+/// Merges the conditions into the object's current ones and patches the
+/// status, skipping the patch when nothing changed so the controller isn't
+/// woken up by its own writes.
+pub(crate) async fn patch_conditions<K>(
+    client: Client,
+    obj: &K,
+    current: &[Condition],
+    updates: Vec<Condition>,
+) -> Result<(), kube::Error>
+where
+    K: Resource<DynamicType = (), Scope = NamespaceResourceScope>
+        + Clone
+        + DeserializeOwned
+        + Debug,
+{
+    let mut conditions = current.to_vec();
+    let mut changed = false;
+    for condition in updates {
+        changed |= set_condition(&mut conditions, condition);
+    }
+
+    if !changed {
+        return Ok(());
+    }
+
+    let ns = obj.namespace().unwrap_or("default".to_string());
+    let api: Api<K> = Api::namespaced(client, ns.as_str());
+    let status = conditions_patch(&conditions);
+
+    api.patch_status(
+        obj.name_any().as_str(),
+        &PatchParams::default(),
+        &Patch::Merge(&status),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// A status holding only the conditions. Objects created without a status
+/// get it as their first one, so every other field of the status must be
+/// optional.
+fn conditions_patch(conditions: &[Condition]) -> serde_json::Value {
+    json!({
+        "status": { "conditions": conditions }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use common::crds::{Agent, Manager, Ship};
+    use kube::CustomResourceExt;
+
+    use super::*;
+
+    /// Fields of the CRD's status schema the API server requires.
+    fn required_status_fields<K: CustomResourceExt>() -> Vec<String> {
+        let crd = serde_json::to_value(K::crd()).unwrap();
+        let status =
+            &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["status"];
+
+        status["required"]
+            .as_array()
+            .map(|r| {
+                r.iter()
+                    .filter_map(|f| f.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn assert_patch_is_valid<K: CustomResourceExt>() {
+        let conditions = vec![Condition::new(
+            Condition::READY,
+            true,
+            "Ready",
+            String::new(),
+        )];
+        let patch = conditions_patch(&conditions);
+
+        for field in required_status_fields::<K>() {
+            assert!(
+                patch["status"].get(field.as_str()).is_some(),
+                "{} status requires {} which a conditions patch lacks",
+                K::crd_name(),
+                field
+            );
+        }
+    }
+
+    #[test]
+    fn conditions_patch_satisfies_status_schemas() {
+        assert_patch_is_valid::<Manager>();
+        assert_patch_is_valid::<Agent>();
+        assert_patch_is_valid::<Ship>();
+    }
+}